@@ -0,0 +1,169 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::{Request, State};
+
+use crate::config::LandlordConfig;
+use crate::conn::{PeerInfo, PeerSet};
+use crate::lan::{status_of, LanConnectionManager};
+use crate::session::SessionRegistry;
+
+/// Guard of the `/admin` routes: the request must carry the configured token
+/// as `Authorization: Bearer <token>`. Without a token, every request is
+/// forbidden.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(config) = request.rocket().state::<LandlordConfig>() else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+
+        if !config.admin.enabled() {
+            return Outcome::Error((Status::Forbidden, ()));
+        }
+
+        let given = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        match given {
+            Some(given) if same_token(given, config.admin.token.expose()) => {
+                Outcome::Success(Admin)
+            }
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Compares tokens without bailing out on the first differing byte.
+pub(crate) fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AdminPeer {
+    /// Session the peer came through, or `None` for LAN peers.
+    pub session: Option<String>,
+    #[serde(flatten)]
+    pub peer: PeerInfo,
+}
+
+/// The LAN peers, then the peers of every live session.
+async fn peer_sets(
+    lan: &LanConnectionManager,
+    registry: &SessionRegistry,
+) -> Vec<(Option<String>, PeerSet)> {
+    let mut sets = vec![(None, lan.peers.clone())];
+
+    sets.extend(
+        registry
+            .peer_sets()
+            .await
+            .into_iter()
+            .map(|(id, peers)| (Some(id), peers)),
+    );
+
+    sets
+}
+
+/// The set holding the peer `uuid`, if any.
+async fn find_peer(
+    uuid: &str,
+    lan: &LanConnectionManager,
+    registry: &SessionRegistry,
+) -> Option<PeerSet> {
+    for (_, peers) in peer_sets(lan, registry).await {
+        if peers.uuids().await.iter().any(|peer| peer == uuid) {
+            return Some(peers);
+        }
+    }
+
+    None
+}
+
+#[get("/admin/peers")]
+pub async fn list_peers(
+    _admin: Admin,
+    lan: &State<LanConnectionManager>,
+    registry: &State<SessionRegistry>,
+) -> Json<Vec<AdminPeer>> {
+    let mut peers = vec![];
+
+    for (session, set) in peer_sets(lan, registry).await {
+        peers.extend(set.describe().await.into_iter().map(|peer| AdminPeer {
+            session: session.clone(),
+            peer,
+        }));
+    }
+
+    Json(peers)
+}
+
+/// Hands control to the peer `uuid`, taking it from whoever holds it.
+#[post("/admin/peers/<uuid>/control")]
+pub async fn give_control(
+    uuid: &str,
+    _admin: Admin,
+    lan: &State<LanConnectionManager>,
+    registry: &State<SessionRegistry>,
+) -> Status {
+    let Some(peers) = find_peer(uuid, lan, registry).await else {
+        return Status::NotFound;
+    };
+
+    match peers.change_control_to(uuid.to_owned()).await {
+        Ok(()) => {
+            info!("Control given to peer '{uuid}' by the administrator.");
+            Status::NoContent
+        }
+        Err(e) => status_of(&e),
+    }
+}
+
+/// Takes control away from every peer until it is given again.
+#[delete("/admin/control")]
+pub async fn revoke_control(
+    _admin: Admin,
+    lan: &State<LanConnectionManager>,
+    registry: &State<SessionRegistry>,
+) -> Status {
+    for (_, peers) in peer_sets(lan, registry).await {
+        peers.revoke_control().await;
+    }
+
+    info!("Control revoked by the administrator.");
+    Status::NoContent
+}
+
+/// Disconnects the peer `uuid`.
+#[delete("/admin/peers/<uuid>")]
+pub async fn kick_peer(
+    uuid: &str,
+    _admin: Admin,
+    lan: &State<LanConnectionManager>,
+    registry: &State<SessionRegistry>,
+) -> Status {
+    let Some(peers) = find_peer(uuid, lan, registry).await else {
+        return Status::NotFound;
+    };
+
+    match peers.disconnect_peer(uuid.to_owned()).await {
+        Ok(()) => {
+            info!("Peer '{uuid}' kicked by the administrator.");
+            Status::NoContent
+        }
+        Err(e) => status_of(&e),
+    }
+}
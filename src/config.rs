@@ -0,0 +1,673 @@
+use rocket::fairing::AdHoc;
+use rocket::figment::providers::Env;
+use rocket::figment::Figment;
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize, Serializer};
+use rocket::State;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use webrtc::api::media_engine::{MIME_TYPE_H264, MIME_TYPE_VP8};
+use webrtc::ice_transport::ice_server::RTCIceServer;
+
+/// A configuration value that must never be displayed.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("<redacted>")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum VideoCodec {
+    Vp8,
+    H264,
+}
+
+impl VideoCodec {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            VideoCodec::Vp8 => MIME_TYPE_VP8,
+            VideoCodec::H264 => MIME_TYPE_H264,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct SignalingConfig {
+    /// Base websocket URL of the signaling server, e.g. `ws://127.0.0.1:7878`.
+    pub address: String,
+    /// Seconds between two websocket pings.
+    pub ping_interval: u64,
+    /// Seconds without any frame from the server after which the link is
+    /// considered dead and re-established.
+    pub ping_timeout: u64,
+}
+
+impl Default for SignalingConfig {
+    fn default() -> Self {
+        Self {
+            address: "ws://127.0.0.1:7878".to_owned(),
+            ping_interval: 15,
+            ping_timeout: 45,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct IceServerConfig {
+    pub urls: Vec<String>,
+    pub username: String,
+    pub credential: Secret,
+}
+
+/// A TURN server accepting time-limited credentials derived from a secret
+/// it shares with the landlord (the TURN REST API scheme, `use-auth-secret`
+/// in coturn).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct TurnRestConfig {
+    /// `turn:` or `turns:` URLs, e.g. `turn:turn.example.org:3478?transport=tcp`.
+    pub urls: Vec<String>,
+    pub shared_secret: Secret,
+    /// Lifetime of the credentials handed to a peer, in seconds.
+    pub ttl: u64,
+}
+
+impl Default for TurnRestConfig {
+    fn default() -> Self {
+        Self {
+            urls: vec![],
+            shared_secret: Secret::default(),
+            ttl: 86400,
+        }
+    }
+}
+
+impl TurnRestConfig {
+    /// Computes the credentials of `peer`, valid for `ttl` seconds from `now`:
+    /// the username is `<expiry timestamp>:<peer>` and the credential is the
+    /// base64 HMAC-SHA1 of that username keyed by the shared secret.
+    pub fn credentials_for(&self, peer: &str, now: SystemTime) -> RTCIceServer {
+        use base64::Engine;
+        use hmac::{Hmac, Mac};
+
+        let expiry = (now + Duration::from_secs(self.ttl))
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let username = format!("{expiry}:{peer}");
+
+        let mut mac = Hmac::<sha1::Sha1>::new_from_slice(self.shared_secret.expose().as_bytes())
+            .expect("HMAC accepts keys of any length.");
+        mac.update(username.as_bytes());
+
+        RTCIceServer {
+            urls: self.urls.clone(),
+            username,
+            credential: base64::engine::general_purpose::STANDARD
+                .encode(mac.finalize().into_bytes()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct IceConfig {
+    /// STUN and TURN servers with fixed credentials, used by the landlord only.
+    pub servers: Vec<IceServerConfig>,
+    /// TURN servers for which every peer gets its own short-lived credentials.
+    pub turn: Vec<TurnRestConfig>,
+}
+
+impl Default for IceConfig {
+    fn default() -> Self {
+        Self {
+            servers: vec![IceServerConfig {
+                urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+                ..Default::default()
+            }],
+            turn: vec![],
+        }
+    }
+}
+
+impl IceConfig {
+    pub fn rtc_ice_servers(&self) -> Vec<RTCIceServer> {
+        self.servers
+            .iter()
+            .map(|server| RTCIceServer {
+                urls: server.urls.clone(),
+                username: server.username.clone(),
+                credential: server.credential.expose().to_owned(),
+            })
+            .collect()
+    }
+
+    /// The TURN servers of `turn` along with freshly computed credentials
+    /// for `peer`, meant to be shared with that peer.
+    pub fn turn_servers_for(&self, peer: &str) -> Vec<RTCIceServer> {
+        let now = SystemTime::now();
+
+        self.turn
+            .iter()
+            .map(|server| server.credentials_for(peer, now))
+            .collect()
+    }
+}
+
+/// The desktop shared with peers. Its layout is probed with `xrandr` on
+/// Linux, `width` and `height` only stand for it when it cannot be.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct ScreenConfig {
+    pub width: u32,
+    pub height: u32,
+    /// Output to capture, by its `xrandr` name such as `HDMI-1`. The whole
+    /// desktop is captured when empty or when the output is not connected.
+    pub monitor: String,
+    /// Seconds between two probes of the layout, 0 to probe it only once.
+    pub layout_interval: u64,
+}
+
+impl Default for ScreenConfig {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            monitor: String::new(),
+            layout_interval: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    pub frame_rate: u32,
+    /// Target bitrate as understood by ffmpeg's `-b:v`, e.g. `2M` or `800k`.
+    pub bitrate: String,
+    pub width: u32,
+    pub height: u32,
+    /// Forces a codec instead of picking one from the session type.
+    pub codec: Option<VideoCodec>,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            frame_rate: 24,
+            bitrate: "2M".to_owned(),
+            width: 1280,
+            height: 720,
+            codec: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum TokenAlgorithm {
+    #[serde(rename = "HS256")]
+    Hs256,
+    #[serde(rename = "EdDSA")]
+    EdDsa,
+}
+
+/// Verification of the signed tokens (JWT) given to `/negotiate-server`.
+/// Tokens are forwarded unchecked unless a `key` is configured.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct TokenConfig {
+    pub algorithm: TokenAlgorithm,
+    /// Shared secret for HS256, public key in PEM format for EdDSA.
+    pub key: Secret,
+    /// Expected `aud` claim.
+    pub audience: String,
+    /// Identifier of this host, expected in the `host_id` claim.
+    pub host_id: String,
+    /// Clock skew tolerated when checking `exp`, in seconds.
+    pub leeway: u64,
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: TokenAlgorithm::Hs256,
+            key: Secret::default(),
+            audience: String::new(),
+            host_id: String::new(),
+            leeway: 30,
+        }
+    }
+}
+
+impl TokenConfig {
+    pub fn enabled(&self) -> bool {
+        !self.key.expose().is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct PeersConfig {
+    /// Number of peers served at once, further connections are refused.
+    pub max: usize,
+}
+
+impl Default for PeersConfig {
+    fn default() -> Self {
+        Self { max: 8 }
+    }
+}
+
+/// What to do when `/negotiate-server` is called with the token of a live
+/// session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+    /// Keep the live session and answer 409.
+    Reject,
+    /// Close the live session and start a new one.
+    Replace,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct SessionsConfig {
+    pub on_duplicate: DuplicatePolicy,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> Self {
+        Self {
+            on_duplicate: DuplicatePolicy::Reject,
+        }
+    }
+}
+
+/// Access to the `/admin` routes and `/metrics`, refused unless a `token` is
+/// configured.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Bearer token expected in the `Authorization` header.
+    pub token: Secret,
+}
+
+impl AdminConfig {
+    pub fn enabled(&self) -> bool {
+        !self.token.expose().is_empty()
+    }
+}
+
+/// Peers offering through `/sdp` on the local network. Without a `token`
+/// they only watch the screen, with one they must present it and may take
+/// control.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct LanConfig {
+    /// Bearer token expected in the `Authorization` header.
+    pub token: Secret,
+}
+
+impl LanConfig {
+    pub fn enabled(&self) -> bool {
+        !self.token.expose().is_empty()
+    }
+}
+
+/// The bus carrying peer events to `/events`, the logs and the webhook.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct EventsConfig {
+    /// Events kept for each subscriber before the oldest are dropped.
+    pub capacity: usize,
+    /// URL receiving every event as a JSON POST, disabled when empty. It is
+    /// kept secret since such URLs often embed a key.
+    pub webhook: Secret,
+    /// Time allowed for the webhook to answer, in seconds.
+    pub webhook_timeout: u64,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            webhook: Secret::default(),
+            webhook_timeout: 5,
+        }
+    }
+}
+
+/// How input of the peer in control reaches the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "kebab-case")]
+pub enum InputBackend {
+    /// mouse-rs, with libxdo for the keyboard and extra buttons on Linux,
+    /// where it needs the `xdo` feature.
+    #[default]
+    MouseRs,
+    /// Virtual devices created through `/dev/uinput`, which work under
+    /// Wayland and on the console too. The only backend with a touchscreen
+    /// and a pen, others get touch as mouse gestures.
+    Uinput,
+    /// The XTest extension of the X server on `$DISPLAY`.
+    Xtest,
+}
+
+/// How the deltas of a peer in relative pointer mode move the host pointer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "kebab-case")]
+pub enum RelativeMotion {
+    /// As motion of the input device, which games reading raw input need.
+    /// The host may accelerate it like that of a physical mouse.
+    #[default]
+    Native,
+    /// As moves to where the pointer would be, exactly as far as on the
+    /// peer, without acceleration.
+    Exact,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct InputConfig {
+    pub backend: InputBackend,
+    pub relative_motion: RelativeMotion,
+}
+
+/// Clipboard sharing between the host and the peer in control, over the
+/// `clipboard` data channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct ClipboardConfig {
+    pub enabled: bool,
+    /// Whether PNG images are shared along with text.
+    pub images: bool,
+    /// Largest text shared, in bytes.
+    pub max_text: usize,
+    /// Largest image shared, in bytes of PNG.
+    pub max_image: usize,
+    /// Milliseconds between two reads of the host clipboard.
+    pub poll_interval: u64,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            images: false,
+            max_text: 1024 * 1024,
+            max_image: 8 * 1024 * 1024,
+            poll_interval: 500,
+        }
+    }
+}
+
+/// Files exchanged with the peer in control over the `file_transfer` data
+/// channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct FileTransferConfig {
+    pub enabled: bool,
+    /// Where files sent by peers are saved, and the only place peers may
+    /// fetch files from. Created when missing.
+    pub directory: String,
+    /// Largest file accepted from a peer, in bytes.
+    pub max_size: u64,
+    /// Most bytes the partial files of the directory may take up together,
+    /// counting files being received at their full size. Offers beyond it
+    /// are rejected.
+    pub quota: u64,
+    /// Seconds a partial file is kept without being written to, after which
+    /// it is removed rather than resumed.
+    pub partial_lifetime: u64,
+}
+
+impl Default for FileTransferConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: "downloads".to_owned(),
+            max_size: 4 * 1024 * 1024 * 1024,
+            quota: 16 * 1024 * 1024 * 1024,
+            partial_lifetime: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Virtual pads fed by the peer in control over the `gamepad_events` data
+/// channel, which need write access to `/dev/uinput`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct GamepadConfig {
+    pub enabled: bool,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Settings of every landlord subsystem, read from the `landlord` key of
+/// Rocket's figment (`Rocket.toml`, `ROCKET_LANDLORD`) and from `LANDLORD_*`
+/// variables, where `__` separates nested keys (`LANDLORD_CAPTURE__BITRATE`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct LandlordConfig {
+    pub signaling: SignalingConfig,
+    pub ice: IceConfig,
+    pub screen: ScreenConfig,
+    pub capture: CaptureConfig,
+    pub peers: PeersConfig,
+    pub token: TokenConfig,
+    pub sessions: SessionsConfig,
+    pub admin: AdminConfig,
+    pub lan: LanConfig,
+    pub events: EventsConfig,
+    pub input: InputConfig,
+    pub clipboard: ClipboardConfig,
+    pub file_transfer: FileTransferConfig,
+    pub gamepad: GamepadConfig,
+}
+
+impl LandlordConfig {
+    pub fn from_figment(figment: &Figment) -> anyhow::Result<Self> {
+        let figment = figment.clone().merge(
+            Env::prefixed("LANDLORD_")
+                .split("__")
+                .map(|key| format!("landlord.{key}").into())
+                .global(),
+        );
+
+        let config = match figment.contains("landlord") {
+            true => figment.extract_inner::<Self>("landlord")?,
+            false => Self::default(),
+        };
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let address = &self.signaling.address;
+
+        if !(address.starts_with("ws://") || address.starts_with("wss://")) {
+            anyhow::bail!("signaling.address must be a ws:// or wss:// URL, got '{address}'");
+        }
+
+        if self.signaling.ping_interval == 0 {
+            anyhow::bail!("signaling.ping_interval must be positive");
+        }
+
+        if self.signaling.ping_timeout <= self.signaling.ping_interval {
+            anyhow::bail!("signaling.ping_timeout must be longer than signaling.ping_interval");
+        }
+
+        for server in &self.ice.servers {
+            if server.urls.is_empty() {
+                anyhow::bail!("ice.servers entries need at least one URL");
+            }
+
+            for url in &server.urls {
+                if !["stun:", "stuns:", "turn:", "turns:"]
+                    .iter()
+                    .any(|scheme| url.starts_with(scheme))
+                {
+                    anyhow::bail!("ice.servers URL '{url}' is not a STUN or TURN URL");
+                }
+            }
+        }
+
+        for server in &self.ice.turn {
+            if server.urls.is_empty() {
+                anyhow::bail!("ice.turn entries need at least one URL");
+            }
+
+            if let Some(url) = server
+                .urls
+                .iter()
+                .find(|url| !(url.starts_with("turn:") || url.starts_with("turns:")))
+            {
+                anyhow::bail!("ice.turn URL '{url}' is not a TURN URL");
+            }
+
+            if server.shared_secret.expose().is_empty() {
+                anyhow::bail!("ice.turn entries need a shared_secret");
+            }
+
+            if server.ttl == 0 {
+                anyhow::bail!("ice.turn ttl must be positive");
+            }
+        }
+
+        if self.screen.width == 0 || self.screen.height == 0 {
+            anyhow::bail!("screen.width and screen.height must be positive");
+        }
+
+        if self.capture.width == 0 || self.capture.height == 0 {
+            anyhow::bail!("capture.width and capture.height must be positive");
+        }
+
+        if !(1..=120).contains(&self.capture.frame_rate) {
+            anyhow::bail!("capture.frame_rate must be between 1 and 120");
+        }
+
+        if self.peers.max == 0 {
+            anyhow::bail!("peers.max must be positive");
+        }
+
+        if self.token.enabled() {
+            if self.token.audience.is_empty() || self.token.host_id.is_empty() {
+                anyhow::bail!("token.audience and token.host_id are required along with token.key");
+            }
+
+            if self.token.algorithm == TokenAlgorithm::EdDsa {
+                jsonwebtoken::DecodingKey::from_ed_pem(self.token.key.expose().as_bytes())
+                    .map_err(|e| anyhow::anyhow!("token.key is not an Ed25519 public key: {e}"))?;
+            }
+        }
+
+        if self.clipboard.enabled && self.clipboard.poll_interval == 0 {
+            anyhow::bail!("clipboard.poll_interval must be positive");
+        }
+
+        if self.file_transfer.enabled && self.file_transfer.directory.is_empty() {
+            anyhow::bail!("file_transfer.directory is required");
+        }
+
+        if self.file_transfer.enabled && self.file_transfer.quota < self.file_transfer.max_size {
+            anyhow::bail!("file_transfer.quota must be at least file_transfer.max_size");
+        }
+
+        if self.events.capacity == 0 {
+            anyhow::bail!("events.capacity must be positive");
+        }
+
+        let webhook = self.events.webhook.expose();
+
+        if !(webhook.is_empty()
+            || webhook.starts_with("http://")
+            || webhook.starts_with("https://"))
+        {
+            anyhow::bail!("events.webhook must be an http:// or https:// URL");
+        }
+
+        if self.events.webhook_timeout == 0 {
+            anyhow::bail!("events.webhook_timeout must be positive");
+        }
+
+        let digits = self.capture.bitrate.trim_end_matches(['k', 'K', 'm', 'M']);
+
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            anyhow::bail!(
+                "capture.bitrate must look like '2M' or '800k', got '{}'",
+                self.capture.bitrate
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads and validates the [`LandlordConfig`], refusing to launch on errors.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Landlord configuration", |rocket| async {
+        match LandlordConfig::from_figment(rocket.figment()) {
+            Ok(config) => Ok(rocket.manage(config)),
+            Err(e) => {
+                error!("Invalid landlord configuration: {e}");
+                Err(rocket)
+            }
+        }
+    })
+}
+
+/// Shows the effective configuration, with secrets redacted.
+#[get("/config")]
+pub fn effective_config(config: &State<LandlordConfig>) -> Json<LandlordConfig> {
+    Json(config.inner().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_turn_credentials() {
+        let turn = TurnRestConfig {
+            urls: vec!["turn:turn.example.org:3478".to_owned()],
+            shared_secret: Secret("north-wind".to_owned()),
+            ttl: 86400,
+        };
+
+        let server = turn.credentials_for(
+            "4c0e5a3b-peer",
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        );
+
+        assert_eq!(server.urls, turn.urls);
+        assert_eq!(server.username, "1700086400:4c0e5a3b-peer");
+        // From `openssl dgst -sha1 -hmac north-wind -binary | base64`.
+        assert_eq!(server.credential, "gtdcYEwPW28b1hJPDGBQQj7+cjM=");
+    }
+}
@@ -0,0 +1,697 @@
+use std::borrow::Cow;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use base64::Engine;
+use rocket::serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Notify, RwLock};
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+
+use crate::config::ClipboardConfig;
+use crate::conn::{peer_utils, AetherPeerConnection, PeerSet};
+
+/// Largest `data` of one message, well below the message size browsers
+/// accept even once escaped in JSON.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// What a clipboard holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardContent {
+    Text(String),
+    /// A PNG image.
+    Png(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+enum ContentKind {
+    Text,
+    Png,
+}
+
+/// Part of a clipboard content: text as is, or a PNG image in base64. A
+/// content spans as many messages as needed, all but the last one flagged
+/// with `more`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ClipboardChunk {
+    kind: ContentKind,
+    data: String,
+    #[serde(default)]
+    more: bool,
+}
+
+/// A message of the `clipboard` data channel, in both directions.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename = "clipboard")]
+struct ClipboardMessage {
+    payload: ClipboardChunk,
+}
+
+/// A content being received from a peer, one chunk at a time.
+#[derive(Default)]
+struct Incoming {
+    kind: Option<ContentKind>,
+    data: String,
+    oversized: bool,
+}
+
+impl Incoming {
+    /// Adds `chunk`, and returns the content it completes.
+    fn push(
+        &mut self,
+        chunk: ClipboardChunk,
+        config: &ClipboardConfig,
+    ) -> anyhow::Result<Option<ClipboardContent>> {
+        // A content of another kind starts over.
+        if self.kind.is_some_and(|kind| kind != chunk.kind) {
+            *self = Self::default();
+        }
+
+        self.kind = Some(chunk.kind);
+
+        let limit = match chunk.kind {
+            ContentKind::Text => config.max_text,
+            ContentKind::Png => config.max_image.div_ceil(3) * 4,
+        };
+
+        if !self.oversized {
+            self.data.push_str(&chunk.data);
+
+            if self.data.len() > limit {
+                self.oversized = true;
+                self.data = String::new();
+            }
+        }
+
+        if chunk.more {
+            return Ok(None);
+        }
+
+        let incoming = std::mem::take(self);
+
+        if incoming.oversized {
+            anyhow::bail!("the content is over the size limit");
+        }
+
+        Ok(Some(match chunk.kind {
+            ContentKind::Text => ClipboardContent::Text(incoming.data),
+            ContentKind::Png => ClipboardContent::Png(
+                base64::engine::general_purpose::STANDARD.decode(incoming.data)?,
+            ),
+        }))
+    }
+}
+
+/// A clipboard of the host. Reading and writing it may block.
+pub trait ClipboardBackend: Send {
+    /// The current content, or `None` when it holds nothing shareable.
+    /// Images are only read when `images` is set.
+    fn read(&mut self, images: bool) -> anyhow::Result<Option<ClipboardContent>>;
+
+    fn write(&mut self, content: &ClipboardContent) -> anyhow::Result<()>;
+}
+
+/// The clipboard of the desktop session, through arboard.
+pub struct SystemClipboard {
+    clipboard: arboard::Clipboard,
+    /// Hash of the last image read and its PNG, which is costly to encode
+    /// again at every read.
+    last_image: Option<(u64, Vec<u8>)>,
+}
+
+impl SystemClipboard {
+    pub fn open() -> anyhow::Result<Self> {
+        Ok(Self {
+            clipboard: arboard::Clipboard::new()?,
+            last_image: None,
+        })
+    }
+}
+
+impl ClipboardBackend for SystemClipboard {
+    fn read(&mut self, images: bool) -> anyhow::Result<Option<ClipboardContent>> {
+        match self.clipboard.get_text() {
+            Ok(text) => return Ok(Some(ClipboardContent::Text(text))),
+            Err(arboard::Error::ContentNotAvailable) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        if !images {
+            return Ok(None);
+        }
+
+        let image = match self.clipboard.get_image() {
+            Ok(image) => image,
+            Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut hasher = DefaultHasher::new();
+        (image.width, image.height, &image.bytes).hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let png = match &self.last_image {
+            Some((last, png)) if *last == hash => png.clone(),
+            _ => {
+                let png = encode_png(&image)?;
+                self.last_image = Some((hash, png.clone()));
+                png
+            }
+        };
+
+        Ok(Some(ClipboardContent::Png(png)))
+    }
+
+    fn write(&mut self, content: &ClipboardContent) -> anyhow::Result<()> {
+        match content {
+            ClipboardContent::Text(text) => self.clipboard.set_text(text)?,
+            ClipboardContent::Png(png) => self.clipboard.set_image(decode_png(png)?)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn encode_png(image: &arboard::ImageData) -> anyhow::Result<Vec<u8>> {
+    let mut png = vec![];
+
+    let mut encoder = png::Encoder::new(&mut png, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.bytes)?;
+    writer.finish()?;
+
+    Ok(png)
+}
+
+/// Decodes `png` to the RGBA pixels arboard expects, within the memory limits
+/// of the png crate.
+fn decode_png(png: &[u8]) -> anyhow::Result<arboard::ImageData<'static>> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(png));
+    decoder.set_transformations(
+        png::Transformations::normalize_to_color8() | png::Transformations::ALPHA,
+    );
+
+    let mut reader = decoder.read_info()?;
+    let mut pixels = vec![
+        0;
+        reader
+            .output_buffer_size()
+            .ok_or_else(|| anyhow!("the image is too large"))?
+    ];
+
+    let frame = reader.next_frame(&mut pixels)?;
+    pixels.truncate(frame.buffer_size());
+
+    let rgba = match frame.color_type {
+        png::ColorType::Rgba => pixels,
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        colors => anyhow::bail!("unexpected {colors:?} image"),
+    };
+
+    Ok(arboard::ImageData {
+        width: frame.width as usize,
+        height: frame.height as usize,
+        bytes: rgba.into(),
+    })
+}
+
+/// A clipboard kept in memory, for tests on hosts without a display.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct FakeClipboard(Arc<Mutex<Option<ClipboardContent>>>);
+
+#[cfg(test)]
+impl FakeClipboard {
+    pub fn content(&self) -> Option<ClipboardContent> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Replaces the content, as if copied on the host.
+    pub fn copy(&self, content: ClipboardContent) {
+        self.0.lock().unwrap().replace(content);
+    }
+}
+
+#[cfg(test)]
+impl ClipboardBackend for FakeClipboard {
+    fn read(&mut self, images: bool) -> anyhow::Result<Option<ClipboardContent>> {
+        Ok(self
+            .content()
+            .filter(|content| images || matches!(content, ClipboardContent::Text(_))))
+    }
+
+    fn write(&mut self, content: &ClipboardContent) -> anyhow::Result<()> {
+        self.copy(content.clone());
+        Ok(())
+    }
+}
+
+/// The clipboard of the host, shared by every peer. Changes made on the host
+/// are noticed by reading it every `clipboard.poll_interval`.
+#[derive(Clone)]
+pub struct HostClipboard {
+    backend: Arc<Mutex<Box<dyn ClipboardBackend>>>,
+    /// The content last read or written.
+    latest: Arc<watch::Sender<Option<Arc<ClipboardContent>>>>,
+    config: ClipboardConfig,
+}
+
+impl HostClipboard {
+    /// Shares `backend` and starts polling it.
+    pub fn start(backend: Box<dyn ClipboardBackend>, config: ClipboardConfig) -> Self {
+        let clipboard = Self {
+            backend: Arc::new(Mutex::new(backend)),
+            latest: Arc::new(watch::channel(None).0),
+            config,
+        };
+
+        tokio::spawn(clipboard.clone().follow());
+
+        clipboard
+    }
+
+    fn fits(&self, content: &ClipboardContent) -> bool {
+        match content {
+            ClipboardContent::Text(text) => text.len() <= self.config.max_text,
+            ClipboardContent::Png(png) => self.config.images && png.len() <= self.config.max_image,
+        }
+    }
+
+    async fn read(&self) -> anyhow::Result<Option<ClipboardContent>> {
+        let backend = self.backend.clone();
+        let images = self.config.images;
+
+        tokio::task::spawn_blocking(move || {
+            backend
+                .lock()
+                .map_err(|_| anyhow!("the clipboard is poisoned"))?
+                .read(images)
+        })
+        .await?
+    }
+
+    /// Writes `content` to the host clipboard.
+    pub async fn write(&self, content: ClipboardContent) -> anyhow::Result<()> {
+        let backend = self.backend.clone();
+        let written = content.clone();
+
+        tokio::task::spawn_blocking(move || {
+            backend
+                .lock()
+                .map_err(|_| anyhow!("the clipboard is poisoned"))?
+                .write(&written)
+        })
+        .await??;
+
+        // Peers are not sent back what they wrote.
+        self.latest.send_if_modified(|latest| {
+            latest.replace(Arc::new(content));
+            false
+        });
+
+        Ok(())
+    }
+
+    /// Publishes every change of the host clipboard small enough to share.
+    async fn follow(self) {
+        let mut ticker = tokio::time::interval(Duration::from_millis(self.config.poll_interval));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        loop {
+            ticker.tick().await;
+
+            let content = match self.read().await {
+                Ok(Some(content)) => content,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("Unable to read the clipboard: {e:#}");
+                    continue;
+                }
+            };
+
+            self.latest.send_if_modified(|latest| {
+                if latest.as_deref() == Some(&content) {
+                    return false;
+                }
+
+                let fits = self.fits(&content);
+
+                if !fits {
+                    debug!("The clipboard of the host is too large to share.");
+                }
+
+                latest.replace(Arc::new(content));
+                fits
+            });
+        }
+    }
+}
+
+async fn in_control(peers: &PeerSet, peer: &Arc<RwLock<AetherPeerConnection>>) -> bool {
+    peer_utils::fetch_peer_in_control(peers)
+        .await
+        .is_some_and(|ctrl| Arc::ptr_eq(&ctrl, peer))
+}
+
+/// Serves the `clipboard` data channel of `peer`: what the peer copies goes
+/// to the host clipboard and what is copied on the host goes to the peer,
+/// both only while it is in control.
+pub(super) fn serve(
+    channel: Arc<RTCDataChannel>,
+    peer: Arc<RwLock<AetherPeerConnection>>,
+    peers: PeerSet,
+    clipboard: HostClipboard,
+) {
+    let closed = Arc::new(Notify::new());
+    let on_close = closed.clone();
+
+    channel.on_close(Box::new(move || {
+        on_close.notify_one();
+        Box::pin(async {})
+    }));
+
+    tokio::spawn(send_host_changes(
+        channel.clone(),
+        peer.clone(),
+        peers.clone(),
+        clipboard.clone(),
+        closed,
+    ));
+
+    let incoming = Arc::new(Mutex::new(Incoming::default()));
+
+    channel.on_message(Box::new(move |msg: DataChannelMessage| {
+        let content = match serde_json::from_slice::<ClipboardMessage>(&msg.data) {
+            Ok(message) => incoming
+                .lock()
+                .map_err(|_| anyhow!("the incoming clipboard is poisoned"))
+                .and_then(|mut incoming| incoming.push(message.payload, &clipboard.config)),
+            Err(e) => Err(anyhow!("unexpected value: {e}")),
+        };
+
+        let peer = peer.clone();
+        let peers = peers.clone();
+        let clipboard = clipboard.clone();
+
+        Box::pin(async move {
+            match content {
+                Ok(Some(content)) => accept(content, &peer, &peers, &clipboard).await,
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(
+                        "Ignoring a clipboard of peer '{}': {e}",
+                        peer.read().await.uuid
+                    );
+                }
+            }
+        })
+    }));
+}
+
+/// Writes what `peer` copied to the host clipboard, if it is in control and
+/// the content may be shared.
+async fn accept(
+    content: ClipboardContent,
+    peer: &Arc<RwLock<AetherPeerConnection>>,
+    peers: &PeerSet,
+    clipboard: &HostClipboard,
+) {
+    if !clipboard.fits(&content) {
+        warn!(
+            "Ignoring a clipboard of peer '{}': images are not shared or it is too large.",
+            peer.read().await.uuid
+        );
+        return;
+    }
+
+    if !in_control(peers, peer).await {
+        debug!(
+            "Ignoring the clipboard of peer '{}', which is not in control.",
+            peer.read().await.uuid
+        );
+        return;
+    }
+
+    if let Err(e) = clipboard.write(content).await {
+        warn!("Unable to write the clipboard: {e:#}");
+    }
+}
+
+/// Sends the host clipboard to `peer` as it takes control, then its changes
+/// while it is in control, until its channel closes.
+async fn send_host_changes(
+    channel: Arc<RTCDataChannel>,
+    peer: Arc<RwLock<AetherPeerConnection>>,
+    peers: PeerSet,
+    clipboard: HostClipboard,
+    closed: Arc<Notify>,
+) {
+    let mut updates = clipboard.latest.subscribe();
+    let control_taken = peer.read().await.control_taken.clone();
+
+    loop {
+        tokio::select! {
+            changed = updates.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+            }
+            _ = control_taken.notified() => {}
+            _ = closed.notified() => break,
+        }
+
+        let Some(content) = updates.borrow_and_update().clone() else {
+            continue;
+        };
+
+        // The latest content may be too large, once control is taken.
+        if !clipboard.fits(&content) || !in_control(&peers, &peer).await {
+            continue;
+        }
+
+        if let Err(e) = send(&channel, &content).await {
+            warn!("Unable to send the clipboard: {e}");
+        }
+    }
+}
+
+async fn send(channel: &RTCDataChannel, content: &ClipboardContent) -> anyhow::Result<()> {
+    let (kind, data) = match content {
+        ClipboardContent::Text(text) => (ContentKind::Text, Cow::Borrowed(text.as_str())),
+        ClipboardContent::Png(png) => (
+            ContentKind::Png,
+            Cow::Owned(base64::engine::general_purpose::STANDARD.encode(png)),
+        ),
+    };
+
+    let chunks = split(&data, CHUNK_SIZE);
+    let last = chunks.len() - 1;
+
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let message = serde_json::to_string(&ClipboardMessage {
+            payload: ClipboardChunk {
+                kind,
+                data: chunk.to_owned(),
+                more: index < last,
+            },
+        })?;
+
+        channel.send_text(message).await?;
+    }
+
+    Ok(())
+}
+
+/// Splits `data` in pieces of at most `size` bytes, on character boundaries,
+/// unless a single character is larger. Empty data still makes one piece.
+fn split(data: &str, size: usize) -> Vec<&str> {
+    let mut pieces = vec![];
+    let mut rest = data;
+
+    while rest.len() > size {
+        let mut end = size;
+
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        if end == 0 {
+            end = rest.chars().next().map_or(0, char::len_utf8);
+        }
+
+        let (piece, tail) = rest.split_at(end);
+        pieces.push(piece);
+        rest = tail;
+    }
+
+    if !rest.is_empty() || pieces.is_empty() {
+        pieces.push(rest);
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: ContentKind, data: &str, more: bool) -> ClipboardChunk {
+        ClipboardChunk {
+            kind,
+            data: data.to_owned(),
+            more,
+        }
+    }
+
+    #[test]
+    fn reassembles_chunks() {
+        let config = ClipboardConfig::default();
+        let mut incoming = Incoming::default();
+
+        let text = "héllo wörld".repeat(3);
+        let pieces = split(&text, 7);
+        let last = pieces.len() - 1;
+
+        for (index, piece) in pieces.iter().enumerate() {
+            let content = incoming
+                .push(chunk(ContentKind::Text, piece, index < last), &config)
+                .unwrap();
+
+            match index < last {
+                true => assert_eq!(content, None),
+                false => assert_eq!(content, Some(ClipboardContent::Text(text.clone()))),
+            }
+        }
+
+        let png = vec![0x89, b'P', b'N', b'G', 0, 1, 2, 3, 4];
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&png);
+        let (head, tail) = encoded.split_at(5);
+
+        assert_eq!(
+            incoming
+                .push(chunk(ContentKind::Png, head, true), &config)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            incoming
+                .push(chunk(ContentKind::Png, tail, false), &config)
+                .unwrap(),
+            Some(ClipboardContent::Png(png))
+        );
+    }
+
+    #[test]
+    fn starts_over_on_another_kind() {
+        let config = ClipboardConfig::default();
+        let mut incoming = Incoming::default();
+
+        incoming
+            .push(chunk(ContentKind::Png, "AAAA", true), &config)
+            .unwrap();
+
+        assert_eq!(
+            incoming
+                .push(chunk(ContentKind::Text, "text", false), &config)
+                .unwrap(),
+            Some(ClipboardContent::Text("text".to_owned()))
+        );
+    }
+
+    #[test]
+    fn refuses_contents_over_the_limits() {
+        let config = ClipboardConfig {
+            max_text: 8,
+            max_image: 3,
+            ..Default::default()
+        };
+        let mut incoming = Incoming::default();
+
+        assert_eq!(
+            incoming
+                .push(chunk(ContentKind::Text, "12345", true), &config)
+                .unwrap(),
+            None
+        );
+        assert!(incoming
+            .push(chunk(ContentKind::Text, "6789", false), &config)
+            .is_err());
+
+        // The next content is received afresh.
+        assert_eq!(
+            incoming
+                .push(chunk(ContentKind::Text, "12345678", false), &config)
+                .unwrap(),
+            Some(ClipboardContent::Text("12345678".to_owned()))
+        );
+
+        // 3 bytes of PNG are 4 in base64, 4 bytes are 8.
+        assert!(incoming
+            .push(chunk(ContentKind::Png, "AAAAAA==", false), &config)
+            .is_err());
+    }
+
+    #[test]
+    fn splits_on_character_boundaries() {
+        assert_eq!(split("", 4), vec![""]);
+        assert_eq!(split("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(split("aéé", 2), vec!["a", "é", "é"]);
+        assert_eq!(split("€", 1), vec!["€"]);
+    }
+
+    #[tokio::test]
+    async fn only_the_controller_writes() {
+        let fake = FakeClipboard::default();
+        let clipboard = HostClipboard::start(
+            Box::new(fake.clone()),
+            ClipboardConfig {
+                max_text: 16,
+                ..Default::default()
+            },
+        );
+
+        let controller = AetherPeerConnection::detached("controller").await;
+        let watcher = AetherPeerConnection::detached("watcher").await;
+
+        let peers = PeerSet::default();
+        peers
+            .write()
+            .await
+            .extend([controller.clone(), watcher.clone()]);
+        peers
+            .change_control_to("controller".to_owned())
+            .await
+            .unwrap();
+
+        let copied = |text: &str| ClipboardContent::Text(text.to_owned());
+
+        accept(copied("from the watcher"), &watcher, &peers, &clipboard).await;
+        assert_eq!(fake.content(), None);
+
+        accept(
+            copied("from the controller"),
+            &controller,
+            &peers,
+            &clipboard,
+        )
+        .await;
+        assert_eq!(fake.content(), None, "over max_text");
+
+        accept(copied("controller"), &controller, &peers, &clipboard).await;
+        assert_eq!(fake.content(), Some(copied("controller")));
+
+        let image = ClipboardContent::Png(vec![1, 2, 3]);
+        accept(image, &controller, &peers, &clipboard).await;
+        assert_eq!(fake.content(), Some(copied("controller")), "images are off");
+    }
+}
@@ -0,0 +1,447 @@
+use std::fmt;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::config::LandlordConfig;
+use crate::conn::input::Position;
+use crate::probe::probe_layout;
+
+/// An area of the desktop, in desktop pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// An output of the desktop, such as a monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    pub name: String,
+    pub rect: Rect,
+}
+
+/// The desktop spanned by every output, and where each of them sits in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayLayout {
+    pub desktop: Rect,
+    pub monitors: Vec<Monitor>,
+}
+
+impl DisplayLayout {
+    /// A single output of `width` by `height`, assumed when the layout cannot
+    /// be probed.
+    pub fn fallback(width: u32, height: u32) -> Self {
+        Self {
+            desktop: Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+            monitors: vec![],
+        }
+    }
+
+    /// The area of the output named `monitor`, or the whole desktop when it
+    /// is not connected, has no area or `monitor` is empty. Positions cannot
+    /// be mapped into an empty region.
+    pub fn region(&self, monitor: &str) -> Rect {
+        self.monitors
+            .iter()
+            .find(|candidate| !monitor.is_empty() && candidate.name == monitor)
+            .map(|monitor| monitor.rect)
+            .filter(|rect| !rect.is_empty())
+            .unwrap_or(self.desktop)
+    }
+}
+
+/// Turns positions in the video sent to peers into desktop pixels.
+///
+/// The capture scales the region to fit the frame without stretching it and
+/// centers it, leaving bars on two sides when their aspect ratios differ.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoordinateMapper {
+    /// The captured area of the desktop.
+    pub region: Rect,
+    /// Width and height of the video frames.
+    pub frame: (u32, u32),
+}
+
+impl CoordinateMapper {
+    /// Where the region shows in a frame: its left, top, width and height,
+    /// in frame pixels.
+    pub fn content(&self) -> (f64, f64, f64, f64) {
+        let (frame_width, frame_height) = (self.frame.0 as f64, self.frame.1 as f64);
+        let (region_width, region_height) = (self.region.width as f64, self.region.height as f64);
+
+        let scale = (frame_width / region_width).min(frame_height / region_height);
+        let (width, height) = (region_width * scale, region_height * scale);
+
+        (
+            (frame_width - width) / 2.0,
+            (frame_height - height) / 2.0,
+            width,
+            height,
+        )
+    }
+
+    /// The desktop pixel under `at`, a position in the frame. Positions on
+    /// the bars go to the nearest edge of the region.
+    pub fn map(&self, at: Position) -> (i32, i32) {
+        let (left, top, width, height) = self.content();
+
+        let axis = |ratio: f64, frame: u32, offset: f64, size: f64, origin: i32, pixels: u32| {
+            let along = ((ratio * frame as f64 - offset) / size).clamp(0.0, 1.0);
+            // The far edge of the region is one pixel short of its size.
+            origin + ((along * pixels as f64) as i32).min(pixels as i32 - 1)
+        };
+
+        (
+            axis(
+                at.x_ratio,
+                self.frame.0,
+                left,
+                width,
+                self.region.x,
+                self.region.width,
+            ),
+            axis(
+                at.y_ratio,
+                self.frame.1,
+                top,
+                height,
+                self.region.y,
+                self.region.height,
+            ),
+        )
+    }
+
+    /// How many desktop pixels an area of `width_ratio` by `height_ratio`
+    /// of the frame spans.
+    pub fn size(&self, width_ratio: f64, height_ratio: f64) -> (u32, u32) {
+        let (_, _, width, height) = self.content();
+
+        let axis = |ratio: f64, frame: u32, size: f64, pixels: u32| match ratio.is_finite() {
+            true => (ratio.max(0.0) * frame as f64 / size * pixels as f64)
+                .round()
+                .min(pixels as f64) as u32,
+            false => 0,
+        };
+
+        (
+            axis(width_ratio, self.frame.0, width, self.region.width),
+            axis(height_ratio, self.frame.1, height, self.region.height),
+        )
+    }
+}
+
+/// The layout of the desktop as last probed, and the part of it shared with
+/// peers. Captures and pointers read it each time they need it, and follow
+/// changes such as a monitor being plugged in or rotated.
+#[derive(Clone)]
+pub struct Display {
+    layout: watch::Receiver<DisplayLayout>,
+    monitor: String,
+    frame: (u32, u32),
+}
+
+impl Display {
+    /// Probes the layout, then again every `screen.layout_interval` seconds.
+    pub async fn start(config: &LandlordConfig) -> Self {
+        let monitor = config.screen.monitor.clone();
+        let layout = probe()
+            .await
+            .unwrap_or_else(|| DisplayLayout::fallback(config.screen.width, config.screen.height));
+
+        log_layout(&layout, &monitor);
+
+        let (sender, receiver) = watch::channel(layout);
+
+        if config.screen.layout_interval > 0 {
+            tokio::spawn(follow_layout(
+                sender,
+                monitor.clone(),
+                Duration::from_secs(config.screen.layout_interval),
+            ));
+        }
+
+        Self {
+            layout: receiver,
+            monitor,
+            frame: (config.capture.width, config.capture.height),
+        }
+    }
+
+    /// A display whose layout never changes, for tests.
+    #[cfg(test)]
+    pub fn fixed(layout: DisplayLayout, monitor: &str, frame: (u32, u32)) -> Self {
+        Self {
+            layout: watch::channel(layout).1,
+            monitor: monitor.to_owned(),
+            frame,
+        }
+    }
+
+    pub fn desktop(&self) -> Rect {
+        self.layout.borrow().desktop
+    }
+
+    /// The area of the desktop to capture.
+    pub fn region(&self) -> Rect {
+        self.layout.borrow().region(&self.monitor)
+    }
+
+    pub fn mapper(&self) -> CoordinateMapper {
+        CoordinateMapper {
+            region: self.region(),
+            frame: self.frame,
+        }
+    }
+
+    /// Waits for the layout to change, forever once it no longer can.
+    pub async fn changed(&mut self) {
+        if self.layout.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+async fn probe() -> Option<DisplayLayout> {
+    tokio::task::spawn_blocking(probe_layout)
+        .await
+        .ok()
+        .flatten()
+}
+
+fn log_layout(layout: &DisplayLayout, monitor: &str) {
+    if !monitor.is_empty() && !layout.monitors.iter().any(|m| m.name == monitor) {
+        warn!("Output '{monitor}' is not connected, sharing the whole desktop.");
+    }
+
+    info!(
+        "Desktop is {}, sharing {}.",
+        layout.desktop,
+        layout.region(monitor)
+    );
+}
+
+/// Probes the layout every `interval`, publishing it whenever it changes.
+async fn follow_layout(sender: watch::Sender<DisplayLayout>, monitor: String, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let Some(layout) = probe().await else {
+            continue;
+        };
+
+        let changed = sender.send_if_modified(|current| {
+            if *current == layout {
+                return false;
+            }
+
+            *current = layout;
+            true
+        });
+
+        if changed {
+            log_layout(&sender.borrow(), &monitor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn at(x_ratio: f64, y_ratio: f64) -> Position {
+        Position { x_ratio, y_ratio }
+    }
+
+    #[test]
+    fn maps_ratios_to_pixels() {
+        let mapper = CoordinateMapper {
+            region: rect(0, 0, 1920, 1080),
+            frame: (1280, 720),
+        };
+
+        assert_eq!(mapper.content(), (0.0, 0.0, 1280.0, 720.0));
+        assert_eq!(mapper.map(at(0.0, 0.0)), (0, 0));
+        assert_eq!(mapper.map(at(0.5, 0.25)), (960, 270));
+        assert_eq!(mapper.map(at(1.0, 1.0)), (1919, 1079));
+        assert_eq!(mapper.size(0.1, 0.1), (192, 108));
+    }
+
+    #[test]
+    fn skips_the_bars_of_a_pillarbox() {
+        // A 4:3 region in a 16:9 frame, with bars on the left and right.
+        let mapper = CoordinateMapper {
+            region: rect(0, 0, 1440, 1080),
+            frame: (1920, 1080),
+        };
+
+        assert_eq!(mapper.content(), (240.0, 0.0, 1440.0, 1080.0));
+        assert_eq!(mapper.map(at(0.125, 0.5)), (0, 540));
+        assert_eq!(mapper.map(at(0.5, 0.5)), (720, 540));
+        assert_eq!(mapper.map(at(0.05, 0.5)), (0, 540));
+        assert_eq!(mapper.map(at(0.95, 0.5)), (1439, 540));
+    }
+
+    #[test]
+    fn skips_the_bars_of_a_letterbox() {
+        // An ultrawide region in a 16:9 frame, with bars above and below.
+        let mapper = CoordinateMapper {
+            region: rect(0, 0, 2560, 1080),
+            frame: (1920, 1080),
+        };
+
+        assert_eq!(mapper.content(), (0.0, 135.0, 1920.0, 810.0));
+        assert_eq!(mapper.map(at(0.5, 0.125)), (1280, 0));
+        assert_eq!(mapper.map(at(0.5, 0.5)), (1280, 540));
+        assert_eq!(mapper.map(at(0.5, 0.05)), (1280, 0));
+        assert_eq!(mapper.map(at(0.5, 0.95)), (1280, 1079));
+    }
+
+    #[test]
+    fn maps_into_the_shared_monitor() {
+        let layout = DisplayLayout {
+            desktop: rect(0, 0, 4480, 1440),
+            monitors: vec![
+                Monitor {
+                    name: "DP-1".to_owned(),
+                    rect: rect(0, 0, 2560, 1440),
+                },
+                Monitor {
+                    name: "HDMI-1".to_owned(),
+                    rect: rect(2560, 360, 1920, 1080),
+                },
+            ],
+        };
+
+        let display = Display::fixed(layout.clone(), "HDMI-1", (1280, 720));
+        assert_eq!(display.region(), rect(2560, 360, 1920, 1080));
+        assert_eq!(display.mapper().map(at(0.0, 0.0)), (2560, 360));
+        assert_eq!(display.mapper().map(at(0.5, 0.5)), (3520, 900));
+        assert_eq!(display.mapper().map(at(1.0, 1.0)), (4479, 1439));
+
+        // Unplugged, the monitor gives way to the whole desktop.
+        let display = Display::fixed(layout, "VGA-1", (1280, 720));
+        assert_eq!(display.region(), rect(0, 0, 4480, 1440));
+    }
+
+    /// Regions of many shapes, with frames of many shapes.
+    fn mappers() -> impl Iterator<Item = CoordinateMapper> {
+        let sizes = [1, 2, 3, 7, 480, 640, 1080, 1366, 1920, 2160, 3840];
+        let frames = [
+            (1, 1),
+            (2, 3),
+            (640, 480),
+            (1280, 720),
+            (1080, 1920),
+            (1920, 1080),
+        ];
+
+        sizes.into_iter().flat_map(move |width| {
+            sizes.into_iter().flat_map(move |height| {
+                frames.into_iter().map(move |frame| CoordinateMapper {
+                    region: rect(-1920, 360, width, height),
+                    frame,
+                })
+            })
+        })
+    }
+
+    /// From -0.5 to 1.5, in steps of 1/200.
+    fn ratios() -> impl Iterator<Item = f64> {
+        (-100..=300).map(|step| step as f64 / 200.0)
+    }
+
+    #[test]
+    fn maps_into_the_region_monotonically() {
+        for mapper in mappers() {
+            let region = mapper.region;
+            let (right, bottom) = (
+                region.x + region.width as i32 - 1,
+                region.y + region.height as i32 - 1,
+            );
+
+            assert_eq!(mapper.map(at(0.0, 0.0)), (region.x, region.y), "{mapper:?}");
+            assert_eq!(mapper.map(at(1.0, 1.0)), (right, bottom), "{mapper:?}");
+
+            let mut last = (i32::MIN, i32::MIN);
+
+            for ratio in ratios() {
+                let (x, y) = mapper.map(at(ratio, ratio));
+
+                assert!((region.x..=right).contains(&x), "{mapper:?} {ratio}");
+                assert!((region.y..=bottom).contains(&y), "{mapper:?} {ratio}");
+                assert!(x >= last.0 && y >= last.1, "{mapper:?} {ratio}");
+
+                last = (x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn sizes_stay_within_the_region() {
+        for mapper in mappers() {
+            for ratio in ratios().chain([f64::INFINITY, f64::NAN, 1e300]) {
+                let (width, height) = mapper.size(ratio, ratio);
+
+                assert!(width <= mapper.region.width, "{mapper:?} {ratio}");
+                assert!(height <= mapper.region.height, "{mapper:?} {ratio}");
+            }
+        }
+    }
+
+    #[test]
+    fn skips_monitors_without_area() {
+        let layout = DisplayLayout {
+            desktop: rect(0, 0, 1920, 1080),
+            monitors: vec![Monitor {
+                name: "HDMI-1".to_owned(),
+                rect: rect(0, 0, 0, 1080),
+            }],
+        };
+
+        assert_eq!(layout.region("HDMI-1"), layout.desktop);
+    }
+
+    #[test]
+    fn clamps_positions_off_the_frame() {
+        let mapper = CoordinateMapper {
+            region: rect(1920, 0, 1920, 1080),
+            frame: (1920, 1080),
+        };
+
+        assert_eq!(mapper.map(at(-0.5, 2.0)), (1920, 1079));
+        assert_eq!(mapper.map(at(1.5, -1.0)), (3839, 0));
+        assert_eq!(mapper.map(at(f64::INFINITY, f64::NEG_INFINITY)), (3839, 0));
+    }
+}
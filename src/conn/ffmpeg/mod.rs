@@ -1,34 +1,21 @@
+use webrtc::api::media_engine::MIME_TYPE_H264;
+
+use crate::config::CaptureConfig;
+use crate::conn::display::Rect;
+
 #[cfg(target_os = "windows")]
-pub(crate) fn get_ffmpeg_command() -> Vec<String> {
+fn get_capture_input(region: Rect) -> Vec<String> {
     [
-        "-re",
         "-f",
         "gdigrab",
+        "-offset_x",
+        &region.x.to_string(),
+        "-offset_y",
+        &region.y.to_string(),
+        "-video_size",
+        &format!("{}x{}", region.width, region.height),
         "-i",
         "desktop",
-        "-deadline",
-        "realtime",
-        "-preset",
-        "ultrafast",
-        "-quality",
-        "realtime",
-        "-speed",
-        "16",
-        "-cpu-used",
-        "8",
-        "-vf",
-        "scale=1280:720",
-        "-c:v",
-        "vp8",
-        "-pix_fmt",
-        "yuv420p",
-        "-r",
-        "24",
-        "-b:v",
-        "2M",
-        "-f",
-        "ivf",
-        "-",
     ]
     .into_iter()
     .map(String::from)
@@ -36,40 +23,109 @@ pub(crate) fn get_ffmpeg_command() -> Vec<String> {
 }
 
 #[cfg(target_os = "linux")]
-pub(crate) fn get_ffmpeg_command() -> Vec<String> {
+fn get_capture_input(region: Rect) -> Vec<String> {
     let display = std::env::var("DISPLAY").unwrap_or(String::from(":0"));
 
     [
-        "-re",
         "-f",
         "x11grab",
+        "-video_size",
+        &format!("{}x{}", region.width, region.height),
         "-i",
-        &format!("{display}.0"),
-        "-deadline",
-        "realtime",
-        "-preset",
-        "ultrafast",
-        "-quality",
-        "realtime",
-        "-speed",
-        "16",
-        "-cpu-used",
-        "8",
-        "-vf",
-        "scale=1280:720",
-        "-c:v",
-        "vp8",
-        "-pix_fmt",
-        "yuv420p",
-        "-r",
-        "24",
-        "-b:v",
-        "2M",
-        "-f",
-        "ivf",
-        "-",
+        &format!("{display}.0+{},{}", region.x, region.y),
     ]
     .into_iter()
     .map(String::from)
     .collect()
 }
+
+/// Fails unless an `ffmpeg` binary can be run from the `PATH`.
+pub(crate) fn check_available() -> anyhow::Result<()> {
+    let status = std::process::Command::new("ffmpeg")
+        .args(["-hide_banner", "-version"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map_err(|e| anyhow::anyhow!("Unable to run ffmpeg, is it in PATH? {e}"))?;
+
+    if !status.success() {
+        anyhow::bail!("ffmpeg exited with {status}.");
+    }
+
+    Ok(())
+}
+
+/// Arguments making ffmpeg write `region` of the desktop to stdout, encoded
+/// as `codec`: an IVF stream for VP8 or an Annex B bytestream for H264.
+///
+/// The region is scaled to fit the frame without being stretched, and
+/// centered between black bars, as expected by
+/// [`CoordinateMapper`](crate::conn::display::CoordinateMapper).
+pub(crate) fn get_ffmpeg_command(
+    capture: &CaptureConfig,
+    codec: &str,
+    region: Rect,
+) -> Vec<String> {
+    let (width, height) = (capture.width, capture.height);
+    let scale = format!(
+        "scale={width}:{height}:force_original_aspect_ratio=decrease:force_divisible_by=2,\
+         pad={width}:{height}:(ow-iw)/2:(oh-ih)/2"
+    );
+    let frame_rate = capture.frame_rate.to_string();
+
+    let mut command = vec![String::from("-re")];
+    command.extend(get_capture_input(region));
+
+    let encoding: &[&str] = if codec == MIME_TYPE_H264 {
+        &[
+            "-preset",
+            "ultrafast",
+            "-tune",
+            "zerolatency",
+            "-vf",
+            &scale,
+            "-c:v",
+            "libx264",
+            "-pix_fmt",
+            "yuv420p",
+            "-r",
+            &frame_rate,
+            "-b:v",
+            &capture.bitrate,
+            "-bsf:v",
+            "h264_mp4toannexb",
+            "-f",
+            "h264",
+            "-",
+        ]
+    } else {
+        &[
+            "-deadline",
+            "realtime",
+            "-preset",
+            "ultrafast",
+            "-quality",
+            "realtime",
+            "-speed",
+            "16",
+            "-cpu-used",
+            "8",
+            "-vf",
+            &scale,
+            "-c:v",
+            "vp8",
+            "-pix_fmt",
+            "yuv420p",
+            "-r",
+            &frame_rate,
+            "-b:v",
+            &capture.bitrate,
+            "-f",
+            "ivf",
+            "-",
+        ]
+    };
+
+    command.extend(encoding.iter().map(|arg| String::from(*arg)));
+    command
+}
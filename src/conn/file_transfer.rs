@@ -0,0 +1,976 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use rocket::serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{Mutex, Notify, RwLock};
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+
+use crate::config::FileTransferConfig;
+use crate::conn::{peer_utils, AetherPeerConnection, PeerSet};
+
+/// Largest part of a file carried by one binary message.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Binary messages start with the id of their transfer and the offset of
+/// their part in the file, a big-endian `u64`.
+const HEADER_SIZE: usize = 16 + 8;
+
+/// Sending pauses once that many bytes wait in the channel, and resumes once
+/// they are down to [`LOW_WATER`].
+const HIGH_WATER: usize = 1024 * 1024;
+const LOW_WATER: usize = 256 * 1024;
+
+/// A control message of the `file_transfer` data channel. Either side sends
+/// a file by offering it; the receiver accepts or rejects the offer, and once
+/// every part of the file is in, checks its digest and tells the sender how
+/// it went.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "action", rename_all = "snake_case")]
+enum Transfer {
+    /// Proposes to send a file, whose SHA-256 digest is given in hex.
+    Offer {
+        id: String,
+        name: String,
+        size: u64,
+        sha256: String,
+    },
+    /// Asks for a file of the host, which offers it under `id` in return.
+    Request {
+        id: String,
+        name: String,
+    },
+    /// Accepts an offer. The sender skips the first `offset` bytes, kept by
+    /// the receiver from an earlier attempt.
+    Accept {
+        id: String,
+        offset: u64,
+    },
+    Reject {
+        id: String,
+        reason: String,
+    },
+    /// The whole file was received and matches its digest.
+    Complete {
+        id: String,
+    },
+    Failed {
+        id: String,
+        reason: String,
+    },
+    /// Stops a transfer. The receiver keeps what it got to resume later.
+    Cancel {
+        id: String,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename = "file_transfer")]
+struct TransferMessage {
+    payload: Transfer,
+}
+
+/// The directory of the host files are exchanged through.
+///
+/// Files being received are kept as `.<sha256>.part` until complete, so that
+/// a peer reconnecting after a failure resumes where it stopped by offering
+/// the same file again. Partial files take up at most the quota of the
+/// directory together, and those left untouched for too long are removed.
+/// Peers only ever name plain files of the directory itself, and never
+/// hidden ones.
+#[derive(Debug, Clone)]
+pub struct TransferDirectory {
+    root: PathBuf,
+    max_size: u64,
+    quota: u64,
+    partial_lifetime: Duration,
+    /// Size of each file being received, by digest, shared by every channel.
+    receiving: Arc<std::sync::Mutex<HashMap<String, u64>>>,
+}
+
+impl TransferDirectory {
+    /// Creates the directory of `config` when missing.
+    pub fn open(config: &FileTransferConfig) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&config.directory)
+            .with_context(|| format!("Unable to create {}", config.directory))?;
+
+        Ok(Self {
+            root: std::fs::canonicalize(&config.directory)?,
+            max_size: config.max_size,
+            quota: config.quota,
+            partial_lifetime: Duration::from_secs(config.partial_lifetime),
+            receiving: Arc::default(),
+        })
+    }
+
+    /// Where the file named `name` by a peer goes.
+    fn path_of(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let mut components = Path::new(name).components();
+
+        let plain = matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        );
+
+        if !plain || name.starts_with('.') || name.contains(['/', '\\', ':', '\0']) {
+            anyhow::bail!("'{name}' is not a valid file name");
+        }
+
+        Ok(self.root.join(name))
+    }
+
+    /// The file named `name` by a peer, which must exist within the
+    /// directory once links are followed.
+    async fn existing(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let path = tokio::fs::canonicalize(self.path_of(name)?)
+            .await
+            .map_err(|_| anyhow!("there is no file '{name}'"))?;
+
+        if !path.starts_with(&self.root) || !tokio::fs::metadata(&path).await?.is_file() {
+            anyhow::bail!("there is no file '{name}'");
+        }
+
+        Ok(path)
+    }
+
+    fn partial_of(&self, sha256: &str) -> PathBuf {
+        self.root.join(format!(".{sha256}.part"))
+    }
+
+    fn receiving(&self) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<String, u64>>> {
+        self.receiving
+            .lock()
+            .map_err(|_| anyhow!("the transfer directory is poisoned"))
+    }
+
+    /// The size of each partial file, by digest, once those untouched for
+    /// longer than their lifetime are removed.
+    async fn sweep_partials(&self) -> anyhow::Result<HashMap<String, u64>> {
+        let mut partials = HashMap::new();
+        let mut entries = tokio::fs::read_dir(&self.root).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(sha256) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix('.')?.strip_suffix(".part"))
+            else {
+                continue;
+            };
+
+            let metadata = entry.metadata().await?;
+            let stale = metadata
+                .modified()?
+                .elapsed()
+                .is_ok_and(|age| age > self.partial_lifetime);
+
+            if stale && !self.receiving()?.contains_key(sha256) {
+                info!(
+                    "Removing the stale partial file {}.",
+                    entry.path().display()
+                );
+                tokio::fs::remove_file(entry.path()).await?;
+                continue;
+            }
+
+            partials.insert(sha256.to_owned(), metadata.len());
+        }
+
+        Ok(partials)
+    }
+
+    /// Makes room for receiving the file of digest `sha256`, released once
+    /// the claim is dropped. Fails when the file is already being received,
+    /// or when the partial files would go over the quota.
+    async fn claim(&self, sha256: &str, size: u64) -> anyhow::Result<Claim> {
+        let partials = self.sweep_partials().await?;
+        let mut receiving = self.receiving()?;
+
+        if receiving.contains_key(sha256) {
+            anyhow::bail!("the file is already being received");
+        }
+
+        // Files being received count at their full size, written or not.
+        let used: u64 = partials
+            .iter()
+            .filter(|(partial, _)| partial.as_str() != sha256)
+            .map(|(partial, &len)| len.max(receiving.get(partial).copied().unwrap_or(0)))
+            .chain(
+                receiving
+                    .iter()
+                    .filter(|(partial, _)| !partials.contains_key(*partial))
+                    .map(|(_, &size)| size),
+            )
+            .sum();
+
+        if used.saturating_add(size) > self.quota {
+            anyhow::bail!("there is no room left for the file");
+        }
+
+        receiving.insert(sha256.to_owned(), size);
+
+        Ok(Claim {
+            sha256: sha256.to_owned(),
+            receiving: self.receiving.clone(),
+        })
+    }
+
+    /// Starts receiving the file `name` of `size` bytes and digest `sha256`
+    /// as `id`, from the whole chunks a partial file of the same digest
+    /// already holds.
+    async fn receive(
+        &self,
+        id: Uuid,
+        name: String,
+        size: u64,
+        sha256: String,
+    ) -> anyhow::Result<Upload> {
+        let sha256 = sha256.to_ascii_lowercase();
+
+        if sha256.len() != 64 || !sha256.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            anyhow::bail!("'{sha256}' is not a SHA-256 digest");
+        }
+
+        if size > self.max_size {
+            anyhow::bail!("the file is over the size limit");
+        }
+
+        if tokio::fs::try_exists(self.path_of(&name)?).await? {
+            anyhow::bail!("there already is a file '{name}'");
+        }
+
+        let claim = self.claim(&sha256, size).await?;
+
+        let partial = self.partial_of(&sha256);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&partial)
+            .await?;
+
+        // Only whole chunks are kept, as the last one may have been cut short.
+        let kept = file.metadata().await?.len().min(size);
+        let offset = kept - kept % CHUNK_SIZE as u64;
+
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let mut hashed = 0;
+
+        while hashed < offset {
+            let wanted = (offset - hashed).min(CHUNK_SIZE as u64) as usize;
+            let read = file.read(&mut buffer[..wanted]).await?;
+
+            if read == 0 {
+                anyhow::bail!("the partial file shrank");
+            }
+
+            hasher.update(&buffer[..read]);
+            hashed += read as u64;
+        }
+
+        file.set_len(offset).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+        Ok(Upload {
+            id,
+            name,
+            sha256,
+            size,
+            partial,
+            file,
+            hasher,
+            received: offset,
+            _claim: claim,
+        })
+    }
+}
+
+/// Room taken in a [`TransferDirectory`] by a file being received.
+struct Claim {
+    sha256: String,
+    receiving: Arc<std::sync::Mutex<HashMap<String, u64>>>,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if let Ok(mut receiving) = self.receiving.lock() {
+            receiving.remove(&self.sha256);
+        }
+    }
+}
+
+/// A file being received from the peer.
+struct Upload {
+    id: Uuid,
+    name: String,
+    sha256: String,
+    size: u64,
+    partial: PathBuf,
+    file: File,
+    hasher: Sha256,
+    received: u64,
+    _claim: Claim,
+}
+
+impl Upload {
+    /// Writes the part of the file at `offset`, which must follow the parts
+    /// written so far.
+    async fn write(&mut self, offset: u64, bytes: &[u8]) -> anyhow::Result<()> {
+        let end = self.received + bytes.len() as u64;
+
+        if offset != self.received || end > self.size {
+            anyhow::bail!("unexpected chunk at {offset} bytes");
+        }
+
+        self.file.write_all(bytes).await?;
+        self.hasher.update(bytes);
+        self.received = end;
+
+        Ok(())
+    }
+
+    fn is_complete(&self) -> bool {
+        self.received == self.size
+    }
+
+    /// Checks the digest of the file, and moves it to its name in
+    /// `directory`.
+    async fn finish(mut self, directory: &TransferDirectory) -> anyhow::Result<PathBuf> {
+        self.file.flush().await?;
+
+        let digest = format!("{:x}", self.hasher.finalize_reset());
+
+        if digest != self.sha256 {
+            tokio::fs::remove_file(&self.partial).await?;
+            anyhow::bail!("the file does not match its digest");
+        }
+
+        let path = directory.path_of(&self.name)?;
+
+        // The name may have been taken since the offer, keep the file to
+        // resume from under another name.
+        if tokio::fs::try_exists(&path).await? {
+            anyhow::bail!("there already is a file '{}'", self.name);
+        }
+
+        tokio::fs::rename(&self.partial, &path).await?;
+
+        Ok(path)
+    }
+}
+
+/// A file of the host offered to the peer, and the task sending it once
+/// accepted.
+struct Download {
+    id: Uuid,
+    path: PathBuf,
+    size: u64,
+    task: Option<AbortHandle>,
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
+
+/// Transfers of one `file_transfer` channel: one file at most in each
+/// direction. Offering or requesting another file replaces the previous one.
+#[derive(Default)]
+struct Transfers {
+    upload: Option<Upload>,
+    download: Option<Download>,
+}
+
+/// What serving one channel needs.
+struct Session {
+    channel: Arc<RTCDataChannel>,
+    peer: Arc<RwLock<AetherPeerConnection>>,
+    peers: PeerSet,
+    directory: TransferDirectory,
+    transfers: Mutex<Transfers>,
+    /// Notified when the channel drained down to [`LOW_WATER`].
+    drained: Arc<Notify>,
+}
+
+/// Serves the `file_transfer` data channel of `peer`, which may only exchange
+/// files while it is in control. Transfers fail once it loses control.
+pub(super) fn serve(
+    channel: Arc<RTCDataChannel>,
+    peer: Arc<RwLock<AetherPeerConnection>>,
+    peers: PeerSet,
+    directory: TransferDirectory,
+) {
+    let drained = Arc::new(Notify::new());
+    let on_low = drained.clone();
+    let opened = channel.clone();
+
+    // Set earlier, the threshold and its handler never reach the stream of a
+    // channel the peer opened.
+    channel.on_open(Box::new(move || {
+        Box::pin(async move {
+            opened.set_buffered_amount_low_threshold(LOW_WATER).await;
+            opened
+                .on_buffered_amount_low(Box::new(move || {
+                    on_low.notify_one();
+                    Box::pin(async {})
+                }))
+                .await;
+        })
+    }));
+
+    let session = Arc::new(Session {
+        channel: channel.clone(),
+        peer,
+        peers,
+        directory,
+        transfers: Mutex::new(Transfers::default()),
+        drained,
+    });
+
+    let on_close = session.clone();
+
+    channel.on_close(Box::new(move || {
+        let session = on_close.clone();
+
+        // Partial files stay for the peer to resume.
+        Box::pin(async move {
+            *session.transfers.lock().await = Transfers::default();
+        })
+    }));
+
+    channel.on_message(Box::new(move |msg: DataChannelMessage| {
+        let session = session.clone();
+
+        Box::pin(async move {
+            let result = match msg.is_string {
+                true => match serde_json::from_slice::<TransferMessage>(&msg.data) {
+                    Ok(message) => session.handle(message.payload).await,
+                    Err(e) => Err(anyhow!("unexpected value: {e}")),
+                },
+                false => session.receive_chunk(&msg.data).await,
+            };
+
+            if let Err(e) = result {
+                warn!(
+                    "File transfer with peer '{}' failed: {e:#}",
+                    session.peer.read().await.uuid
+                );
+            }
+        })
+    }));
+}
+
+impl Session {
+    async fn handle(self: &Arc<Self>, message: Transfer) -> anyhow::Result<()> {
+        match message {
+            Transfer::Offer {
+                id,
+                name,
+                size,
+                sha256,
+            } => {
+                if let Err(e) = self.start_upload(&id, name, size, sha256).await {
+                    self.reply(Transfer::Reject {
+                        id,
+                        reason: e.to_string(),
+                    })
+                    .await?;
+                }
+            }
+            Transfer::Request { id, name } => {
+                if let Err(e) = self.offer_download(&id, &name).await {
+                    self.reply(Transfer::Reject {
+                        id,
+                        reason: e.to_string(),
+                    })
+                    .await?;
+                }
+            }
+            Transfer::Accept { id, offset } => self.start_download(parse_id(&id)?, offset).await?,
+            Transfer::Complete { id } => {
+                if let Some(download) = self.take_download(parse_id(&id)?).await {
+                    info!("Sent {} to the peer.", download.path.display());
+                }
+            }
+            Transfer::Failed { id, reason } => {
+                if let Some(download) = self.take_download(parse_id(&id)?).await {
+                    warn!("Sending {} failed: {reason}", download.path.display());
+                }
+            }
+            Transfer::Reject { id, .. } => {
+                self.take_download(parse_id(&id)?).await;
+            }
+            Transfer::Cancel { id } => {
+                let id = parse_id(&id)?;
+                let mut transfers = self.transfers.lock().await;
+
+                if transfers
+                    .upload
+                    .as_ref()
+                    .is_some_and(|upload| upload.id == id)
+                {
+                    transfers.upload = None;
+                }
+
+                if transfers
+                    .download
+                    .as_ref()
+                    .is_some_and(|download| download.id == id)
+                {
+                    transfers.download = None;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn in_control(&self) -> anyhow::Result<()> {
+        let in_control = peer_utils::fetch_peer_in_control(&self.peers)
+            .await
+            .is_some_and(|ctrl| Arc::ptr_eq(&ctrl, &self.peer));
+
+        match in_control {
+            true => Ok(()),
+            false => Err(anyhow!("the peer is not in control")),
+        }
+    }
+
+    async fn reply(&self, message: Transfer) -> anyhow::Result<()> {
+        let message = serde_json::to_string(&TransferMessage { payload: message })?;
+        self.channel.send_text(message).await?;
+        Ok(())
+    }
+
+    /// Accepts a file offered by the peer, from what a partial file of the
+    /// same digest already holds.
+    async fn start_upload(
+        &self,
+        id: &str,
+        name: String,
+        size: u64,
+        sha256: String,
+    ) -> anyhow::Result<()> {
+        self.in_control().await?;
+
+        let id = parse_id(id)?;
+
+        // The previous upload gives its room back, or the file back when the
+        // peer offers it again.
+        self.transfers.lock().await.upload = None;
+
+        let upload = self.directory.receive(id, name, size, sha256).await?;
+        let offset = upload.received;
+
+        if offset > 0 {
+            info!("Resuming {} from {offset} bytes.", upload.name);
+        }
+
+        self.transfers.lock().await.upload = Some(upload);
+
+        self.reply(Transfer::Accept {
+            id: id.to_string(),
+            offset,
+        })
+        .await?;
+
+        if offset == size {
+            self.finish_upload().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn receive_chunk(&self, data: &[u8]) -> anyhow::Result<()> {
+        if data.len() < HEADER_SIZE {
+            anyhow::bail!("the chunk is too short");
+        }
+
+        let (header, bytes) = data.split_at(HEADER_SIZE);
+        let id = Uuid::from_slice(&header[..16])?;
+        let offset = u64::from_be_bytes(header[16..].try_into()?);
+
+        let in_control = self.in_control().await;
+        let mut transfers = self.transfers.lock().await;
+
+        // Parts of a cancelled or replaced transfer may still be on their way.
+        let Some(upload) = transfers.upload.as_mut().filter(|upload| upload.id == id) else {
+            return Ok(());
+        };
+
+        let written = match in_control {
+            Ok(()) => upload.write(offset, bytes).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = written {
+            transfers.upload = None;
+            drop(transfers);
+
+            self.reply(Transfer::Failed {
+                id: id.to_string(),
+                reason: e.to_string(),
+            })
+            .await?;
+
+            return Err(e);
+        }
+
+        let complete = upload.is_complete();
+        drop(transfers);
+
+        if complete {
+            self.finish_upload().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the complete upload to its name, and tells the peer how it went.
+    async fn finish_upload(&self) -> anyhow::Result<()> {
+        let Some(upload) = self.transfers.lock().await.upload.take() else {
+            return Ok(());
+        };
+
+        let id = upload.id.to_string();
+
+        match upload.finish(&self.directory).await {
+            Ok(path) => {
+                info!("Received {} from the peer.", path.display());
+                self.reply(Transfer::Complete { id }).await
+            }
+            Err(e) => {
+                self.reply(Transfer::Failed {
+                    id,
+                    reason: e.to_string(),
+                })
+                .await?;
+
+                Err(e)
+            }
+        }
+    }
+
+    /// Offers the file the peer asked for.
+    async fn offer_download(&self, id: &str, name: &str) -> anyhow::Result<()> {
+        self.in_control().await?;
+
+        let id = parse_id(id)?;
+        let path = self.directory.existing(name).await?;
+
+        let mut file = File::open(&path).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        let mut size = 0;
+
+        loop {
+            let read = file.read(&mut buffer).await?;
+
+            if read == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..read]);
+            size += read as u64;
+        }
+
+        self.transfers.lock().await.download = Some(Download {
+            id,
+            path,
+            size,
+            task: None,
+        });
+
+        self.reply(Transfer::Offer {
+            id: id.to_string(),
+            name: name.to_owned(),
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+        .await
+    }
+
+    /// Starts sending the offered file from `offset`.
+    async fn start_download(self: &Arc<Self>, id: Uuid, offset: u64) -> anyhow::Result<()> {
+        let mut transfers = self.transfers.lock().await;
+
+        let Some(download) = transfers
+            .download
+            .as_mut()
+            .filter(|download| download.id == id && download.task.is_none())
+        else {
+            anyhow::bail!("no file was offered as '{id}'");
+        };
+
+        if offset > download.size {
+            anyhow::bail!("the offset is past the end of the file");
+        }
+
+        let session = self.clone();
+        let path = download.path.clone();
+
+        let task = tokio::spawn(async move {
+            if let Err(e) = session.send_file(id, &path, offset).await {
+                warn!("Sending {} failed: {e:#}", path.display());
+
+                let _ = session
+                    .reply(Transfer::Failed {
+                        id: id.to_string(),
+                        reason: e.to_string(),
+                    })
+                    .await;
+
+                // Aborts this very task, which has nothing left to do.
+                session.take_download(id).await;
+            }
+        });
+
+        download.task = Some(task.abort_handle());
+        Ok(())
+    }
+
+    async fn send_file(&self, id: Uuid, path: &Path, offset: u64) -> anyhow::Result<()> {
+        let mut file = File::open(path).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+        let mut offset = offset;
+        let mut chunk = vec![0; HEADER_SIZE + CHUNK_SIZE];
+        chunk[..16].copy_from_slice(id.as_bytes());
+
+        loop {
+            let read = file.read(&mut chunk[HEADER_SIZE..]).await?;
+
+            if read == 0 {
+                return Ok(());
+            }
+
+            self.in_control().await?;
+
+            while self.channel.buffered_amount().await > HIGH_WATER {
+                self.drained.notified().await;
+            }
+
+            chunk[16..HEADER_SIZE].copy_from_slice(&offset.to_be_bytes());
+            self.channel
+                .send(&chunk[..HEADER_SIZE + read].to_vec().into())
+                .await?;
+
+            offset += read as u64;
+        }
+    }
+
+    async fn take_download(&self, id: Uuid) -> Option<Download> {
+        let mut transfers = self.transfers.lock().await;
+
+        match &transfers.download {
+            Some(download) if download.id == id => transfers.download.take(),
+            _ => None,
+        }
+    }
+}
+
+fn parse_id(id: &str) -> anyhow::Result<Uuid> {
+    Uuid::parse_str(id).map_err(|_| anyhow!("'{id}' is not a valid id"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    fn directory(quota: u64) -> TransferDirectory {
+        let root = std::env::temp_dir().join(format!("landlord-{}", Uuid::new_v4()));
+
+        TransferDirectory::open(&FileTransferConfig {
+            directory: root.to_string_lossy().into_owned(),
+            max_size: quota,
+            quota,
+            partial_lifetime: 60,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn digest(byte: char) -> String {
+        byte.to_string().repeat(64)
+    }
+
+    #[test]
+    fn names_stay_within_the_directory() {
+        let directory = directory(100);
+
+        for name in [
+            "../x",
+            "/etc/passwd",
+            "a/b",
+            "a\\b",
+            ".hidden",
+            "C:x",
+            "",
+            ".",
+            "..",
+            "nul\0",
+        ] {
+            assert!(directory.path_of(name).is_err(), "{name:?}");
+        }
+
+        assert_eq!(
+            directory.path_of("report 2.pdf").unwrap(),
+            directory.root.join("report 2.pdf")
+        );
+
+        std::fs::remove_dir_all(&directory.root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn links_stay_within_the_directory() {
+        let directory = directory(100);
+        let outside = directory.root.with_extension("outside");
+
+        std::fs::write(&outside, "secret").unwrap();
+        std::fs::write(directory.root.join("inside"), "shared").unwrap();
+        std::fs::create_dir(directory.root.join("folder")).unwrap();
+        std::os::unix::fs::symlink(&outside, directory.root.join("escape")).unwrap();
+        std::os::unix::fs::symlink("inside", directory.root.join("alias")).unwrap();
+
+        assert!(directory.existing("escape").await.is_err());
+        assert!(directory.existing("folder").await.is_err());
+        assert!(directory.existing("missing").await.is_err());
+        assert!(directory.existing("../inside").await.is_err());
+        assert_eq!(
+            directory.existing("alias").await.unwrap(),
+            directory.root.join("inside")
+        );
+
+        std::fs::remove_file(&outside).unwrap();
+        std::fs::remove_dir_all(&directory.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn uploads_resume_from_whole_chunks() {
+        let directory = directory(1 << 20);
+        let content: Vec<u8> = (0..CHUNK_SIZE * 5 / 2).map(|i| i as u8).collect();
+        let sha256 = format!("{:x}", Sha256::digest(&content));
+        let (first, second) = (CHUNK_SIZE, CHUNK_SIZE * 3 / 2);
+
+        let mut upload = directory
+            .receive(Uuid::new_v4(), "data.bin".to_owned(), 0, sha256.clone())
+            .await
+            .unwrap();
+        assert!(upload.write(0, &content[..1]).await.is_err());
+        drop(upload);
+
+        let size = content.len() as u64;
+        let mut upload = directory
+            .receive(Uuid::new_v4(), "data.bin".to_owned(), size, sha256.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(upload.received, 0);
+        upload.write(0, &content[..first]).await.unwrap();
+        upload
+            .write(first as u64, &content[first..second])
+            .await
+            .unwrap();
+        assert!(upload.write(0, &content[..first]).await.is_err());
+
+        // The connection drops, and the peer offers the file again.
+        drop(upload);
+        let mut upload = directory
+            .receive(
+                Uuid::new_v4(),
+                "data.bin".to_owned(),
+                size,
+                sha256.to_uppercase(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(upload.received, first as u64);
+        upload.write(first as u64, &content[first..]).await.unwrap();
+        assert!(upload.is_complete());
+
+        let path = upload.finish(&directory).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), content);
+        assert!(!directory.partial_of(&sha256).exists());
+
+        // The name is taken from now on.
+        assert!(directory
+            .receive(Uuid::new_v4(), "data.bin".to_owned(), size, sha256)
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(&directory.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn uploads_not_matching_their_digest_are_removed() {
+        let directory = directory(100);
+        let sha256 = digest('a');
+
+        let mut upload = directory
+            .receive(Uuid::new_v4(), "data.bin".to_owned(), 4, sha256.clone())
+            .await
+            .unwrap();
+        upload.write(0, b"data").await.unwrap();
+
+        assert!(upload.finish(&directory).await.is_err());
+        assert!(!directory.partial_of(&sha256).exists());
+        assert!(!directory.root.join("data.bin").exists());
+
+        std::fs::remove_dir_all(&directory.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn claims_stay_within_the_quota() {
+        let directory = directory(100);
+        std::fs::write(directory.partial_of(&digest('a')), [0; 30]).unwrap();
+
+        let claim = directory.claim(&digest('b'), 60).await.unwrap();
+        assert!(directory.claim(&digest('c'), 20).await.is_err());
+        assert!(directory.claim(&digest('b'), 10).await.is_err());
+
+        // Resuming a partial file takes no more room than the file.
+        drop(claim);
+        assert!(directory.claim(&digest('a'), 100).await.is_ok());
+
+        std::fs::remove_dir_all(&directory.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stale_partial_files_are_removed() {
+        let directory = directory(100);
+        let stale = directory.partial_of(&digest('a'));
+        let fresh = directory.partial_of(&digest('b'));
+
+        std::fs::write(&stale, [0; 50]).unwrap();
+        std::fs::write(&fresh, [0; 50]).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(120))
+            .unwrap();
+
+        assert!(directory.claim(&digest('c'), 50).await.is_ok());
+        assert!(!stale.exists());
+        assert!(fresh.exists());
+
+        std::fs::remove_dir_all(&directory.root).unwrap();
+    }
+}
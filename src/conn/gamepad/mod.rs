@@ -0,0 +1,501 @@
+#[cfg(test)]
+mod recording;
+#[cfg(target_os = "linux")]
+mod uinput;
+
+use std::sync::Arc;
+
+use rocket::serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, RwLock};
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+
+use crate::conn::{peer_utils, AetherPeerConnection, PeerSet};
+
+/// Most pads a peer may plug in at once, as many as an Xbox takes.
+pub const MAX_PADS: usize = 4;
+
+pub const EV_KEY: u16 = 0x01;
+pub const EV_ABS: u16 = 0x03;
+
+const BTN_A: u16 = 0x130;
+const BTN_B: u16 = 0x131;
+const BTN_X: u16 = 0x133;
+const BTN_Y: u16 = 0x134;
+const BTN_TL: u16 = 0x136;
+const BTN_TR: u16 = 0x137;
+const BTN_SELECT: u16 = 0x13a;
+const BTN_START: u16 = 0x13b;
+const BTN_MODE: u16 = 0x13c;
+const BTN_THUMBL: u16 = 0x13d;
+const BTN_THUMBR: u16 = 0x13e;
+
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+
+/// Largest deflection of a stick, either way.
+const STICK_MAX: i32 = 32767;
+/// Value of a trigger pulled all the way.
+const TRIGGER_MAX: i32 = 255;
+
+/// Buttons of the standard layout of the Gamepad API, by index, with the
+/// button of an Xbox 360 pad each one is. Triggers and the d-pad are axes of
+/// the pad instead.
+const BUTTONS: [(usize, u16); 11] = [
+    (0, BTN_A),
+    (1, BTN_B),
+    (2, BTN_X),
+    (3, BTN_Y),
+    (4, BTN_TL),
+    (5, BTN_TR),
+    (8, BTN_SELECT),
+    (9, BTN_START),
+    (10, BTN_THUMBL),
+    (11, BTN_THUMBR),
+    (16, BTN_MODE),
+];
+
+/// Triggers of the standard layout, by button index, and their axis.
+const TRIGGERS: [(usize, u16); 2] = [(6, ABS_Z), (7, ABS_RZ)];
+
+/// Sticks of the standard layout, by axis index, and their axis. Vertical
+/// axes count downwards on both.
+const STICKS: [(usize, u16); 4] = [(0, ABS_X), (1, ABS_Y), (2, ABS_RX), (3, ABS_RY)];
+
+/// Up, down, left and right of the d-pad, by button index.
+const DPAD: [usize; 4] = [12, 13, 14, 15];
+
+/// An input event of a virtual pad, as uinput takes them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PadEvent {
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl PadEvent {
+    fn key(code: u16, down: bool) -> Self {
+        Self {
+            kind: EV_KEY,
+            code,
+            value: down as i32,
+        }
+    }
+
+    fn axis(code: u16, value: i32) -> Self {
+        Self {
+            kind: EV_ABS,
+            code,
+            value,
+        }
+    }
+}
+
+/// The state of a pad of the peer, as in `Gamepad`. A pad is plugged in on
+/// the host with its first state and unplugged once it is disconnected.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PadState {
+    /// Position of the pad among the pads of the peer.
+    pub index: usize,
+    #[serde(default = "connected")]
+    pub connected: bool,
+    /// Value of each button, from 0 to 1.
+    #[serde(default)]
+    pub buttons: Vec<f64>,
+    /// Value of each axis, from -1 to 1.
+    #[serde(default)]
+    pub axes: Vec<f64>,
+}
+
+fn connected() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct GamepadMessage {
+    payload: PadState,
+}
+
+impl PadState {
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice::<GamepadMessage>(data)?.payload)
+    }
+
+    fn button(&self, index: usize) -> f64 {
+        let value = self.buttons.get(index).copied().unwrap_or(0.0);
+        if value.is_finite() {
+            value.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    fn pressed(&self, index: usize) -> bool {
+        self.button(index) >= 0.5
+    }
+
+    fn axis(&self, index: usize) -> f64 {
+        let value = self.axes.get(index).copied().unwrap_or(0.0);
+        if value.is_finite() {
+            value.clamp(-1.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Every control of an Xbox 360 pad in this state, always in the same
+    /// order.
+    fn events(&self) -> Vec<PadEvent> {
+        let [up, down, left, right] = DPAD.map(|index| self.pressed(index) as i32);
+
+        BUTTONS
+            .iter()
+            .map(|&(index, code)| PadEvent::key(code, self.pressed(index)))
+            .chain(TRIGGERS.iter().map(|&(index, code)| {
+                PadEvent::axis(
+                    code,
+                    (self.button(index) * TRIGGER_MAX as f64).round() as i32,
+                )
+            }))
+            .chain(STICKS.iter().map(|&(index, code)| {
+                PadEvent::axis(code, (self.axis(index) * STICK_MAX as f64).round() as i32)
+            }))
+            .chain([
+                PadEvent::axis(ABS_HAT0X, right - left),
+                PadEvent::axis(ABS_HAT0Y, down - up),
+            ])
+            .collect()
+    }
+}
+
+/// A rumble effect played by a game on a pad, for the peer to play on its
+/// own. Stopping an effect is a rumble with no magnitude.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Rumble {
+    pub index: usize,
+    /// Magnitude of the low-frequency motor, from 0 to 1.
+    pub strong: f64,
+    /// Magnitude of the high-frequency motor, from 0 to 1.
+    pub weak: f64,
+    /// Milliseconds the effect lasts.
+    pub duration: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename = "rumble")]
+struct RumbleMessage {
+    payload: Rumble,
+}
+
+/// A virtual pad plugged in on the host, unplugged once dropped.
+pub trait PadDevice: Send + Sync {
+    /// Emits `events`, followed by the report that applies them.
+    fn emit(&mut self, events: &[PadEvent]) -> anyhow::Result<()>;
+}
+
+/// Plugs in the virtual pads of peers.
+pub trait PadBackend: Send + Sync {
+    /// Plugs in pad `index` of a peer, whose rumble effects go to `rumble`.
+    fn plug(
+        &self,
+        index: usize,
+        rumble: mpsc::UnboundedSender<Rumble>,
+    ) -> anyhow::Result<Box<dyn PadDevice>>;
+}
+
+/// The backend of the host, or why it has none.
+pub fn open() -> anyhow::Result<Arc<dyn PadBackend>> {
+    #[cfg(target_os = "linux")]
+    return Ok(Arc::new(uinput::UinputPads));
+
+    #[cfg(not(target_os = "linux"))]
+    anyhow::bail!("virtual gamepads need Linux")
+}
+
+struct Pad {
+    device: Box<dyn PadDevice>,
+    /// Every control as last emitted.
+    state: Vec<PadEvent>,
+}
+
+/// The pads of one peer: those it plugged in on the host, and the rumble
+/// effects games play on them.
+pub struct Gamepads {
+    backend: Option<Arc<dyn PadBackend>>,
+    pads: Vec<Option<Pad>>,
+    rumble: mpsc::UnboundedSender<Rumble>,
+    /// Until the `gamepad_events` channel of the peer takes them.
+    feedback: Option<mpsc::UnboundedReceiver<Rumble>>,
+}
+
+impl Gamepads {
+    /// Pads plugged in through `backend`, none when gamepads are disabled or
+    /// unavailable.
+    pub fn new(backend: Option<Arc<dyn PadBackend>>) -> Self {
+        let (rumble, feedback) = mpsc::unbounded_channel();
+
+        Self {
+            backend,
+            pads: (0..MAX_PADS).map(|_| None).collect(),
+            rumble,
+            feedback: Some(feedback),
+        }
+    }
+
+    pub fn apply(&mut self, state: PadState) {
+        let Some(slot) = self.pads.get_mut(state.index) else {
+            warn!(
+                "Ignoring pad {}, only {MAX_PADS} are supported.",
+                state.index
+            );
+            return;
+        };
+
+        if !state.connected {
+            *slot = None;
+            return;
+        }
+
+        if slot.is_none() {
+            let Some(backend) = &self.backend else {
+                return;
+            };
+
+            match backend.plug(state.index, self.rumble.clone()) {
+                Ok(device) => {
+                    slot.replace(Pad {
+                        device,
+                        state: PadState::default().events(),
+                    });
+                }
+                Err(e) => {
+                    warn!("Virtual gamepads are unavailable: {e:#}");
+                    // Rather than trying again with every state.
+                    self.backend = None;
+                    return;
+                }
+            }
+        }
+
+        let Some(pad) = slot else {
+            return;
+        };
+
+        let events = state.events();
+        let changed: Vec<PadEvent> = events
+            .iter()
+            .zip(&pad.state)
+            .filter(|(event, last)| event != last)
+            .map(|(event, _)| *event)
+            .collect();
+
+        if changed.is_empty() {
+            return;
+        }
+
+        match pad.device.emit(&changed) {
+            Ok(()) => pad.state = events,
+            Err(e) => {
+                warn!("Unplugging pad {}: {e:#}", state.index);
+                *slot = None;
+            }
+        }
+    }
+
+    /// Unplugs every pad, once the peer loses control.
+    pub fn release_all(&mut self) {
+        self.pads.iter_mut().for_each(|pad| *pad = None);
+    }
+
+    /// The rumble effects played on the pads, taken once.
+    fn take_feedback(&mut self) -> Option<mpsc::UnboundedReceiver<Rumble>> {
+        self.feedback.take()
+    }
+}
+
+/// Serves the `gamepad_events` data channel of `peer`: the state of its pads
+/// goes to virtual pads of the host while it is in control, and rumble
+/// effects played on them go back to it.
+pub(super) fn serve(
+    channel: Arc<RTCDataChannel>,
+    peer: Arc<RwLock<AetherPeerConnection>>,
+    peers: PeerSet,
+) {
+    let feedback_channel = channel.clone();
+    let feedback_peer = peer.clone();
+
+    tokio::spawn(async move {
+        let Some(feedback) = feedback_peer.write().await.gamepads.take_feedback() else {
+            return;
+        };
+
+        send_rumble(feedback_channel, feedback).await;
+    });
+
+    channel.on_message(Box::new(move |msg: DataChannelMessage| {
+        let state = PadState::parse(&msg.data);
+
+        let peer = peer.clone();
+        let peers = peers.clone();
+
+        Box::pin(async move {
+            let state = match state {
+                Ok(state) => state,
+                Err(e) => {
+                    error!("Unexpected value in the gamepad events data channel: {e}");
+                    return;
+                }
+            };
+
+            let in_control = peer_utils::fetch_peer_in_control(&peers)
+                .await
+                .is_some_and(|ctrl| Arc::ptr_eq(&ctrl, &peer));
+
+            let mut peer = peer.write().await;
+
+            if in_control {
+                peer.gamepads.apply(state);
+            } else {
+                debug!(
+                    "Ignoring gamepad input of peer '{}', which is not in control.",
+                    peer.uuid
+                );
+            }
+        })
+    }));
+}
+
+/// Sends rumble effects to the peer until its pads are gone or its channel
+/// closes.
+async fn send_rumble(channel: Arc<RTCDataChannel>, mut feedback: mpsc::UnboundedReceiver<Rumble>) {
+    while let Some(rumble) = feedback.recv().await {
+        let Ok(message) = serde_json::to_string(&RumbleMessage { payload: rumble }) else {
+            continue;
+        };
+
+        if channel.send_text(message).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::recording::{PadLog, RecordingPads};
+    use super::*;
+
+    fn state(index: usize, buttons: &[f64], axes: &[f64]) -> PadState {
+        PadState {
+            index,
+            connected: true,
+            buttons: buttons.to_vec(),
+            axes: axes.to_vec(),
+        }
+    }
+
+    fn gamepads() -> (Gamepads, RecordingPads) {
+        let pads = RecordingPads::new();
+        (Gamepads::new(Some(Arc::new(pads.clone()))), pads)
+    }
+
+    #[test]
+    fn emits_only_changed_controls() {
+        let (mut gamepads, pads) = gamepads();
+
+        gamepads.apply(state(1, &[1.0], &[]));
+        gamepads.apply(state(1, &[1.0], &[]));
+        gamepads.apply(state(1, &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.5], &[0.5]));
+        gamepads.apply(state(1, &[], &[]));
+
+        assert_eq!(
+            pads.take(),
+            [
+                PadLog::Plugged(1),
+                PadLog::Event(1, PadEvent::key(BTN_A, true)),
+                PadLog::Event(1, PadEvent::axis(ABS_Z, 128)),
+                PadLog::Event(1, PadEvent::axis(ABS_X, 16384)),
+                PadLog::Event(1, PadEvent::key(BTN_A, false)),
+                PadLog::Event(1, PadEvent::axis(ABS_Z, 0)),
+                PadLog::Event(1, PadEvent::axis(ABS_X, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unplugs_disconnected_pads() {
+        let (mut gamepads, pads) = gamepads();
+
+        gamepads.apply(state(0, &[], &[]));
+        gamepads.apply(PadState {
+            connected: false,
+            ..state(0, &[], &[])
+        });
+        // Plugged back in, without anything to emit.
+        gamepads.apply(state(0, &[], &[]));
+
+        assert_eq!(
+            pads.take(),
+            [PadLog::Plugged(0), PadLog::Unplugged(0), PadLog::Plugged(0),]
+        );
+    }
+
+    #[test]
+    fn unplugs_every_pad_once_control_is_lost() {
+        let (mut gamepads, pads) = gamepads();
+
+        gamepads.apply(state(0, &[], &[]));
+        gamepads.apply(state(3, &[], &[]));
+        gamepads.release_all();
+
+        assert_eq!(
+            pads.take(),
+            [
+                PadLog::Plugged(0),
+                PadLog::Plugged(3),
+                PadLog::Unplugged(0),
+                PadLog::Unplugged(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_pads_past_the_last() {
+        let (mut gamepads, pads) = gamepads();
+
+        gamepads.apply(state(MAX_PADS, &[1.0], &[]));
+        gamepads.apply(state(usize::MAX, &[1.0], &[]));
+
+        assert_eq!(pads.take(), []);
+    }
+
+    #[test]
+    fn relays_rumble_of_plugged_pads() {
+        let (mut gamepads, pads) = gamepads();
+        let mut feedback = gamepads.take_feedback().unwrap();
+
+        let rumble = Rumble {
+            index: 2,
+            strong: 1.0,
+            weak: 0.5,
+            duration: 200,
+        };
+
+        assert!(!pads.rumble(rumble));
+
+        gamepads.apply(state(2, &[], &[]));
+        assert!(pads.rumble(rumble));
+        assert_eq!(feedback.try_recv().unwrap(), rumble);
+
+        gamepads.release_all();
+        assert!(!pads.rumble(rumble));
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+
+use super::{PadBackend, PadDevice, PadEvent, Rumble, MAX_PADS};
+
+/// What happened to the pads of a [`RecordingPads`], in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PadLog {
+    Plugged(usize),
+    Event(usize, PadEvent),
+    Unplugged(usize),
+}
+
+#[derive(Default)]
+struct Shared {
+    log: Vec<PadLog>,
+    /// Where the rumble of each plugged pad goes.
+    rumble: Vec<Option<mpsc::UnboundedSender<Rumble>>>,
+}
+
+/// Keeps events in memory instead of writing them to uinput.
+struct RecordingPad {
+    index: usize,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl PadDevice for RecordingPad {
+    fn emit(&mut self, events: &[PadEvent]) -> anyhow::Result<()> {
+        let mut shared = self
+            .shared
+            .lock()
+            .map_err(|_| anyhow::anyhow!("the recording is poisoned"))?;
+
+        for event in events {
+            shared.log.push(PadLog::Event(self.index, *event));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for RecordingPad {
+    fn drop(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.log.push(PadLog::Unplugged(self.index));
+            shared.rumble[self.index] = None;
+        }
+    }
+}
+
+/// A [`PadBackend`] whose pads are recorded rather than plugged in, for tests
+/// of the gamepads and their data channel on hosts without uinput.
+#[derive(Clone)]
+pub struct RecordingPads(Arc<Mutex<Shared>>);
+
+impl RecordingPads {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Shared {
+            log: vec![],
+            rumble: vec![None; MAX_PADS],
+        })))
+    }
+
+    /// Takes what happened to the pads so far.
+    pub fn take(&self) -> Vec<PadLog> {
+        std::mem::take(&mut self.0.lock().unwrap().log)
+    }
+
+    /// Plays `rumble` on its pad as a game would, if it is plugged in.
+    pub fn rumble(&self, rumble: Rumble) -> bool {
+        let shared = self.0.lock().unwrap();
+
+        shared
+            .rumble
+            .get(rumble.index)
+            .and_then(Option::as_ref)
+            .is_some_and(|sender| sender.send(rumble).is_ok())
+    }
+}
+
+impl PadBackend for RecordingPads {
+    fn plug(
+        &self,
+        index: usize,
+        rumble: mpsc::UnboundedSender<Rumble>,
+    ) -> anyhow::Result<Box<dyn PadDevice>> {
+        let mut shared = self
+            .0
+            .lock()
+            .map_err(|_| anyhow::anyhow!("the recording is poisoned"))?;
+
+        shared.log.push(PadLog::Plugged(index));
+        shared.rumble[index] = Some(rumble);
+
+        Ok(Box::new(RecordingPad {
+            index,
+            shared: self.0.clone(),
+        }))
+    }
+}
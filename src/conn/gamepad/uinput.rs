@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context};
+use evdev::uinput::{VirtualDevice, VirtualEventStream};
+use evdev::{
+    AbsInfo, AbsoluteAxisCode, AttributeSet, BusType, EventSummary, FFEffectCode, FFEffectKind,
+    InputEvent, InputId, KeyCode, UInputCode, UinputAbsSetup,
+};
+use tokio::sync::mpsc;
+
+use super::{
+    PadBackend, PadDevice, PadEvent, Rumble, ABS_HAT0X, ABS_HAT0Y, BUTTONS, STICKS, STICK_MAX,
+    TRIGGERS, TRIGGER_MAX,
+};
+
+/// Identity of a wired Xbox 360 pad, which games and SDL know the layout of.
+const XBOX_360: (u16, u16, u16) = (0x045e, 0x028e, 0x0110);
+
+/// Rumble effects a game may upload to a pad at once.
+const MAX_EFFECTS: u32 = 16;
+
+/// Plugs in pads through virtual devices of the kernel, posing as Xbox 360
+/// pads. The landlord needs write access to `/dev/uinput`.
+pub struct UinputPads;
+
+impl PadBackend for UinputPads {
+    fn plug(
+        &self,
+        index: usize,
+        rumble: mpsc::UnboundedSender<Rumble>,
+    ) -> anyhow::Result<Box<dyn PadDevice>> {
+        let keys: AttributeSet<KeyCode> = BUTTONS.iter().map(|&(_, code)| KeyCode(code)).collect();
+        let effects: AttributeSet<FFEffectCode> = [FFEffectCode::FF_RUMBLE].into_iter().collect();
+
+        let axis = |code, min, max, fuzz, flat| {
+            UinputAbsSetup::new(
+                AbsoluteAxisCode(code),
+                AbsInfo::new(0, min, max, fuzz, flat, 0),
+            )
+        };
+
+        let (vendor, product, version) = XBOX_360;
+        let name = format!("Landlord virtual pad {}", index + 1);
+
+        let mut builder = VirtualDevice::builder()
+            .context("Unable to open /dev/uinput")?
+            .name(&name)
+            .input_id(InputId::new(BusType::BUS_USB, vendor, product, version))
+            .with_keys(&keys)?
+            .with_ff(&effects)?
+            .with_ff_effects_max(MAX_EFFECTS);
+
+        for &(_, code) in &STICKS {
+            builder =
+                builder.with_absolute_axis(&axis(code, -STICK_MAX - 1, STICK_MAX, 16, 128))?;
+        }
+
+        for &(_, code) in &TRIGGERS {
+            builder = builder.with_absolute_axis(&axis(code, 0, TRIGGER_MAX, 0, 0))?;
+        }
+
+        for code in [ABS_HAT0X, ABS_HAT0Y] {
+            builder = builder.with_absolute_axis(&axis(code, -1, 1, 0, 0))?;
+        }
+
+        let stream = builder.build()?.into_event_stream()?;
+        let (events, incoming) = mpsc::unbounded_channel();
+
+        tokio::spawn(run(stream, incoming, index, rumble));
+
+        Ok(Box::new(UinputPad(events)))
+    }
+}
+
+/// A pad whose device is owned by the task of [`run`], which unplugs it once
+/// the pad is dropped.
+struct UinputPad(mpsc::UnboundedSender<Vec<PadEvent>>);
+
+impl PadDevice for UinputPad {
+    fn emit(&mut self, events: &[PadEvent]) -> anyhow::Result<()> {
+        self.0
+            .send(events.to_vec())
+            .map_err(|_| anyhow!("the virtual pad is gone"))
+    }
+}
+
+enum Wake {
+    Events(Vec<PadEvent>),
+    Kernel(InputEvent),
+}
+
+/// Writes the events of pad `index` to its device, and reads back the rumble
+/// effects games upload and play on it.
+async fn run(
+    mut stream: VirtualEventStream,
+    mut incoming: mpsc::UnboundedReceiver<Vec<PadEvent>>,
+    index: usize,
+    rumble: mpsc::UnboundedSender<Rumble>,
+) {
+    // Strong and weak magnitudes, and length, of each uploaded effect.
+    let mut effects = HashMap::<u16, (u16, u16, u16)>::new();
+
+    loop {
+        let wake = tokio::select! {
+            events = incoming.recv() => match events {
+                Some(events) => Wake::Events(events),
+                None => break,
+            },
+            event = stream.next_event() => match event {
+                Ok(event) => Wake::Kernel(event),
+                Err(e) => {
+                    warn!("Unplugging pad {index}: {e}");
+                    break;
+                }
+            },
+        };
+
+        let result = match wake {
+            Wake::Events(events) => {
+                let events: Vec<InputEvent> = events
+                    .iter()
+                    .map(|event| InputEvent::new(event.kind, event.code, event.value))
+                    .collect();
+
+                stream.device_mut().emit(&events)
+            }
+            Wake::Kernel(event) => handle(&mut stream, event, index, &mut effects, &rumble),
+        };
+
+        if let Err(e) = result {
+            warn!("Unplugging pad {index}: {e}");
+            break;
+        }
+    }
+}
+
+/// Keeps track of the effects a game uploads, and turns those it plays or
+/// stops into rumbles.
+fn handle(
+    stream: &mut VirtualEventStream,
+    event: InputEvent,
+    index: usize,
+    effects: &mut HashMap<u16, (u16, u16, u16)>,
+    rumble: &mpsc::UnboundedSender<Rumble>,
+) -> std::io::Result<()> {
+    match event.destructure() {
+        EventSummary::UInput(event, UInputCode::UI_FF_UPLOAD, _) => {
+            let upload = stream.device_mut().process_ff_upload(event)?;
+            let effect = upload.effect();
+
+            if let FFEffectKind::Rumble {
+                strong_magnitude,
+                weak_magnitude,
+            } = effect.kind
+            {
+                effects.insert(
+                    upload.effect_id() as u16,
+                    (strong_magnitude, weak_magnitude, effect.replay.length),
+                );
+            }
+        }
+        EventSummary::UInput(event, UInputCode::UI_FF_ERASE, _) => {
+            let erase = stream.device_mut().process_ff_erase(event)?;
+            effects.remove(&(erase.effect_id() as u16));
+        }
+        EventSummary::ForceFeedback(_, code, playing) => {
+            let Some(&(strong, weak, length)) = effects.get(&code.0) else {
+                return Ok(());
+            };
+
+            let magnitude = |value: u16| match playing > 0 {
+                true => value as f64 / u16::MAX as f64,
+                false => 0.0,
+            };
+
+            let _ = rumble.send(Rumble {
+                index,
+                strong: magnitude(strong),
+                weak: magnitude(weak),
+                duration: if playing > 0 { length as u64 } else { 0 },
+            });
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use rocket::fairing::AdHoc;
+
+use crate::config::LandlordConfig;
+use crate::conn::clipboard::{HostClipboard, SystemClipboard};
+use crate::conn::display::Display;
+use crate::conn::file_transfer::TransferDirectory;
+use crate::conn::gamepad::{self, PadBackend};
+use crate::conn::input::InputHandle;
+
+/// What the peer in control gets to use on the host, shared by every
+/// connection manager.
+#[derive(Clone)]
+pub struct Host {
+    pub input: InputHandle,
+    /// Unless clipboard sharing is disabled or unavailable.
+    pub clipboard: Option<HostClipboard>,
+    /// Unless file transfer is disabled or its directory unusable.
+    pub files: Option<TransferDirectory>,
+    /// Unless virtual gamepads are disabled or unavailable.
+    pub gamepads: Option<Arc<dyn PadBackend>>,
+}
+
+/// Probes the [`Display`], then opens the input, the clipboard, the transfer
+/// directory and the gamepads of the host and manages them as a [`Host`].
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Host access", |rocket| async {
+        let config = rocket
+            .state::<LandlordConfig>()
+            .cloned()
+            .expect("The landlord configuration must be attached first.");
+
+        let display = Display::start(&config).await;
+
+        let clipboard = match config.clipboard.enabled {
+            true => match SystemClipboard::open() {
+                Ok(backend) => Some(HostClipboard::start(
+                    Box::new(backend),
+                    config.clipboard.clone(),
+                )),
+                Err(e) => {
+                    warn!("Clipboard sharing is unavailable: {e}");
+                    None
+                }
+            },
+            false => None,
+        };
+
+        let files = match config.file_transfer.enabled {
+            true => match TransferDirectory::open(&config.file_transfer) {
+                Ok(directory) => Some(directory),
+                Err(e) => {
+                    warn!("File transfer is unavailable: {e:#}");
+                    None
+                }
+            },
+            false => None,
+        };
+
+        let gamepads = match config.gamepad.enabled {
+            true => match gamepad::open() {
+                Ok(backend) => Some(backend),
+                Err(e) => {
+                    warn!("Virtual gamepads are unavailable: {e}");
+                    None
+                }
+            },
+            false => None,
+        };
+
+        rocket.manage(Host {
+            input: InputHandle::from_config(&config, display),
+            clipboard,
+            files,
+            gamepads,
+        })
+    })
+}
@@ -0,0 +1,829 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+
+use rocket::serde::Deserialize;
+
+use crate::config::{InputBackend, LandlordConfig, RelativeMotion};
+use crate::conn::display::Display;
+use crate::conn::keyboard::Key;
+
+#[cfg(any(feature = "xdo", not(target_os = "linux")))]
+mod mouse;
+#[cfg(test)]
+pub(crate) mod recording;
+mod touch;
+#[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(target_os = "linux")]
+mod xtest;
+
+pub use touch::{PenState, TouchContact, TouchPoint};
+
+/// Wheel travel of one notch, in the pixels reported by browsers.
+const PIXELS_PER_NOTCH: f64 = 100.0;
+/// Lines scrolled by one wheel notch.
+const LINES_PER_NOTCH: f64 = 3.0;
+/// Most notches one wheel message turns either way, as backends click once
+/// per notch on the input thread every peer shares.
+const MAX_NOTCHES: f64 = 100.0;
+
+/// A point of the shared screen, relative to its size.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Position {
+    pub x_ratio: f64,
+    pub y_ratio: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum MouseButton {
+    #[default]
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+/// Unit of the wheel deltas, as in `WheelEvent.deltaMode`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum DeltaMode {
+    #[default]
+    Pixel,
+    Line,
+}
+
+/// How a peer points: at positions of the screen, or by moving the pointer
+/// as a locked pointer of the browser does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum PointerMode {
+    #[default]
+    Absolute,
+    Relative,
+}
+
+/// What a peer does with its pointer. Buttons default to `left` and `at`
+/// moves the pointer before acting, in absolute mode only.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "action", rename_all = "snake_case")]
+pub enum MouseAction {
+    Move {
+        at: Position,
+    },
+    Down {
+        #[serde(default)]
+        button: MouseButton,
+        at: Option<Position>,
+    },
+    Up {
+        #[serde(default)]
+        button: MouseButton,
+        at: Option<Position>,
+    },
+    Click {
+        #[serde(default)]
+        button: MouseButton,
+        at: Option<Position>,
+    },
+    DoubleClick {
+        #[serde(default)]
+        button: MouseButton,
+        at: Option<Position>,
+    },
+    /// Presses `button` at `from`, moves to `to` and releases it there.
+    Drag {
+        #[serde(default)]
+        button: MouseButton,
+        from: Position,
+        to: Position,
+    },
+    /// Scrolls by the deltas of a `WheelEvent`, positive values going right
+    /// and down.
+    Wheel {
+        #[serde(default)]
+        delta_x: f64,
+        #[serde(default)]
+        delta_y: f64,
+        #[serde(default)]
+        delta_mode: DeltaMode,
+        at: Option<Position>,
+    },
+    /// Switches to `mode` from then on.
+    Mode {
+        mode: PointerMode,
+    },
+    /// Moves the pointer by `dx`, `dy` pixels, as in `MouseEvent.movementX`
+    /// and `movementY`. Only in relative mode.
+    MoveBy {
+        dx: f64,
+        dy: f64,
+    },
+    /// A finger or pen lands, moves or lifts.
+    Touch(TouchPoint),
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", untagged)]
+enum MousePayload {
+    Action(MouseAction),
+    /// The original message, a left click.
+    ClickedAt {
+        clicked_at: Position,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct MouseMessage {
+    payload: MousePayload,
+}
+
+impl MouseAction {
+    /// Reads a message of the `mouse_events` data channel.
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        Ok(
+            match serde_json::from_slice::<MouseMessage>(data)?.payload {
+                MousePayload::Action(action) => action,
+                MousePayload::ClickedAt { clicked_at } => MouseAction::Click {
+                    button: MouseButton::Left,
+                    at: Some(clicked_at),
+                },
+            },
+        )
+    }
+
+    /// Whether the action hands control to a peer when nobody has it.
+    /// Hovering, releasing a button or switching modes does not, and touch
+    /// only does when a finger or pen goes down.
+    pub fn claims_control(&self) -> bool {
+        match self {
+            MouseAction::Touch(point) => point.phase == touch::TouchPhase::Down,
+            action => !matches!(
+                action,
+                MouseAction::Move { .. }
+                    | MouseAction::MoveBy { .. }
+                    | MouseAction::Mode { .. }
+                    | MouseAction::Up { .. }
+            ),
+        }
+    }
+}
+
+/// The pointer of one peer: the buttons it holds down, the wheel travel and
+/// motion not worth a notch or a pixel yet, and its fingers and pen.
+pub struct Pointer {
+    input: InputHandle,
+    mode: PointerMode,
+    held: Vec<MouseButton>,
+    wheel: (f64, f64),
+    motion: (f64, f64),
+    /// Where the pointer was last moved to, for exact relative motion.
+    position: Option<(i32, i32)>,
+    touches: touch::Touches,
+}
+
+impl Pointer {
+    pub fn new(input: InputHandle) -> Self {
+        Self {
+            input,
+            mode: PointerMode::Absolute,
+            held: vec![],
+            wheel: (0.0, 0.0),
+            motion: (0.0, 0.0),
+            position: None,
+            touches: touch::Touches::default(),
+        }
+    }
+
+    pub fn apply(&mut self, action: MouseAction) {
+        match action {
+            MouseAction::Move { at } => self.move_near(Some(at)),
+            MouseAction::Down { button, at } => {
+                self.move_near(at);
+                self.press(button);
+            }
+            MouseAction::Up { button, at } => {
+                self.move_near(at);
+                self.release(button);
+            }
+            MouseAction::Click { button, at } => {
+                self.move_near(at);
+                self.click(button);
+            }
+            MouseAction::DoubleClick { button, at } => {
+                self.move_near(at);
+                self.click(button);
+                self.click(button);
+            }
+            MouseAction::Drag { button, from, to } => {
+                self.move_near(Some(from));
+                self.press(button);
+                self.move_near(Some(to));
+                self.release(button);
+            }
+            MouseAction::Wheel {
+                delta_x,
+                delta_y,
+                delta_mode,
+                at,
+            } => {
+                self.move_near(at);
+                self.scroll(delta_x, delta_y, delta_mode);
+            }
+            MouseAction::Mode { mode } => {
+                self.mode = mode;
+                self.motion = (0.0, 0.0);
+            }
+            MouseAction::MoveBy { dx, dy } => self.move_by(dx, dy),
+            MouseAction::Touch(point) => self.touch(point),
+        }
+    }
+
+    /// Lets go of every button held down and lifts every finger, once the
+    /// peer loses control.
+    pub fn release_all(&mut self) {
+        for button in std::mem::take(&mut self.held) {
+            self.input.send(InputOp::Release(button));
+        }
+
+        self.lift_all();
+
+        self.wheel = (0.0, 0.0);
+    }
+
+    /// Moves to `at`, unless in relative mode, where positions are stale:
+    /// the locked pointer of the browser does not move.
+    fn move_near(&mut self, at: Option<Position>) {
+        if let Some(at) = at.filter(|_| self.mode == PointerMode::Absolute) {
+            let (x, y) = self.input.display().mapper().map(at);
+            self.position = Some((x, y));
+            self.input.send(InputOp::MoveTo(x, y));
+        }
+    }
+
+    fn move_by(&mut self, dx: f64, dy: f64) {
+        if self.mode != PointerMode::Relative || !dx.is_finite() || !dy.is_finite() {
+            return;
+        }
+
+        // No move spans more than the desktop, whatever the peer sends.
+        let desktop = self.input.display().desktop();
+        let (width, height) = (desktop.width as f64, desktop.height as f64);
+        let (x, y) = (
+            (self.motion.0 + dx).clamp(-width, width),
+            (self.motion.1 + dy).clamp(-height, height),
+        );
+        self.motion = (x.fract(), y.fract());
+
+        let (dx, dy) = (x.trunc() as i32, y.trunc() as i32);
+
+        if dx == 0 && dy == 0 {
+            return;
+        }
+
+        match self.input.relative_motion() {
+            RelativeMotion::Native => {
+                // The host pointer may go anywhere from now on.
+                self.position = None;
+                self.input.send(InputOp::MoveBy(dx, dy));
+            }
+            RelativeMotion::Exact => {
+                let region = self.input.display().region();
+                let (right, bottom) = (
+                    region.x + region.width as i32 - 1,
+                    region.y + region.height as i32 - 1,
+                );
+
+                // Starting from the middle when the pointer was never moved.
+                let (x, y) = self.position.unwrap_or((
+                    region.x + region.width as i32 / 2,
+                    region.y + region.height as i32 / 2,
+                ));
+
+                let to = (
+                    x.saturating_add(dx).clamp(region.x, right),
+                    y.saturating_add(dy).clamp(region.y, bottom),
+                );
+
+                self.position = Some(to);
+                self.input.send(InputOp::MoveTo(to.0, to.1));
+            }
+        }
+    }
+
+    fn press(&mut self, button: MouseButton) {
+        if !self.held.contains(&button) {
+            self.held.push(button);
+        }
+
+        self.input.send(InputOp::Press(button));
+    }
+
+    fn release(&mut self, button: MouseButton) {
+        self.held.retain(|held| *held != button);
+        self.input.send(InputOp::Release(button));
+    }
+
+    fn click(&mut self, button: MouseButton) {
+        self.press(button);
+        self.release(button);
+    }
+
+    fn scroll(&mut self, delta_x: f64, delta_y: f64, mode: DeltaMode) {
+        if !delta_x.is_finite() || !delta_y.is_finite() {
+            return;
+        }
+
+        let per_notch = match mode {
+            DeltaMode::Pixel => PIXELS_PER_NOTCH,
+            DeltaMode::Line => LINES_PER_NOTCH,
+        };
+
+        let (x, y) = (
+            (self.wheel.0 + delta_x / per_notch).clamp(-MAX_NOTCHES, MAX_NOTCHES),
+            (self.wheel.1 + delta_y / per_notch).clamp(-MAX_NOTCHES, MAX_NOTCHES),
+        );
+
+        // Smooth scrolling sends small deltas, keep what is left for later.
+        self.wheel = (x.fract(), y.fract());
+
+        let (horizontal, vertical) = (x.trunc() as i32, y.trunc() as i32);
+
+        if horizontal != 0 || vertical != 0 {
+            self.input.send(InputOp::Scroll {
+                horizontal,
+                vertical,
+            });
+        }
+    }
+}
+
+/// What the peers ask of the host, carried out in order by the injector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputOp {
+    MoveTo(i32, i32),
+    MoveBy(i32, i32),
+    Press(MouseButton),
+    Release(MouseButton),
+    /// Wheel notches, positive values going right and down.
+    Scroll {
+        horizontal: i32,
+        vertical: i32,
+    },
+    KeyDown(Key),
+    KeyUp(Key),
+    /// Puts the finger of `slot` of the touchscreen down, or lifts it.
+    Touch {
+        slot: usize,
+        contact: Option<TouchContact>,
+    },
+    /// Brings the pen in range, or takes it away.
+    Pen(Option<PenState>),
+}
+
+/// A way of injecting input on the host, picked by `input.backend`.
+///
+/// Injectors are opened and used on the input thread only, so they need not
+/// be `Send`: mouse-rs and libxdo handles cannot leave the thread that opened
+/// them.
+pub trait InputInjector {
+    /// Moves the pointer to the pixel `x`, `y` of the screen.
+    fn move_to(&mut self, x: i32, y: i32) -> anyhow::Result<()>;
+
+    /// Moves the pointer by `dx`, `dy` pixels, as a mouse of the host would.
+    fn move_by(&mut self, dx: i32, dy: i32) -> anyhow::Result<()>;
+
+    fn press(&mut self, button: MouseButton) -> anyhow::Result<()>;
+
+    fn release(&mut self, button: MouseButton) -> anyhow::Result<()>;
+
+    /// Turns the wheels by whole notches, positive values going right and
+    /// down.
+    fn scroll(&mut self, horizontal: i32, vertical: i32) -> anyhow::Result<()>;
+
+    fn key_down(&mut self, key: Key) -> anyhow::Result<()>;
+
+    fn key_up(&mut self, key: Key) -> anyhow::Result<()>;
+
+    /// Whether the injector has a touchscreen and a pen. Without them, touch
+    /// is turned into mouse gestures and never reaches the injector.
+    fn supports_touch(&self) -> bool {
+        false
+    }
+
+    fn touch(&mut self, _slot: usize, _contact: Option<TouchContact>) -> anyhow::Result<()> {
+        anyhow::bail!("this input backend has no touchscreen")
+    }
+
+    fn pen(&mut self, _pen: Option<PenState>) -> anyhow::Result<()> {
+        anyhow::bail!("this input backend has no pen")
+    }
+
+    fn perform(&mut self, op: InputOp) -> anyhow::Result<()> {
+        match op {
+            InputOp::MoveTo(x, y) => self.move_to(x, y),
+            InputOp::MoveBy(dx, dy) => self.move_by(dx, dy),
+            InputOp::Press(button) => self.press(button),
+            InputOp::Release(button) => self.release(button),
+            InputOp::Scroll {
+                horizontal,
+                vertical,
+            } => self.scroll(horizontal, vertical),
+            InputOp::KeyDown(key) => self.key_down(key),
+            InputOp::KeyUp(key) => self.key_up(key),
+            InputOp::Touch { slot, contact } => self.touch(slot, contact),
+            InputOp::Pen(pen) => self.pen(pen),
+        }
+    }
+}
+
+enum Command {
+    Perform(InputOp),
+    /// Answers once every command sent before is done.
+    #[cfg(test)]
+    Flush(mpsc::Sender<()>),
+}
+
+/// Sends input to the thread owning the [`InputInjector`]. Every connection
+/// manager shares the same handle, which keeps the input of all peers in
+/// order.
+#[derive(Clone)]
+pub struct InputHandle {
+    sender: mpsc::Sender<Command>,
+    display: Display,
+    relative_motion: RelativeMotion,
+    /// Set once the injector is open, if it supports touch.
+    touch: Arc<AtomicBool>,
+}
+
+impl InputHandle {
+    /// Starts the input thread, on which `open` creates the injector. Input
+    /// lands on `display`.
+    pub fn spawn<F>(display: Display, open: F) -> Self
+    where
+        F: FnOnce() -> anyhow::Result<Box<dyn InputInjector>> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let touch = Arc::new(AtomicBool::new(false));
+        let supports_touch = touch.clone();
+
+        std::thread::Builder::new()
+            .name("input".to_owned())
+            .spawn(move || {
+                let mut injector = match open() {
+                    Ok(injector) => injector,
+                    Err(e) => {
+                        error!("Unable to set up input injection, input will be ignored: {e:#}");
+                        return;
+                    }
+                };
+
+                supports_touch.store(injector.supports_touch(), Ordering::Relaxed);
+
+                for command in rx {
+                    match command {
+                        Command::Perform(op) => {
+                            if let Err(e) = injector.perform(op) {
+                                warn!("Unable to inject input: {e:#}");
+                            }
+                        }
+                        #[cfg(test)]
+                        Command::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            })
+            .expect("Unable to start the input thread.");
+
+        Self {
+            sender: tx,
+            display,
+            relative_motion: RelativeMotion::default(),
+            touch,
+        }
+    }
+
+    /// Starts the backend chosen in `input.backend`.
+    pub fn from_config(config: &LandlordConfig, display: Display) -> Self {
+        let handle = match config.input.backend {
+            #[cfg(any(feature = "xdo", not(target_os = "linux")))]
+            InputBackend::MouseRs => {
+                Self::spawn(display, || Ok(Box::new(mouse::MouseRsInjector::new())))
+            }
+            #[cfg(all(not(feature = "xdo"), target_os = "linux"))]
+            InputBackend::MouseRs => Self::spawn(display, || {
+                anyhow::bail!("the mouse-rs input backend needs the `xdo` feature on Linux")
+            }),
+            #[cfg(target_os = "linux")]
+            InputBackend::Uinput => {
+                let desktop = display.clone();
+                Self::spawn(display, move || {
+                    Ok(Box::new(uinput::UinputInjector::new(desktop)?))
+                })
+            }
+            #[cfg(target_os = "linux")]
+            InputBackend::Xtest => {
+                Self::spawn(display, || Ok(Box::new(xtest::XTestInjector::new()?)))
+            }
+            #[cfg(not(target_os = "linux"))]
+            backend => Self::spawn(display, move || {
+                anyhow::bail!("the {backend:?} input backend needs Linux")
+            }),
+        };
+
+        Self {
+            relative_motion: config.input.relative_motion,
+            ..handle
+        }
+    }
+
+    /// The display input lands on.
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn relative_motion(&self) -> RelativeMotion {
+        self.relative_motion
+    }
+
+    /// Whether touch reaches the host as is, rather than as mouse gestures.
+    pub fn supports_touch(&self) -> bool {
+        self.touch.load(Ordering::Relaxed)
+    }
+
+    pub fn send(&self, op: InputOp) {
+        // Input is dropped when the injector could not be opened.
+        let _ = self.sender.send(Command::Perform(op));
+    }
+
+    /// Waits until the injector is done with every op sent so far.
+    #[cfg(test)]
+    pub fn flush(&self) {
+        let (done, finished) = mpsc::channel();
+
+        if self.sender.send(Command::Flush(done)).is_ok() {
+            let _ = finished.recv();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::recording::Recording;
+    use super::*;
+    use crate::conn::display::DisplayLayout;
+
+    fn recording() -> Recording {
+        Recording::new(Display::fixed(
+            DisplayLayout::fallback(1920, 1080),
+            "",
+            (1920, 1080),
+        ))
+    }
+
+    fn action(json: &str) -> MouseAction {
+        MouseAction::parse(format!(r#"{{"payload": {json}}}"#).as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn clicks_where_the_peer_points() {
+        let recording = recording();
+        let mut pointer = Pointer::new(recording.handle());
+
+        pointer.apply(action(
+            r#"{"clicked_at": {"x_ratio": 0.5, "y_ratio": 0.25}}"#,
+        ));
+        pointer.apply(action(
+            r#"{"action": "drag", "button": "right",
+                "from": {"x_ratio": 0, "y_ratio": 0}, "to": {"x_ratio": 1, "y_ratio": 1}}"#,
+        ));
+
+        assert_eq!(
+            recording.take(),
+            [
+                InputOp::MoveTo(960, 270),
+                InputOp::Press(MouseButton::Left),
+                InputOp::Release(MouseButton::Left),
+                InputOp::MoveTo(0, 0),
+                InputOp::Press(MouseButton::Right),
+                InputOp::MoveTo(1919, 1079),
+                InputOp::Release(MouseButton::Right),
+            ]
+        );
+    }
+
+    #[test]
+    fn scrolls_by_whole_notches() {
+        let recording = recording();
+        let mut pointer = Pointer::new(recording.handle());
+
+        for _ in 0..3 {
+            pointer.apply(action(r#"{"action": "wheel", "delta_y": 40}"#));
+        }
+
+        pointer.apply(action(
+            r#"{"action": "wheel", "delta_x": -3, "delta_mode": "line"}"#,
+        ));
+
+        assert_eq!(
+            recording.take(),
+            [
+                InputOp::Scroll {
+                    horizontal: 0,
+                    vertical: 1,
+                },
+                InputOp::Scroll {
+                    horizontal: -1,
+                    vertical: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bounds_notches_per_message() {
+        let recording = recording();
+        let mut pointer = Pointer::new(recording.handle());
+
+        pointer.apply(action(
+            r#"{"action": "wheel", "delta_x": -1e300, "delta_y": 1e12}"#,
+        ));
+        pointer.apply(MouseAction::Wheel {
+            delta_x: f64::NAN,
+            delta_y: f64::INFINITY,
+            delta_mode: DeltaMode::Pixel,
+            at: None,
+        });
+        // Nothing was left over by the huge deltas.
+        pointer.apply(action(r#"{"action": "wheel", "delta_y": 100}"#));
+
+        assert_eq!(
+            recording.take(),
+            [
+                InputOp::Scroll {
+                    horizontal: -100,
+                    vertical: 100,
+                },
+                InputOp::Scroll {
+                    horizontal: 0,
+                    vertical: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_by_deltas_in_relative_mode() {
+        let recording = recording();
+        let mut pointer = Pointer::new(recording.handle());
+
+        // Positions are ignored, and deltas only count in relative mode.
+        pointer.apply(action(r#"{"action": "move_by", "dx": 5, "dy": 5}"#));
+        pointer.apply(action(r#"{"action": "mode", "mode": "relative"}"#));
+        pointer.apply(action(
+            r#"{"action": "move", "at": {"x_ratio": 0.5, "y_ratio": 0.5}}"#,
+        ));
+        pointer.apply(action(r#"{"action": "move_by", "dx": 2.5, "dy": -1}"#));
+        pointer.apply(action(r#"{"action": "move_by", "dx": 0.5, "dy": 0}"#));
+
+        assert_eq!(
+            recording.take(),
+            [InputOp::MoveBy(2, -1), InputOp::MoveBy(1, 0)]
+        );
+    }
+
+    #[test]
+    fn bounds_huge_deltas() {
+        let recording = recording();
+        let mut pointer = Pointer::new(recording.handle());
+
+        pointer.apply(action(r#"{"action": "mode", "mode": "relative"}"#));
+        pointer.apply(action(r#"{"action": "move_by", "dx": 1e10, "dy": -1e300}"#));
+        pointer.apply(action(r#"{"action": "move_by", "dx": 3, "dy": 0}"#));
+
+        // Non-finite deltas cannot come from JSON, only from a bug.
+        pointer.apply(MouseAction::MoveBy {
+            dx: f64::INFINITY,
+            dy: 1.0,
+        });
+        pointer.apply(MouseAction::MoveBy {
+            dx: f64::NAN,
+            dy: 1.0,
+        });
+
+        assert_eq!(
+            recording.take(),
+            [InputOp::MoveBy(1920, -1080), InputOp::MoveBy(3, 0)]
+        );
+
+        let mut pointer = Pointer::new(InputHandle {
+            relative_motion: RelativeMotion::Exact,
+            ..recording.handle()
+        });
+
+        pointer.apply(action(r#"{"action": "mode", "mode": "relative"}"#));
+        pointer.apply(action(r#"{"action": "move_by", "dx": 1e10, "dy": 1e10}"#));
+        pointer.apply(action(r#"{"action": "move_by", "dx": 1e10, "dy": -1e10}"#));
+
+        assert_eq!(
+            recording.take(),
+            [InputOp::MoveTo(1919, 1079), InputOp::MoveTo(1919, 0)]
+        );
+    }
+
+    #[test]
+    fn releases_held_buttons() {
+        let recording = recording();
+        let mut pointer = Pointer::new(recording.handle());
+
+        pointer.apply(action(r#"{"action": "down", "button": "middle"}"#));
+        pointer.apply(action(r#"{"action": "down"}"#));
+        pointer.apply(action(r#"{"action": "up", "button": "middle"}"#));
+        pointer.release_all();
+
+        assert_eq!(
+            recording.take(),
+            [
+                InputOp::Press(MouseButton::Middle),
+                InputOp::Press(MouseButton::Left),
+                InputOp::Release(MouseButton::Middle),
+                InputOp::Release(MouseButton::Left),
+            ]
+        );
+    }
+
+    #[test]
+    fn taps_click_without_a_touchscreen() {
+        let recording = recording();
+        let mut pointer = Pointer::new(recording.handle());
+
+        for phase in ["down", "up"] {
+            pointer.apply(action(&format!(
+                r#"{{"action": "touch", "phase": "{phase}", "id": 1,
+                    "at": {{"x_ratio": 0.25, "y_ratio": 0.5}}}}"#
+            )));
+        }
+
+        assert_eq!(
+            recording.take(),
+            [
+                InputOp::MoveTo(480, 540),
+                InputOp::Press(MouseButton::Left),
+                InputOp::Release(MouseButton::Left),
+            ]
+        );
+    }
+
+    #[test]
+    fn fingers_go_to_the_touchscreen() {
+        let recording = Recording::with_touch(Display::fixed(
+            DisplayLayout::fallback(1920, 1080),
+            "",
+            (1920, 1080),
+        ));
+        let mut pointer = Pointer::new(recording.handle());
+
+        for (id, phase) in [(7, "down"), (8, "down"), (7, "up")] {
+            pointer.apply(action(&format!(
+                r#"{{"action": "touch", "phase": "{phase}", "id": {id}, "pressure": 1,
+                    "at": {{"x_ratio": 0.5, "y_ratio": 0.5}}}}"#
+            )));
+        }
+
+        pointer.release_all();
+
+        let contact = TouchContact {
+            x: 960,
+            y: 540,
+            pressure: touch::PRESSURE_MAX,
+            major: 0,
+            minor: 0,
+        };
+
+        assert_eq!(
+            recording.take(),
+            [
+                InputOp::Touch {
+                    slot: 0,
+                    contact: Some(contact),
+                },
+                InputOp::Touch {
+                    slot: 1,
+                    contact: Some(contact),
+                },
+                InputOp::Touch {
+                    slot: 0,
+                    contact: None,
+                },
+                InputOp::Touch {
+                    slot: 1,
+                    contact: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use anyhow::anyhow;
+use mouse_rs::types::keys::Keys;
+use mouse_rs::Mouse;
+
+use super::{InputInjector, MouseButton};
+use crate::conn::keyboard::Key;
+
+/// Injects input through mouse-rs, and through libxdo for what mouse-rs
+/// leaves out on Linux.
+pub struct MouseRsInjector {
+    mouse: Mouse,
+    xdo: Option<xdo::Xdo>,
+}
+
+impl MouseRsInjector {
+    pub fn new() -> Self {
+        Self {
+            mouse: Mouse::new(),
+            xdo: xdo::Xdo::new(),
+        }
+    }
+}
+
+impl InputInjector for MouseRsInjector {
+    fn move_to(&mut self, x: i32, y: i32) -> anyhow::Result<()> {
+        self.mouse.move_to(x, y).map_err(|e| anyhow!("{e}"))
+    }
+
+    /// mouse-rs only moves to positions, so the motion is never accelerated.
+    fn move_by(&mut self, dx: i32, dy: i32) -> anyhow::Result<()> {
+        let at = self.mouse.get_position().map_err(|e| anyhow!("{e}"))?;
+        self.move_to(at.x.saturating_add(dx), at.y.saturating_add(dy))
+    }
+
+    fn press(&mut self, button: MouseButton) -> anyhow::Result<()> {
+        match key_of(button) {
+            Some(key) => self.mouse.press(&key).map_err(|e| anyhow!("{e}")),
+            None => xdo::press(self.xdo.as_ref(), button),
+        }
+    }
+
+    fn release(&mut self, button: MouseButton) -> anyhow::Result<()> {
+        match key_of(button) {
+            Some(key) => self.mouse.release(&key).map_err(|e| anyhow!("{e}")),
+            None => xdo::release(self.xdo.as_ref(), button),
+        }
+    }
+
+    fn scroll(&mut self, horizontal: i32, vertical: i32) -> anyhow::Result<()> {
+        let vertical = match vertical {
+            0 => Ok(()),
+            // mouse-rs scrolls down with positive values on X11 only.
+            notches if cfg!(target_os = "linux") => self.mouse.wheel(notches),
+            notches => self.mouse.wheel(-notches),
+        };
+
+        vertical.map_err(|e| anyhow!("{e}"))?;
+        xdo::scroll(self.xdo.as_ref(), horizontal)
+    }
+
+    fn key_down(&mut self, key: Key) -> anyhow::Result<()> {
+        xdo::key_down(self.xdo.as_ref(), key.name)
+    }
+
+    fn key_up(&mut self, key: Key) -> anyhow::Result<()> {
+        xdo::key_up(self.xdo.as_ref(), key.name)
+    }
+}
+
+/// The mouse-rs key of `button`, when mouse-rs can press it on this platform.
+fn key_of(button: MouseButton) -> Option<Keys> {
+    match button {
+        MouseButton::Left => Some(Keys::LEFT),
+        MouseButton::Right => Some(Keys::RIGHT),
+        MouseButton::Middle => Some(Keys::MIDDLE),
+        MouseButton::Back if cfg!(windows) => Some(Keys::X),
+        MouseButton::Forward if cfg!(windows) => Some(Keys::X2),
+        MouseButton::Back | MouseButton::Forward => None,
+    }
+}
+
+/// What mouse-rs leaves out, done through libxdo directly: the horizontal
+/// wheel, the back/forward buttons and the keyboard.
+#[cfg(target_os = "linux")]
+mod xdo {
+    use std::ffi::{c_char, c_int, c_uint, c_ulong, c_void, CString};
+
+    use anyhow::anyhow;
+
+    use super::MouseButton;
+
+    /// `CURRENTWINDOW` of libxdo, the window under the pointer.
+    const CURRENT_WINDOW: c_ulong = 0;
+
+    #[link(name = "xdo")]
+    extern "C" {
+        fn xdo_new(display: *const c_char) -> *mut c_void;
+        fn xdo_mouse_down(xdo: *const c_void, window: c_ulong, button: c_int) -> c_int;
+        fn xdo_mouse_up(xdo: *const c_void, window: c_ulong, button: c_int) -> c_int;
+        fn xdo_click_window(xdo: *const c_void, window: c_ulong, button: c_int) -> c_int;
+        fn xdo_send_keysequence_window_down(
+            xdo: *const c_void,
+            window: c_ulong,
+            keysequence: *const c_char,
+            delay: c_uint,
+        ) -> c_int;
+        fn xdo_send_keysequence_window_up(
+            xdo: *const c_void,
+            window: c_ulong,
+            keysequence: *const c_char,
+            delay: c_uint,
+        ) -> c_int;
+    }
+
+    pub struct Xdo(*mut c_void);
+
+    impl Xdo {
+        pub fn new() -> Option<Self> {
+            // SAFETY: a null display name makes libxdo use $DISPLAY.
+            let xdo = unsafe { xdo_new(std::ptr::null()) };
+            (!xdo.is_null()).then_some(Self(xdo))
+        }
+    }
+
+    fn x_button(button: MouseButton) -> c_int {
+        match button {
+            MouseButton::Left => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Right => 3,
+            MouseButton::Back => 8,
+            MouseButton::Forward => 9,
+        }
+    }
+
+    fn opened(xdo: Option<&Xdo>) -> anyhow::Result<&Xdo> {
+        xdo.ok_or_else(|| anyhow!("libxdo could not open the display"))
+    }
+
+    pub fn press(xdo: Option<&Xdo>, button: MouseButton) -> anyhow::Result<()> {
+        let xdo = opened(xdo)?;
+        // SAFETY: the handle was opened by `xdo_new` and is never freed.
+        unsafe { xdo_mouse_down(xdo.0, CURRENT_WINDOW, x_button(button)) };
+        Ok(())
+    }
+
+    pub fn release(xdo: Option<&Xdo>, button: MouseButton) -> anyhow::Result<()> {
+        let xdo = opened(xdo)?;
+        // SAFETY: the handle was opened by `xdo_new` and is never freed.
+        unsafe { xdo_mouse_up(xdo.0, CURRENT_WINDOW, x_button(button)) };
+        Ok(())
+    }
+
+    /// Clicks buttons 6 (left) and 7 (right), the horizontal wheel of X.
+    pub fn scroll(xdo: Option<&Xdo>, notches: i32) -> anyhow::Result<()> {
+        if notches == 0 {
+            return Ok(());
+        }
+
+        let xdo = opened(xdo)?;
+        let button = if notches < 0 { 6 } else { 7 };
+
+        for _ in 0..notches.unsigned_abs() {
+            // SAFETY: the handle was opened by `xdo_new` and is never freed.
+            unsafe { xdo_click_window(xdo.0, CURRENT_WINDOW, button) };
+        }
+
+        Ok(())
+    }
+
+    pub fn key_down(xdo: Option<&Xdo>, keysym: &str) -> anyhow::Result<()> {
+        let xdo = opened(xdo)?;
+        let keysym = CString::new(keysym)?;
+        // SAFETY: the handle was opened by `xdo_new` and is never freed.
+        unsafe { xdo_send_keysequence_window_down(xdo.0, CURRENT_WINDOW, keysym.as_ptr(), 0) };
+        Ok(())
+    }
+
+    pub fn key_up(xdo: Option<&Xdo>, keysym: &str) -> anyhow::Result<()> {
+        let xdo = opened(xdo)?;
+        let keysym = CString::new(keysym)?;
+        // SAFETY: the handle was opened by `xdo_new` and is never freed.
+        unsafe { xdo_send_keysequence_window_up(xdo.0, CURRENT_WINDOW, keysym.as_ptr(), 0) };
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod xdo {
+
+    use anyhow::anyhow;
+
+    use super::MouseButton;
+
+    pub struct Xdo;
+
+    impl Xdo {
+        pub fn new() -> Option<Self> {
+            None
+        }
+    }
+
+    pub fn press(_: Option<&Xdo>, button: MouseButton) -> anyhow::Result<()> {
+        Err(anyhow!(
+            "the {button:?} button is not supported on this platform"
+        ))
+    }
+
+    pub fn release(_: Option<&Xdo>, button: MouseButton) -> anyhow::Result<()> {
+        press(None, button)
+    }
+
+    pub fn scroll(_: Option<&Xdo>, notches: i32) -> anyhow::Result<()> {
+        match notches {
+            0 => Ok(()),
+            _ => Err(anyhow!(
+                "horizontal scrolling is not supported on this platform"
+            )),
+        }
+    }
+
+    pub fn key_down(_: Option<&Xdo>, _: &str) -> anyhow::Result<()> {
+        Err(anyhow!("keyboard input is not supported on this platform"))
+    }
+
+    pub fn key_up(xdo: Option<&Xdo>, keysym: &str) -> anyhow::Result<()> {
+        key_down(xdo, keysym)
+    }
+}
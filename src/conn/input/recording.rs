@@ -0,0 +1,111 @@
+use std::sync::{Arc, Mutex};
+
+use super::{InputHandle, InputInjector, InputOp, MouseButton, PenState, TouchContact};
+use crate::conn::display::Display;
+use crate::conn::keyboard::Key;
+
+/// Keeps input in memory instead of injecting it.
+struct RecordingInjector {
+    ops: Arc<Mutex<Vec<InputOp>>>,
+    touch: bool,
+}
+
+impl RecordingInjector {
+    fn record(&self, op: InputOp) -> anyhow::Result<()> {
+        self.ops
+            .lock()
+            .map_err(|_| anyhow::anyhow!("the recording is poisoned"))?
+            .push(op);
+        Ok(())
+    }
+}
+
+impl InputInjector for RecordingInjector {
+    fn move_to(&mut self, x: i32, y: i32) -> anyhow::Result<()> {
+        self.record(InputOp::MoveTo(x, y))
+    }
+
+    fn move_by(&mut self, dx: i32, dy: i32) -> anyhow::Result<()> {
+        self.record(InputOp::MoveBy(dx, dy))
+    }
+
+    fn press(&mut self, button: MouseButton) -> anyhow::Result<()> {
+        self.record(InputOp::Press(button))
+    }
+
+    fn release(&mut self, button: MouseButton) -> anyhow::Result<()> {
+        self.record(InputOp::Release(button))
+    }
+
+    fn scroll(&mut self, horizontal: i32, vertical: i32) -> anyhow::Result<()> {
+        self.record(InputOp::Scroll {
+            horizontal,
+            vertical,
+        })
+    }
+
+    fn key_down(&mut self, key: Key) -> anyhow::Result<()> {
+        self.record(InputOp::KeyDown(key))
+    }
+
+    fn key_up(&mut self, key: Key) -> anyhow::Result<()> {
+        self.record(InputOp::KeyUp(key))
+    }
+
+    fn supports_touch(&self) -> bool {
+        self.touch
+    }
+
+    fn touch(&mut self, slot: usize, contact: Option<TouchContact>) -> anyhow::Result<()> {
+        self.record(InputOp::Touch { slot, contact })
+    }
+
+    fn pen(&mut self, pen: Option<PenState>) -> anyhow::Result<()> {
+        self.record(InputOp::Pen(pen))
+    }
+}
+
+/// An [`InputHandle`] whose input is recorded rather than injected, for tests
+/// of the pointer, the keyboard and the data channels on headless hosts.
+pub struct Recording {
+    handle: InputHandle,
+    ops: Arc<Mutex<Vec<InputOp>>>,
+}
+
+impl Recording {
+    /// Records input of an injector without a touchscreen, which gets touch
+    /// as mouse gestures.
+    pub fn new(display: Display) -> Self {
+        Self::open(display, false)
+    }
+
+    /// Records input of an injector with a touchscreen and a pen.
+    pub fn with_touch(display: Display) -> Self {
+        Self::open(display, true)
+    }
+
+    fn open(display: Display, touch: bool) -> Self {
+        let ops = Arc::<Mutex<Vec<InputOp>>>::default();
+        let injector = RecordingInjector {
+            ops: ops.clone(),
+            touch,
+        };
+
+        let handle = InputHandle::spawn(display, move || Ok(Box::new(injector)));
+        // Whether it supports touch is known once it is open.
+        handle.flush();
+
+        Self { handle, ops }
+    }
+
+    /// The handle to give to a [`super::Pointer`], a keyboard or a manager.
+    pub fn handle(&self) -> InputHandle {
+        self.handle.clone()
+    }
+
+    /// Takes the ops recorded so far, once those in flight are done.
+    pub fn take(&self) -> Vec<InputOp> {
+        self.handle.flush();
+        std::mem::take(&mut *self.ops.lock().unwrap())
+    }
+}
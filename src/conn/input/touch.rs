@@ -0,0 +1,402 @@
+use std::time::{Duration, Instant};
+
+use rocket::serde::Deserialize;
+
+use super::{DeltaMode, InputOp, MouseButton, Pointer, Position};
+use crate::conn::display::CoordinateMapper;
+
+/// Most fingers on the touchscreen at once.
+pub const MAX_CONTACTS: usize = 10;
+/// Pressure of a finger or pen pressed as hard as it goes.
+pub const PRESSURE_MAX: u16 = 4095;
+
+/// How long a finger stays down for a right click rather than a left one,
+/// when touch is emulated.
+const LONG_PRESS: Duration = Duration::from_millis(500);
+/// How far a finger may wander, in desktop pixels, and still tap rather than
+/// drag.
+const TAP_SLOP: i32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum TouchPhase {
+    Down,
+    Move,
+    Up,
+    /// The browser took the contact over, e.g. for a gesture of its own. A
+    /// pen cancelled is out of range.
+    Cancel,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum TouchTool {
+    #[default]
+    Finger,
+    Pen,
+    Eraser,
+}
+
+/// A finger or pen of the peer, as in a `PointerEvent` whose `pointerType`
+/// is `touch` or `pen`. Pens also move while hovering, between `up` and
+/// `down`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TouchPoint {
+    pub phase: TouchPhase,
+    /// Identifies the contact from going down to lifting, as `pointerId`.
+    pub id: i64,
+    #[serde(default)]
+    pub tool: TouchTool,
+    pub at: Position,
+    /// From 0 to 1.
+    #[serde(default)]
+    pub pressure: f64,
+    /// Tilt of a pen towards the right and the bottom, in degrees from -90
+    /// to 90.
+    #[serde(default)]
+    pub tilt_x: f64,
+    #[serde(default)]
+    pub tilt_y: f64,
+    /// Size of the contact, relative to the size of the screen as positions
+    /// are.
+    #[serde(default)]
+    pub width_ratio: f64,
+    #[serde(default)]
+    pub height_ratio: f64,
+    /// Whether the barrel button of a pen is pressed.
+    #[serde(default)]
+    pub barrel: bool,
+}
+
+/// A finger on the touchscreen of the host, in desktop pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchContact {
+    pub x: i32,
+    pub y: i32,
+    /// Up to [`PRESSURE_MAX`].
+    pub pressure: u16,
+    /// Length of the contact along its longest side.
+    pub major: u32,
+    pub minor: u32,
+}
+
+/// The pen of the host while in range of the screen, in desktop pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PenState {
+    pub x: i32,
+    pub y: i32,
+    /// Up to [`PRESSURE_MAX`].
+    pub pressure: u16,
+    /// In degrees.
+    pub tilt_x: i32,
+    pub tilt_y: i32,
+    /// Whether the tip touches the screen, rather than hovering.
+    pub touching: bool,
+    pub eraser: bool,
+    pub barrel: bool,
+}
+
+struct Finger {
+    id: i64,
+    /// Slot of the touchscreen the finger is in.
+    slot: usize,
+    from: (i32, i32),
+    at: (i32, i32),
+    since: Instant,
+}
+
+/// What the fingers down do, when touch is emulated with the mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Gesture {
+    Idle,
+    /// One finger down, which clicks once lifted unless it moves.
+    Tap,
+    /// The first finger drags with the left button.
+    Drag,
+    /// Two fingers scroll, from where the point between them was last.
+    Scroll((i32, i32)),
+    /// The fingers left do nothing until lifted.
+    Ended,
+}
+
+/// The fingers and pen of one peer on the screen.
+pub(super) struct Touches {
+    fingers: Vec<Finger>,
+    gesture: Gesture,
+    pen: Option<PenState>,
+    /// Mouse button pressed by the pen, when touch is emulated.
+    pen_button: Option<MouseButton>,
+}
+
+impl Default for Touches {
+    fn default() -> Self {
+        Self {
+            fingers: vec![],
+            gesture: Gesture::Idle,
+            pen: None,
+            pen_button: None,
+        }
+    }
+}
+
+impl Touches {
+    /// The point between the first two fingers.
+    fn centroid(&self) -> (i32, i32) {
+        let (a, b) = (self.fingers[0].at, self.fingers[1].at);
+        ((a.0 + b.0) / 2, (a.1 + b.1) / 2)
+    }
+}
+
+fn pressure_of(pressure: f64) -> u16 {
+    match pressure.is_finite() {
+        true => (pressure.clamp(0.0, 1.0) * PRESSURE_MAX as f64).round() as u16,
+        false => 0,
+    }
+}
+
+fn tilt_of(tilt: f64) -> i32 {
+    match tilt.is_finite() {
+        true => tilt.clamp(-90.0, 90.0).round() as i32,
+        false => 0,
+    }
+}
+
+fn contact_of(point: &TouchPoint, (x, y): (i32, i32), mapper: &CoordinateMapper) -> TouchContact {
+    let (width, height) = mapper.size(point.width_ratio, point.height_ratio);
+
+    TouchContact {
+        x,
+        y,
+        pressure: pressure_of(point.pressure),
+        major: width.max(height),
+        minor: width.min(height),
+    }
+}
+
+/// Touch input goes to the touchscreen and pen of the injector when it has
+/// them. Otherwise fingers are turned into mouse gestures: a tap left clicks,
+/// a long press right clicks once lifted, a finger moving drags and two
+/// fingers scroll. The pen then acts as a mouse with the left button, or the
+/// right one while its barrel button is pressed.
+impl Pointer {
+    pub(super) fn touch(&mut self, point: TouchPoint) {
+        match point.tool {
+            TouchTool::Finger => self.finger(point),
+            TouchTool::Pen | TouchTool::Eraser => self.pen(point),
+        }
+    }
+
+    /// Lifts every finger and takes the pen away, once the peer loses
+    /// control. Buttons pressed by gestures are released with the others.
+    pub(super) fn lift_all(&mut self) {
+        let native = self.input.supports_touch();
+
+        for finger in std::mem::take(&mut self.touches.fingers) {
+            if native {
+                self.input.send(InputOp::Touch {
+                    slot: finger.slot,
+                    contact: None,
+                });
+            }
+        }
+
+        if self.touches.pen.take().is_some() {
+            self.input.send(InputOp::Pen(None));
+        }
+
+        self.touches.gesture = Gesture::Idle;
+        self.touches.pen_button = None;
+    }
+
+    fn finger(&mut self, point: TouchPoint) {
+        let mapper = self.input.display().mapper();
+        let at = mapper.map(point.at);
+        let native = self.input.supports_touch();
+        let index = self.touches.fingers.iter().position(|f| f.id == point.id);
+
+        match (point.phase, index) {
+            (TouchPhase::Down, None) => {
+                let free = (0..MAX_CONTACTS).find(|slot| {
+                    !self
+                        .touches
+                        .fingers
+                        .iter()
+                        .any(|finger| finger.slot == *slot)
+                });
+
+                let Some(slot) = free else {
+                    return;
+                };
+
+                self.touches.fingers.push(Finger {
+                    id: point.id,
+                    slot,
+                    from: at,
+                    at,
+                    since: Instant::now(),
+                });
+
+                if native {
+                    self.input.send(InputOp::Touch {
+                        slot,
+                        contact: Some(contact_of(&point, at, &mapper)),
+                    });
+                } else {
+                    self.gesture_down(at);
+                }
+            }
+            (TouchPhase::Down | TouchPhase::Move, Some(index)) => {
+                self.touches.fingers[index].at = at;
+
+                if native {
+                    self.input.send(InputOp::Touch {
+                        slot: self.touches.fingers[index].slot,
+                        contact: Some(contact_of(&point, at, &mapper)),
+                    });
+                } else {
+                    self.gesture_move(index);
+                }
+            }
+            (TouchPhase::Up | TouchPhase::Cancel, Some(index)) => {
+                let finger = self.touches.fingers.remove(index);
+
+                if native {
+                    self.input.send(InputOp::Touch {
+                        slot: finger.slot,
+                        contact: None,
+                    });
+                } else {
+                    self.gesture_up(index, finger, point.phase == TouchPhase::Up);
+                }
+            }
+            // Fingers the host never saw going down.
+            _ => {}
+        }
+    }
+
+    fn gesture_down(&mut self, at: (i32, i32)) {
+        match (self.touches.fingers.len(), self.touches.gesture) {
+            (1, _) => {
+                self.touches.gesture = Gesture::Tap;
+                self.point_at(at);
+            }
+            (2, Gesture::Tap) => {
+                self.touches.gesture = Gesture::Scroll(self.touches.centroid());
+            }
+            _ => {}
+        }
+    }
+
+    fn gesture_move(&mut self, index: usize) {
+        let first = &self.touches.fingers[0];
+        let at = first.at;
+        let wandered =
+            (at.0 - first.from.0).abs() > TAP_SLOP || (at.1 - first.from.1).abs() > TAP_SLOP;
+
+        match self.touches.gesture {
+            Gesture::Tap if wandered => {
+                self.touches.gesture = Gesture::Drag;
+                self.press(MouseButton::Left);
+                self.point_at(at);
+            }
+            Gesture::Drag if index == 0 => self.point_at(at),
+            Gesture::Scroll(last) => {
+                let centroid = self.touches.centroid();
+                self.touches.gesture = Gesture::Scroll(centroid);
+
+                // The content follows the fingers, as on a phone.
+                self.scroll(
+                    (last.0 - centroid.0) as f64,
+                    (last.1 - centroid.1) as f64,
+                    DeltaMode::Pixel,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn gesture_up(&mut self, index: usize, finger: Finger, lifted: bool) {
+        let gesture = self.touches.gesture;
+
+        match gesture {
+            Gesture::Tap if lifted => match finger.since.elapsed() >= LONG_PRESS {
+                true => self.click(MouseButton::Right),
+                false => self.click(MouseButton::Left),
+            },
+            Gesture::Drag if index == 0 => self.release(MouseButton::Left),
+            _ => {}
+        }
+
+        self.touches.gesture = match (gesture, self.touches.fingers.len()) {
+            (_, 0) => Gesture::Idle,
+            (Gesture::Drag, _) if index != 0 => Gesture::Drag,
+            _ => Gesture::Ended,
+        };
+    }
+
+    fn pen(&mut self, point: TouchPoint) {
+        let at = self.input.display().mapper().map(point.at);
+
+        if !self.input.supports_touch() {
+            match point.phase {
+                TouchPhase::Down => {
+                    let button = match point.barrel {
+                        true => MouseButton::Right,
+                        false => MouseButton::Left,
+                    };
+
+                    self.point_at(at);
+                    self.touches.pen_button = Some(button);
+                    self.press(button);
+                }
+                TouchPhase::Move => self.point_at(at),
+                TouchPhase::Up | TouchPhase::Cancel => {
+                    if let Some(button) = self.touches.pen_button.take() {
+                        self.release(button);
+                    }
+                }
+            }
+
+            return;
+        }
+
+        let touching = match point.phase {
+            TouchPhase::Down => true,
+            TouchPhase::Move => self.touches.pen.is_some_and(|pen| pen.touching),
+            TouchPhase::Up => false,
+            TouchPhase::Cancel => {
+                if self.touches.pen.take().is_some() {
+                    self.input.send(InputOp::Pen(None));
+                }
+
+                return;
+            }
+        };
+
+        let pen = PenState {
+            x: at.0,
+            y: at.1,
+            pressure: if touching {
+                pressure_of(point.pressure)
+            } else {
+                0
+            },
+            tilt_x: tilt_of(point.tilt_x),
+            tilt_y: tilt_of(point.tilt_y),
+            touching,
+            eraser: point.tool == TouchTool::Eraser,
+            barrel: point.barrel,
+        };
+
+        self.touches.pen = Some(pen);
+        self.input.send(InputOp::Pen(Some(pen)));
+    }
+
+    /// Moves the pointer to the desktop pixel `at`, whatever the mode:
+    /// touch always lands where it is.
+    fn point_at(&mut self, (x, y): (i32, i32)) {
+        self.position = Some((x, y));
+        self.input.send(InputOp::MoveTo(x, y));
+    }
+}
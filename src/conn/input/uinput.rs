@@ -0,0 +1,359 @@
+use anyhow::Context;
+use evdev::uinput::VirtualDevice;
+use evdev::{
+    AbsInfo, AbsoluteAxisCode, AttributeSet, EventType, InputEvent, KeyCode, PropType,
+    RelativeAxisCode, UinputAbsSetup,
+};
+
+use super::touch::{MAX_CONTACTS, PRESSURE_MAX};
+use super::{InputInjector, MouseButton, PenState, TouchContact};
+use crate::conn::display::Display;
+use crate::conn::keyboard::{Key, KEYS};
+
+/// Largest value of the absolute axes, which span the whole desktop.
+const AXIS_MAX: i32 = 65535;
+
+const BUTTONS: [MouseButton; 5] = [
+    MouseButton::Left,
+    MouseButton::Right,
+    MouseButton::Middle,
+    MouseButton::Back,
+    MouseButton::Forward,
+];
+
+/// Injects input through virtual devices of the kernel, seen by X11,
+/// Wayland compositors and the console alike. The landlord needs write
+/// access to `/dev/uinput`.
+pub struct UinputInjector {
+    device: VirtualDevice,
+    /// A plain mouse for relative motion, which the absolute pointer of
+    /// `device` cannot carry.
+    mouse: VirtualDevice,
+    /// A multi-touch screen spanning the desktop.
+    touchscreen: VirtualDevice,
+    pen: VirtualDevice,
+    display: Display,
+    /// Tracking id of the finger in each slot of the touchscreen.
+    slots: [Option<i32>; MAX_CONTACTS],
+    last_tracking_id: i32,
+    /// Tool of the pen while in range.
+    pen_tool: Option<KeyCode>,
+}
+
+impl UinputInjector {
+    /// Creates a device with an absolute pointer spanning the desktop of
+    /// `display`, both wheels, the mouse buttons and every key of the key
+    /// table, along with a relative mouse, a touchscreen and a pen.
+    pub fn new(display: Display) -> anyhow::Result<Self> {
+        let keys: AttributeSet<KeyCode> = KEYS
+            .iter()
+            .map(|(_, key)| KeyCode(key.evdev))
+            .chain(BUTTONS.map(code_of))
+            .collect();
+
+        let wheels: AttributeSet<RelativeAxisCode> =
+            [RelativeAxisCode::REL_WHEEL, RelativeAxisCode::REL_HWHEEL]
+                .into_iter()
+                .collect();
+
+        let axis = |code| UinputAbsSetup::new(code, AbsInfo::new(0, 0, AXIS_MAX, 0, 0, 0));
+
+        let device = VirtualDevice::builder()
+            .context("Unable to open /dev/uinput")?
+            .name("Landlord virtual input")
+            .with_keys(&keys)?
+            .with_relative_axes(&wheels)?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_X))?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_Y))?
+            .build()?;
+
+        // Without buttons, the mouse would not be recognized as one.
+        let buttons: AttributeSet<KeyCode> = BUTTONS.map(code_of).into_iter().collect();
+        let motion: AttributeSet<RelativeAxisCode> =
+            [RelativeAxisCode::REL_X, RelativeAxisCode::REL_Y]
+                .into_iter()
+                .collect();
+
+        let mouse = VirtualDevice::builder()
+            .context("Unable to open /dev/uinput")?
+            .name("Landlord virtual mouse")
+            .with_keys(&buttons)?
+            .with_relative_axes(&motion)?
+            .build()?;
+
+        let direct: AttributeSet<PropType> = [PropType::DIRECT].into_iter().collect();
+        let setup = |code, min, max| UinputAbsSetup::new(code, AbsInfo::new(0, min, max, 0, 0, 0));
+        let pressure = |code| setup(code, 0, PRESSURE_MAX as i32);
+
+        let fingers: AttributeSet<KeyCode> = [KeyCode::BTN_TOUCH, KeyCode::BTN_TOOL_FINGER]
+            .into_iter()
+            .collect();
+
+        let touchscreen = VirtualDevice::builder()
+            .context("Unable to open /dev/uinput")?
+            .name("Landlord virtual touchscreen")
+            .with_properties(&direct)?
+            .with_keys(&fingers)?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_X))?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_Y))?
+            .with_absolute_axis(&setup(
+                AbsoluteAxisCode::ABS_MT_SLOT,
+                0,
+                MAX_CONTACTS as i32 - 1,
+            ))?
+            .with_absolute_axis(&setup(
+                AbsoluteAxisCode::ABS_MT_TRACKING_ID,
+                0,
+                TRACKING_ID_MAX,
+            ))?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_MT_POSITION_X))?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_MT_POSITION_Y))?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_MT_TOUCH_MAJOR))?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_MT_TOUCH_MINOR))?
+            .with_absolute_axis(&pressure(AbsoluteAxisCode::ABS_MT_PRESSURE))?
+            .build()?;
+
+        let tools: AttributeSet<KeyCode> = [
+            KeyCode::BTN_TOUCH,
+            KeyCode::BTN_STYLUS,
+            KeyCode::BTN_TOOL_PEN,
+            KeyCode::BTN_TOOL_RUBBER,
+        ]
+        .into_iter()
+        .collect();
+
+        let pen = VirtualDevice::builder()
+            .context("Unable to open /dev/uinput")?
+            .name("Landlord virtual pen")
+            .with_properties(&direct)?
+            .with_keys(&tools)?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_X))?
+            .with_absolute_axis(&axis(AbsoluteAxisCode::ABS_Y))?
+            .with_absolute_axis(&pressure(AbsoluteAxisCode::ABS_PRESSURE))?
+            .with_absolute_axis(&setup(AbsoluteAxisCode::ABS_TILT_X, -90, 90))?
+            .with_absolute_axis(&setup(AbsoluteAxisCode::ABS_TILT_Y, -90, 90))?
+            .build()?;
+
+        Ok(Self {
+            device,
+            mouse,
+            touchscreen,
+            pen,
+            display,
+            slots: [None; MAX_CONTACTS],
+            last_tracking_id: 0,
+            pen_tool: None,
+        })
+    }
+
+    /// Emits `events`, followed by the report that applies them.
+    fn emit(&mut self, events: &[InputEvent]) -> anyhow::Result<()> {
+        Ok(self.device.emit(events)?)
+    }
+
+    fn key(&mut self, code: KeyCode, down: bool) -> anyhow::Result<()> {
+        self.emit(&[key_event(code, down)])
+    }
+
+    /// Scales the desktop pixel `x`, `y` to the axes, as the desktop may
+    /// have been resized since the devices were created.
+    fn scale(&self, x: i32, y: i32) -> (i32, i32) {
+        let desktop = self.display.desktop();
+
+        let axis = |pixel: i32, origin: i32, size: u32| {
+            let last = size.saturating_sub(1).max(1) as f64;
+            ((pixel - origin) as f64 / last * AXIS_MAX as f64).round() as i32
+        };
+
+        (
+            axis(x, desktop.x, desktop.width),
+            axis(y, desktop.y, desktop.height),
+        )
+    }
+
+    /// Scales a length in desktop pixels to the horizontal axis.
+    fn scale_length(&self, pixels: u32) -> i32 {
+        let width = self.display.desktop().width.max(1) as f64;
+        (pixels as f64 / width * AXIS_MAX as f64)
+            .round()
+            .min(AXIS_MAX as f64) as i32
+    }
+}
+
+/// Largest tracking id of a finger, after which they start over from zero.
+const TRACKING_ID_MAX: i32 = 65535;
+
+fn key_event(code: KeyCode, down: bool) -> InputEvent {
+    InputEvent::new(EventType::KEY.0, code.0, down as i32)
+}
+
+fn abs_event(code: AbsoluteAxisCode, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE.0, code.0, value)
+}
+
+fn code_of(button: MouseButton) -> KeyCode {
+    match button {
+        MouseButton::Left => KeyCode::BTN_LEFT,
+        MouseButton::Right => KeyCode::BTN_RIGHT,
+        MouseButton::Middle => KeyCode::BTN_MIDDLE,
+        MouseButton::Back => KeyCode::BTN_SIDE,
+        MouseButton::Forward => KeyCode::BTN_EXTRA,
+    }
+}
+
+impl InputInjector for UinputInjector {
+    /// Scales the desktop pixel to the axes, as the desktop may have been
+    /// resized since the device was created.
+    fn move_to(&mut self, x: i32, y: i32) -> anyhow::Result<()> {
+        let (x, y) = self.scale(x, y);
+
+        self.emit(&[
+            abs_event(AbsoluteAxisCode::ABS_X, x),
+            abs_event(AbsoluteAxisCode::ABS_Y, y),
+        ])
+    }
+
+    fn move_by(&mut self, dx: i32, dy: i32) -> anyhow::Result<()> {
+        Ok(self.mouse.emit(&[
+            InputEvent::new(EventType::RELATIVE.0, RelativeAxisCode::REL_X.0, dx),
+            InputEvent::new(EventType::RELATIVE.0, RelativeAxisCode::REL_Y.0, dy),
+        ])?)
+    }
+
+    fn press(&mut self, button: MouseButton) -> anyhow::Result<()> {
+        self.key(code_of(button), true)
+    }
+
+    fn release(&mut self, button: MouseButton) -> anyhow::Result<()> {
+        self.key(code_of(button), false)
+    }
+
+    fn scroll(&mut self, horizontal: i32, vertical: i32) -> anyhow::Result<()> {
+        // The vertical wheel of evdev counts upwards.
+        self.emit(&[
+            InputEvent::new(
+                EventType::RELATIVE.0,
+                RelativeAxisCode::REL_HWHEEL.0,
+                horizontal,
+            ),
+            InputEvent::new(
+                EventType::RELATIVE.0,
+                RelativeAxisCode::REL_WHEEL.0,
+                -vertical,
+            ),
+        ])
+    }
+
+    fn key_down(&mut self, key: Key) -> anyhow::Result<()> {
+        self.key(KeyCode(key.evdev), true)
+    }
+
+    fn key_up(&mut self, key: Key) -> anyhow::Result<()> {
+        self.key(KeyCode(key.evdev), false)
+    }
+
+    fn supports_touch(&self) -> bool {
+        true
+    }
+
+    /// Follows the type B protocol of the kernel: each finger keeps its slot
+    /// and tracking id from landing to lifting. The first finger also drives
+    /// the single-touch axes.
+    fn touch(&mut self, slot: usize, contact: Option<TouchContact>) -> anyhow::Result<()> {
+        let mut events = vec![abs_event(AbsoluteAxisCode::ABS_MT_SLOT, slot as i32)];
+
+        match contact {
+            Some(contact) => {
+                if self.slots[slot].is_none() {
+                    self.last_tracking_id = (self.last_tracking_id + 1) % (TRACKING_ID_MAX + 1);
+                    self.slots[slot] = Some(self.last_tracking_id);
+                    events.push(abs_event(
+                        AbsoluteAxisCode::ABS_MT_TRACKING_ID,
+                        self.last_tracking_id,
+                    ));
+                }
+
+                let (x, y) = self.scale(contact.x, contact.y);
+
+                events.extend([
+                    abs_event(AbsoluteAxisCode::ABS_MT_POSITION_X, x),
+                    abs_event(AbsoluteAxisCode::ABS_MT_POSITION_Y, y),
+                    abs_event(AbsoluteAxisCode::ABS_MT_PRESSURE, contact.pressure as i32),
+                    abs_event(
+                        AbsoluteAxisCode::ABS_MT_TOUCH_MAJOR,
+                        self.scale_length(contact.major),
+                    ),
+                    abs_event(
+                        AbsoluteAxisCode::ABS_MT_TOUCH_MINOR,
+                        self.scale_length(contact.minor),
+                    ),
+                ]);
+
+                if self.slots.iter().position(Option::is_some) == Some(slot) {
+                    events.extend([
+                        abs_event(AbsoluteAxisCode::ABS_X, x),
+                        abs_event(AbsoluteAxisCode::ABS_Y, y),
+                    ]);
+                }
+            }
+            None => {
+                if self.slots[slot].take().is_none() {
+                    return Ok(());
+                }
+
+                events.push(abs_event(AbsoluteAxisCode::ABS_MT_TRACKING_ID, -1));
+            }
+        }
+
+        let touching = self.slots.iter().any(Option::is_some);
+        events.extend([
+            key_event(KeyCode::BTN_TOUCH, touching),
+            key_event(KeyCode::BTN_TOOL_FINGER, touching),
+        ]);
+
+        Ok(self.touchscreen.emit(&events)?)
+    }
+
+    fn pen(&mut self, pen: Option<PenState>) -> anyhow::Result<()> {
+        let tool = pen.map(|pen| match pen.eraser {
+            true => KeyCode::BTN_TOOL_RUBBER,
+            false => KeyCode::BTN_TOOL_PEN,
+        });
+
+        let mut events = vec![];
+
+        // Switching tools takes the pen out of range first.
+        if self.pen_tool != tool {
+            if let Some(previous) = self.pen_tool {
+                events.extend([
+                    key_event(KeyCode::BTN_TOUCH, false),
+                    key_event(KeyCode::BTN_STYLUS, false),
+                    abs_event(AbsoluteAxisCode::ABS_PRESSURE, 0),
+                    key_event(previous, false),
+                ]);
+            }
+
+            if let Some(tool) = tool {
+                events.push(key_event(tool, true));
+            }
+
+            self.pen_tool = tool;
+        }
+
+        if let Some(pen) = pen {
+            let (x, y) = self.scale(pen.x, pen.y);
+
+            events.extend([
+                abs_event(AbsoluteAxisCode::ABS_X, x),
+                abs_event(AbsoluteAxisCode::ABS_Y, y),
+                abs_event(AbsoluteAxisCode::ABS_PRESSURE, pen.pressure as i32),
+                abs_event(AbsoluteAxisCode::ABS_TILT_X, pen.tilt_x),
+                abs_event(AbsoluteAxisCode::ABS_TILT_Y, pen.tilt_y),
+                key_event(KeyCode::BTN_TOUCH, pen.touching),
+                key_event(KeyCode::BTN_STYLUS, pen.barrel),
+            ]);
+        }
+
+        Ok(self.pen.emit(&events)?)
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    ConnectionExt as _, Keycode, Window, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, KEY_PRESS_EVENT,
+    KEY_RELEASE_EVENT, MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+use x11rb::CURRENT_TIME;
+
+use super::{InputInjector, MouseButton};
+use crate::conn::keyboard::Key;
+
+/// Injects input through the XTest extension of the X server on `$DISPLAY`,
+/// without libxdo.
+pub struct XTestInjector {
+    conn: RustConnection,
+    root: Window,
+    /// Keycode of each keysym of the server keymap.
+    keycodes: HashMap<u32, Keycode>,
+}
+
+impl XTestInjector {
+    pub fn new() -> anyhow::Result<Self> {
+        let (conn, screen) = x11rb::connect(None).context("Unable to open the X display")?;
+        conn.xtest_get_version(2, 2)?
+            .reply()
+            .context("The X server lacks the XTest extension")?;
+
+        let setup = conn.setup();
+        let root = setup.roots[screen].root;
+        let first = setup.min_keycode;
+        let count = setup.max_keycode - first + 1;
+
+        let mapping = conn.get_keyboard_mapping(first, count)?.reply()?;
+        let per_keycode = mapping.keysyms_per_keycode.max(1) as usize;
+        let mut keycodes = HashMap::new();
+
+        // Keysyms without modifiers come first, prefer them.
+        for column in 0..per_keycode {
+            for (index, keysyms) in mapping.keysyms.chunks(per_keycode).enumerate() {
+                if keysyms[column] != 0 {
+                    keycodes
+                        .entry(keysyms[column])
+                        .or_insert(first + index as u8);
+                }
+            }
+        }
+
+        Ok(Self {
+            conn,
+            root,
+            keycodes,
+        })
+    }
+
+    fn fake(&self, kind: u8, detail: u8, x: i16, y: i16) -> anyhow::Result<()> {
+        self.conn
+            .xtest_fake_input(kind, detail, CURRENT_TIME, self.root, x, y, 0)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn click(&self, button: u8) -> anyhow::Result<()> {
+        self.fake(BUTTON_PRESS_EVENT, button, 0, 0)?;
+        self.fake(BUTTON_RELEASE_EVENT, button, 0, 0)
+    }
+
+    fn keycode(&self, key: Key) -> anyhow::Result<Keycode> {
+        self.keycodes
+            .get(&key.keysym)
+            .copied()
+            .ok_or_else(|| anyhow!("the X keymap has no {} key", key.name))
+    }
+}
+
+fn x_button(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Right => 3,
+        MouseButton::Back => 8,
+        MouseButton::Forward => 9,
+    }
+}
+
+impl InputInjector for XTestInjector {
+    fn move_to(&mut self, x: i32, y: i32) -> anyhow::Result<()> {
+        self.fake(MOTION_NOTIFY_EVENT, 0, x as i16, y as i16)
+    }
+
+    /// Motion flagged as relative, which the server accelerates.
+    fn move_by(&mut self, dx: i32, dy: i32) -> anyhow::Result<()> {
+        self.fake(MOTION_NOTIFY_EVENT, 1, dx as i16, dy as i16)
+    }
+
+    fn press(&mut self, button: MouseButton) -> anyhow::Result<()> {
+        self.fake(BUTTON_PRESS_EVENT, x_button(button), 0, 0)
+    }
+
+    fn release(&mut self, button: MouseButton) -> anyhow::Result<()> {
+        self.fake(BUTTON_RELEASE_EVENT, x_button(button), 0, 0)
+    }
+
+    /// Clicks buttons 4 to 7, the wheels of X.
+    fn scroll(&mut self, horizontal: i32, vertical: i32) -> anyhow::Result<()> {
+        let vertical_button = if vertical < 0 { 4 } else { 5 };
+        let horizontal_button = if horizontal < 0 { 6 } else { 7 };
+
+        for _ in 0..vertical.unsigned_abs() {
+            self.click(vertical_button)?;
+        }
+
+        for _ in 0..horizontal.unsigned_abs() {
+            self.click(horizontal_button)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down(&mut self, key: Key) -> anyhow::Result<()> {
+        self.fake(KEY_PRESS_EVENT, self.keycode(key)?, 0, 0)
+    }
+
+    fn key_up(&mut self, key: Key) -> anyhow::Result<()> {
+        self.fake(KEY_RELEASE_EVENT, self.keycode(key)?, 0, 0)
+    }
+}
@@ -0,0 +1,370 @@
+use rocket::serde::Deserialize;
+
+use crate::conn::input::{InputHandle, InputOp};
+
+/// Modifier keys held down on the peer, as in `KeyboardEvent`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub meta: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum KeyAction {
+    Down,
+    Up,
+}
+
+/// A message of the `keyboard_events` data channel.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct KeyEvent {
+    pub action: KeyAction,
+    /// Physical key, as in `KeyboardEvent.code`.
+    pub code: String,
+    #[serde(default)]
+    pub modifiers: Modifiers,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct KeyboardMessage {
+    payload: KeyEvent,
+}
+
+impl KeyEvent {
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice::<KeyboardMessage>(data)?.payload)
+    }
+}
+
+/// A key of the host, as each input backend names it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    /// X keysym name, for libxdo.
+    pub name: &'static str,
+    /// X keysym value, for XTest.
+    pub keysym: u32,
+    /// Linux input event code, for uinput.
+    pub evdev: u16,
+}
+
+const fn key(name: &'static str, keysym: u32, evdev: u16) -> Key {
+    Key {
+        name,
+        keysym,
+        evdev,
+    }
+}
+
+/// Every physical key a peer can press, by `KeyboardEvent.code`, for a US
+/// layout.
+pub const KEYS: [(&str, Key); 117] = [
+    ("KeyA", key("a", 0x61, 30)),
+    ("KeyB", key("b", 0x62, 48)),
+    ("KeyC", key("c", 0x63, 46)),
+    ("KeyD", key("d", 0x64, 32)),
+    ("KeyE", key("e", 0x65, 18)),
+    ("KeyF", key("f", 0x66, 33)),
+    ("KeyG", key("g", 0x67, 34)),
+    ("KeyH", key("h", 0x68, 35)),
+    ("KeyI", key("i", 0x69, 23)),
+    ("KeyJ", key("j", 0x6a, 36)),
+    ("KeyK", key("k", 0x6b, 37)),
+    ("KeyL", key("l", 0x6c, 38)),
+    ("KeyM", key("m", 0x6d, 50)),
+    ("KeyN", key("n", 0x6e, 49)),
+    ("KeyO", key("o", 0x6f, 24)),
+    ("KeyP", key("p", 0x70, 25)),
+    ("KeyQ", key("q", 0x71, 16)),
+    ("KeyR", key("r", 0x72, 19)),
+    ("KeyS", key("s", 0x73, 31)),
+    ("KeyT", key("t", 0x74, 20)),
+    ("KeyU", key("u", 0x75, 22)),
+    ("KeyV", key("v", 0x76, 47)),
+    ("KeyW", key("w", 0x77, 17)),
+    ("KeyX", key("x", 0x78, 45)),
+    ("KeyY", key("y", 0x79, 21)),
+    ("KeyZ", key("z", 0x7a, 44)),
+    ("Digit0", key("0", 0x30, 11)),
+    ("Digit1", key("1", 0x31, 2)),
+    ("Digit2", key("2", 0x32, 3)),
+    ("Digit3", key("3", 0x33, 4)),
+    ("Digit4", key("4", 0x34, 5)),
+    ("Digit5", key("5", 0x35, 6)),
+    ("Digit6", key("6", 0x36, 7)),
+    ("Digit7", key("7", 0x37, 8)),
+    ("Digit8", key("8", 0x38, 9)),
+    ("Digit9", key("9", 0x39, 10)),
+    ("F1", key("F1", 0xffbe, 59)),
+    ("F2", key("F2", 0xffbf, 60)),
+    ("F3", key("F3", 0xffc0, 61)),
+    ("F4", key("F4", 0xffc1, 62)),
+    ("F5", key("F5", 0xffc2, 63)),
+    ("F6", key("F6", 0xffc3, 64)),
+    ("F7", key("F7", 0xffc4, 65)),
+    ("F8", key("F8", 0xffc5, 66)),
+    ("F9", key("F9", 0xffc6, 67)),
+    ("F10", key("F10", 0xffc7, 68)),
+    ("F11", key("F11", 0xffc8, 87)),
+    ("F12", key("F12", 0xffc9, 88)),
+    ("F13", key("F13", 0xffca, 183)),
+    ("F14", key("F14", 0xffcb, 184)),
+    ("F15", key("F15", 0xffcc, 185)),
+    ("F16", key("F16", 0xffcd, 186)),
+    ("F17", key("F17", 0xffce, 187)),
+    ("F18", key("F18", 0xffcf, 188)),
+    ("F19", key("F19", 0xffd0, 189)),
+    ("F20", key("F20", 0xffd1, 190)),
+    ("F21", key("F21", 0xffd2, 191)),
+    ("F22", key("F22", 0xffd3, 192)),
+    ("F23", key("F23", 0xffd4, 193)),
+    ("F24", key("F24", 0xffd5, 194)),
+    ("Numpad0", key("KP_0", 0xffb0, 82)),
+    ("Numpad1", key("KP_1", 0xffb1, 79)),
+    ("Numpad2", key("KP_2", 0xffb2, 80)),
+    ("Numpad3", key("KP_3", 0xffb3, 81)),
+    ("Numpad4", key("KP_4", 0xffb4, 75)),
+    ("Numpad5", key("KP_5", 0xffb5, 76)),
+    ("Numpad6", key("KP_6", 0xffb6, 77)),
+    ("Numpad7", key("KP_7", 0xffb7, 71)),
+    ("Numpad8", key("KP_8", 0xffb8, 72)),
+    ("Numpad9", key("KP_9", 0xffb9, 73)),
+    ("Enter", key("Return", 0xff0d, 28)),
+    ("Escape", key("Escape", 0xff1b, 1)),
+    ("Backspace", key("BackSpace", 0xff08, 14)),
+    ("Tab", key("Tab", 0xff09, 15)),
+    ("Space", key("space", 0x20, 57)),
+    ("Minus", key("minus", 0x2d, 12)),
+    ("Equal", key("equal", 0x3d, 13)),
+    ("BracketLeft", key("bracketleft", 0x5b, 26)),
+    ("BracketRight", key("bracketright", 0x5d, 27)),
+    ("Backslash", key("backslash", 0x5c, 43)),
+    ("IntlBackslash", key("less", 0x3c, 86)),
+    ("Semicolon", key("semicolon", 0x3b, 39)),
+    ("Quote", key("apostrophe", 0x27, 40)),
+    ("Backquote", key("grave", 0x60, 41)),
+    ("Comma", key("comma", 0x2c, 51)),
+    ("Period", key("period", 0x2e, 52)),
+    ("Slash", key("slash", 0x2f, 53)),
+    ("CapsLock", key("Caps_Lock", 0xffe5, 58)),
+    ("PrintScreen", key("Print", 0xff61, 99)),
+    ("ScrollLock", key("Scroll_Lock", 0xff14, 70)),
+    ("Pause", key("Pause", 0xff13, 119)),
+    ("Insert", key("Insert", 0xff63, 110)),
+    ("Delete", key("Delete", 0xffff, 111)),
+    ("Home", key("Home", 0xff50, 102)),
+    ("End", key("End", 0xff57, 107)),
+    ("PageUp", key("Prior", 0xff55, 104)),
+    ("PageDown", key("Next", 0xff56, 109)),
+    ("ArrowLeft", key("Left", 0xff51, 105)),
+    ("ArrowRight", key("Right", 0xff53, 106)),
+    ("ArrowUp", key("Up", 0xff52, 103)),
+    ("ArrowDown", key("Down", 0xff54, 108)),
+    ("NumLock", key("Num_Lock", 0xff7f, 69)),
+    ("NumpadDivide", key("KP_Divide", 0xffaf, 98)),
+    ("NumpadMultiply", key("KP_Multiply", 0xffaa, 55)),
+    ("NumpadSubtract", key("KP_Subtract", 0xffad, 74)),
+    ("NumpadAdd", key("KP_Add", 0xffab, 78)),
+    ("NumpadEnter", key("KP_Enter", 0xff8d, 96)),
+    ("NumpadDecimal", key("KP_Decimal", 0xffae, 83)),
+    ("ContextMenu", key("Menu", 0xff67, 127)),
+    ("ShiftLeft", key("Shift_L", 0xffe1, 42)),
+    ("ShiftRight", key("Shift_R", 0xffe2, 54)),
+    ("ControlLeft", key("Control_L", 0xffe3, 29)),
+    ("ControlRight", key("Control_R", 0xffe4, 97)),
+    ("AltLeft", key("Alt_L", 0xffe9, 56)),
+    ("AltRight", key("Alt_R", 0xffea, 100)),
+    ("MetaLeft", key("Super_L", 0xffeb, 125)),
+    ("MetaRight", key("Super_R", 0xffec, 126)),
+];
+
+/// Left and right keys of each modifier, in the order of [`Modifiers`].
+const MODIFIER_CODES: [[&str; 2]; 4] = [
+    ["ShiftLeft", "ShiftRight"],
+    ["ControlLeft", "ControlRight"],
+    ["AltLeft", "AltRight"],
+    ["MetaLeft", "MetaRight"],
+];
+
+/// The key of the host for the physical key `code`.
+fn key_of(code: &str) -> Option<Key> {
+    KEYS.iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, key)| *key)
+}
+
+/// The keyboard of one peer: the keys it holds down on the host.
+pub struct Keyboard {
+    input: InputHandle,
+    held: Vec<Key>,
+}
+
+impl Keyboard {
+    pub fn new(input: InputHandle) -> Self {
+        Self {
+            input,
+            held: vec![],
+        }
+    }
+
+    pub fn apply(&mut self, event: KeyEvent) {
+        let Some(key) = key_of(&event.code) else {
+            warn!("Ignoring unknown key code '{}'.", event.code);
+            return;
+        };
+
+        self.sync_modifiers(event.modifiers, &event.code);
+
+        match event.action {
+            KeyAction::Down => self.press(key),
+            KeyAction::Up => self.release(key),
+        }
+    }
+
+    /// Lets go of every key held down, once the peer loses control.
+    pub fn release_all(&mut self) {
+        for key in std::mem::take(&mut self.held).into_iter().rev() {
+            self.input.send(InputOp::KeyUp(key));
+        }
+    }
+
+    /// Brings the modifiers held on the host in line with those of the peer,
+    /// which differ when the peer missed a key event, e.g. while its window
+    /// was out of focus.
+    fn sync_modifiers(&mut self, modifiers: Modifiers, code: &str) {
+        let wanted = [
+            modifiers.shift,
+            modifiers.ctrl,
+            modifiers.alt,
+            modifiers.meta,
+        ];
+
+        for (wanted, codes) in wanted.into_iter().zip(MODIFIER_CODES) {
+            // The event itself presses or releases that modifier.
+            if codes.contains(&code) {
+                continue;
+            }
+
+            let keys = codes.map(|code| key_of(code).expect("Modifiers are in the key table."));
+            let holding = self.held.iter().any(|held| keys.contains(held));
+
+            if wanted && !holding {
+                self.press(keys[0]);
+            } else if !wanted && holding {
+                for key in keys {
+                    self.release(key);
+                }
+            }
+        }
+    }
+
+    /// Presses `key`, unless it is held already: the host repeats held keys
+    /// on its own.
+    fn press(&mut self, key: Key) {
+        if !self.held.contains(&key) {
+            self.held.push(key);
+            self.input.send(InputOp::KeyDown(key));
+        }
+    }
+
+    /// Releases `key`, if it was pressed by this peer.
+    fn release(&mut self, key: Key) {
+        if let Some(index) = self.held.iter().position(|held| *held == key) {
+            self.held.remove(index);
+            self.input.send(InputOp::KeyUp(key));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conn::display::{Display, DisplayLayout};
+    use crate::conn::input::recording::Recording;
+
+    fn recording() -> Recording {
+        Recording::new(Display::fixed(
+            DisplayLayout::fallback(1920, 1080),
+            "",
+            (1920, 1080),
+        ))
+    }
+
+    fn event(action: &str, code: &str, modifiers: &str) -> KeyEvent {
+        let message = format!(
+            r#"{{"payload": {{"action": "{action}", "code": "{code}", "modifiers": {{{modifiers}}}}}}}"#
+        );
+
+        KeyEvent::parse(message.as_bytes()).unwrap()
+    }
+
+    fn key(code: &str) -> Key {
+        key_of(code).unwrap()
+    }
+
+    #[test]
+    fn presses_keys_once() {
+        let recording = recording();
+        let mut keyboard = Keyboard::new(recording.handle());
+
+        keyboard.apply(event("down", "KeyA", ""));
+        // Repeated by the browser, and by the host on its own.
+        keyboard.apply(event("down", "KeyA", ""));
+        keyboard.apply(event("up", "KeyA", ""));
+        keyboard.apply(event("up", "KeyA", ""));
+        keyboard.apply(event("down", "NoSuchKey", ""));
+
+        assert_eq!(
+            recording.take(),
+            [InputOp::KeyDown(key("KeyA")), InputOp::KeyUp(key("KeyA"))]
+        );
+    }
+
+    #[test]
+    fn follows_the_modifiers_of_the_peer() {
+        let recording = recording();
+        let mut keyboard = Keyboard::new(recording.handle());
+
+        // The peer missed pressing control, then releasing it.
+        keyboard.apply(event("down", "KeyC", r#""ctrl": true"#));
+        keyboard.apply(event("up", "KeyC", ""));
+        // The event itself presses shift.
+        keyboard.apply(event("down", "ShiftRight", r#""shift": true"#));
+
+        assert_eq!(
+            recording.take(),
+            [
+                InputOp::KeyDown(key("ControlLeft")),
+                InputOp::KeyDown(key("KeyC")),
+                InputOp::KeyUp(key("ControlLeft")),
+                InputOp::KeyUp(key("KeyC")),
+                InputOp::KeyDown(key("ShiftRight")),
+            ]
+        );
+    }
+
+    #[test]
+    fn releases_held_keys_in_reverse() {
+        let recording = recording();
+        let mut keyboard = Keyboard::new(recording.handle());
+
+        keyboard.apply(event("down", "AltLeft", r#""alt": true"#));
+        keyboard.apply(event("down", "Tab", r#""alt": true"#));
+        keyboard.release_all();
+
+        assert_eq!(
+            recording.take(),
+            [
+                InputOp::KeyDown(key("AltLeft")),
+                InputOp::KeyDown(key("Tab")),
+                InputOp::KeyUp(key("Tab")),
+                InputOp::KeyUp(key("AltLeft")),
+            ]
+        );
+    }
+}
@@ -1,16 +1,30 @@
+mod clipboard;
+pub mod display;
 mod ffmpeg;
+mod file_transfer;
+mod gamepad;
+pub mod host;
+pub mod input;
+mod keyboard;
+pub mod protocol;
 mod utils;
 pub mod ws;
 
+use rocket::serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{Notify, RwLock};
-use webrtc::api::media_engine::MIME_TYPE_H264;
-use webrtc::api::API;
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_H264};
+use webrtc::api::{APIBuilder, API};
 use webrtc::data_channel::data_channel_message::DataChannelMessage;
 use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
 use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
 use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::interceptor::registry::Registry;
 use webrtc::peer_connection::configuration::RTCConfiguration;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
@@ -23,14 +37,111 @@ use webrtc::rtcp::{
     receiver_report::ReceiverReport,
 };
 use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::stats::StatsReportType;
 use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
 use webrtc::track::track_local::TrackLocal;
 
+use crate::config::LandlordConfig;
+use crate::conn::gamepad::Gamepads;
+use crate::conn::host::Host;
+use crate::conn::input::{MouseAction, Pointer};
+use crate::conn::keyboard::{KeyEvent, Keyboard};
+use crate::conn::protocol::{Feature, NackCode};
+use crate::events::EventPublisher;
+use crate::metrics::METRICS;
+
+/// Builds the WebRTC API shared by every peer of a connection manager.
+pub fn build_api() -> API {
+    let mut engine = MediaEngine::default();
+
+    engine
+        .register_default_codecs()
+        .expect("Unable to register default codecs.");
+
+    let mut registry = Registry::new();
+
+    registry = register_default_interceptors(registry, &mut engine)
+        .expect("Unable to register default interceptors.");
+
+    APIBuilder::new()
+        .with_media_engine(engine)
+        .with_interceptor_registry(registry)
+        .build()
+}
+
+#[derive(Clone)]
 pub enum ConnectionStatus {
     ControlRelease(String),
     ControlTake(String),
     Connected(String),
     Disconnected(String),
+    LocalCandidate(String, RTCIceCandidateInit),
+    CandidatesGathered(String),
+}
+
+impl ConnectionStatus {
+    /// The peer the status is about.
+    pub fn uuid(&self) -> &str {
+        match self {
+            ConnectionStatus::ControlRelease(uuid)
+            | ConnectionStatus::ControlTake(uuid)
+            | ConnectionStatus::Connected(uuid)
+            | ConnectionStatus::Disconnected(uuid)
+            | ConnectionStatus::LocalCandidate(uuid, _)
+            | ConnectionStatus::CandidatesGathered(uuid) => uuid,
+        }
+    }
+}
+
+/// How a peer should be negotiated by [`AetherWebRTCConnectionManager::connect`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConnectOptions {
+    /// Return the answer before ICE gathering completes and report local
+    /// candidates through [`ConnectionStatus::LocalCandidate`].
+    pub trickle: bool,
+    /// The peer only watches the screen and is never handed control.
+    pub view_only: bool,
+}
+
+/// A request on a peer that the manager refused, with the reason reported
+/// back to the signaling server.
+#[derive(Debug)]
+pub struct PeerError {
+    pub code: NackCode,
+    pub detail: String,
+}
+
+impl PeerError {
+    pub fn new(code: NackCode, detail: impl Into<String>) -> Self {
+        Self {
+            code,
+            detail: detail.into(),
+        }
+    }
+
+    /// The code of `error` if it is a [`PeerError`], [`NackCode::Internal`]
+    /// otherwise.
+    pub fn code_of(error: &anyhow::Error) -> NackCode {
+        error
+            .downcast_ref::<PeerError>()
+            .map_or(NackCode::Internal, |e| e.code)
+    }
+}
+
+impl std::fmt::Display for PeerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.detail)
+    }
+}
+
+impl std::error::Error for PeerError {}
+
+/// Result of [`AetherWebRTCConnectionManager::connect`].
+pub struct PeerAnswer {
+    pub description: RTCSessionDescription,
+    /// TURN servers along with the credentials issued to this peer, to be
+    /// handed over to it with the answer.
+    pub ice_servers: Vec<RTCIceServer>,
 }
 
 pub struct AetherPeerConnection {
@@ -39,70 +150,294 @@ pub struct AetherPeerConnection {
     pub ntfy: Sender<()>,
 
     has_controls: bool,
-    state_sender: Sender<ConnectionStatus>,
+    /// Notified whenever the peer takes control, so that it gets the host
+    /// clipboard right away.
+    control_taken: Arc<Notify>,
+    view_only: bool,
+    connected_at: SystemTime,
+    /// Latest REMB estimate of the peer, in bits per second.
+    estimated_bitrate: Arc<AtomicU64>,
+    pointer: Pointer,
+    keyboard: Keyboard,
+    gamepads: Gamepads,
+    events: EventPublisher,
 }
 
 impl AetherPeerConnection {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         peer_connection: Arc<RTCPeerConnection>,
         uuid: String,
         ntfy: Sender<()>,
-        sender: Sender<ConnectionStatus>,
+        events: EventPublisher,
+        view_only: bool,
+        estimated_bitrate: Arc<AtomicU64>,
+        pointer: Pointer,
+        keyboard: Keyboard,
+        gamepads: Gamepads,
     ) -> Self {
         Self {
             peer_connection,
             uuid,
             ntfy,
             has_controls: false,
-            state_sender: sender,
+            control_taken: Arc::new(Notify::new()),
+            view_only,
+            connected_at: SystemTime::now(),
+            estimated_bitrate,
+            pointer,
+            keyboard,
+            gamepads,
+            events,
         }
     }
 
     async fn take_control(&mut self) -> anyhow::Result<()> {
-        self.state_sender
-            .send(ConnectionStatus::ControlTake(self.uuid.clone()))
-            .await?;
+        self.events
+            .publish(ConnectionStatus::ControlTake(self.uuid.clone()));
+        METRICS.control_changed();
         self.has_controls = true;
+        self.control_taken.notify_one();
         Ok(())
     }
 
     async fn release_control(&mut self) -> anyhow::Result<()> {
-        self.state_sender
-            .send(ConnectionStatus::ControlRelease(self.uuid.clone()))
-            .await?;
+        self.events
+            .publish(ConnectionStatus::ControlRelease(self.uuid.clone()));
+        self.pointer.release_all();
+        self.keyboard.release_all();
+        self.gamepads.release_all();
         self.has_controls = false;
         Ok(())
     }
 
+    /// Requests the teardown of the peer, which is carried out by the watcher
+    /// spawned in [`AetherWebRTCConnectionManager::connect`]. A full channel
+    /// means a teardown is already pending.
     async fn disconnect(&mut self) -> anyhow::Result<()> {
+        let _ = self.ntfy.try_send(());
+        Ok(())
+    }
+
+    async fn announce_disconnection(&mut self) -> anyhow::Result<()> {
         if self.has_controls {
             self.release_control().await?
         }
 
-        self.state_sender
-            .send(ConnectionStatus::Disconnected(self.uuid.clone()))
-            .await?;
+        self.events
+            .publish(ConnectionStatus::Disconnected(self.uuid.clone()));
 
-        self.ntfy.send(()).await?;
         Ok(())
     }
 
     async fn connect(&self) -> anyhow::Result<()> {
-        self.state_sender
-            .send(ConnectionStatus::Connected(self.uuid.clone()))
-            .await?;
+        self.events
+            .publish(ConnectionStatus::Connected(self.uuid.clone()));
         Ok(())
     }
 }
 
+/// Clones share the screen source and the peers, and keep the features in
+/// use when cloned.
+#[derive(Clone)]
 pub struct AetherWebRTCConnectionManager {
     screen_track: Arc<RwLock<Option<Arc<TrackLocalStaticSample>>>>,
-    rtc_configuration: RTCConfiguration,
-    api: API,
+    api: Arc<API>,
+    config: Arc<LandlordConfig>,
+    /// Optional features in use, all the supported ones unless a signaling
+    /// server enabled fewer.
+    features: Vec<Feature>,
 
-    state_watcher: Sender<ConnectionStatus>,
+    events: EventPublisher,
+    host: Host,
+
+    peers: PeerSet,
+}
+
+/// What the local administrator gets to see of a peer.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PeerInfo {
+    pub uuid: String,
+    /// State of the peer connection, such as `connected` or `disconnected`.
+    pub state: String,
+    pub has_control: bool,
+    pub view_only: bool,
+    /// Unix timestamp of the answer to the peer, in seconds.
+    pub connected_at: u64,
+    /// ICE candidates in use, once a pair was selected.
+    pub selected_candidate_pair: Option<String>,
+}
+
+/// Quality of the link to a peer, as exported on `/metrics`.
+pub struct PeerLinkStats {
+    pub uuid: String,
+    /// Latest round-trip time reported by the peer, in seconds.
+    pub round_trip_time: Option<f64>,
+    pub fraction_lost: Option<f64>,
+    /// Latest REMB estimate of the peer, in bits per second.
+    pub estimated_bitrate: Option<f64>,
+}
 
+/// The peers of a connection manager. Clones share the same peers, so the
+/// set can be handed out to act on peers without going through the manager.
+#[derive(Clone, Default)]
+pub struct PeerSet {
     peers: Arc<RwLock<Vec<Arc<RwLock<AetherPeerConnection>>>>>,
+    /// Set once control was revoked from everyone, so that no peer takes it
+    /// back on its own until it is handed over again.
+    control_locked: Arc<AtomicBool>,
+}
+
+impl std::ops::Deref for PeerSet {
+    type Target = Arc<RwLock<Vec<Arc<RwLock<AetherPeerConnection>>>>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.peers
+    }
+}
+
+impl PeerSet {
+    pub async fn change_control_to(&self, uuid: String) -> anyhow::Result<()> {
+        let target = peer_utils::fetch_peer_by_uuid(self, uuid.clone())
+            .await
+            .ok_or_else(|| unknown_peer(&uuid))?;
+
+        if target.read().await.view_only {
+            return Err(PeerError::new(
+                NackCode::NotControllable,
+                format!("Peer '{uuid}' is view-only."),
+            )
+            .into());
+        }
+
+        self.control_locked.store(false, Ordering::SeqCst);
+
+        let snapshot = self.read().await.clone();
+
+        for peer in snapshot.iter() {
+            let mut peer_w = peer.write().await;
+            if peer_w.uuid == uuid {
+                let _ = peer_w.take_control().await;
+            } else if peer_w.has_controls {
+                let _ = peer_w.release_control().await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes control away from every peer until it is handed over again.
+    pub async fn revoke_control(&self) {
+        self.control_locked.store(true, Ordering::SeqCst);
+
+        if let Some(peer) = peer_utils::fetch_peer_in_control(self).await {
+            let _ = peer.write().await.release_control().await;
+        }
+    }
+
+    fn control_locked(&self) -> bool {
+        self.control_locked.load(Ordering::SeqCst)
+    }
+
+    pub async fn disconnect_peer(&self, uuid: String) -> anyhow::Result<()> {
+        let peer = peer_utils::fetch_peer_by_uuid(self, uuid.clone())
+            .await
+            .ok_or_else(|| unknown_peer(&uuid))?;
+
+        peer.write().await.disconnect().await?;
+
+        Ok(())
+    }
+
+    pub async fn uuids(&self) -> Vec<String> {
+        let mut uuids = vec![];
+        let snapshot = self.read().await.clone();
+
+        for peer in snapshot.iter() {
+            uuids.push(peer.read().await.uuid.clone());
+        }
+
+        uuids
+    }
+
+    pub async fn controller(&self) -> Option<String> {
+        match peer_utils::fetch_peer_in_control(self).await {
+            Some(peer) => Some(peer.read().await.uuid.clone()),
+            None => None,
+        }
+    }
+
+    pub async fn link_stats(&self) -> Vec<PeerLinkStats> {
+        let mut links = vec![];
+        let snapshot = self.read().await.clone();
+
+        for peer in snapshot.iter() {
+            let (uuid, peer_connection, estimated_bitrate) = {
+                let peer = peer.read().await;
+                (
+                    peer.uuid.clone(),
+                    peer.peer_connection.clone(),
+                    peer.estimated_bitrate.load(Ordering::Relaxed),
+                )
+            };
+
+            let remote_video = peer_connection
+                .get_stats()
+                .await
+                .reports
+                .into_values()
+                .find_map(|report| match report {
+                    StatsReportType::RemoteInboundRTP(stats) if stats.kind == "video" => {
+                        Some(stats)
+                    }
+                    _ => None,
+                });
+
+            links.push(PeerLinkStats {
+                uuid,
+                round_trip_time: remote_video
+                    .as_ref()
+                    .and_then(|stats| stats.round_trip_time),
+                fraction_lost: remote_video.map(|stats| stats.fraction_lost),
+                estimated_bitrate: (estimated_bitrate > 0).then_some(estimated_bitrate as f64),
+            });
+        }
+
+        links
+    }
+
+    pub async fn describe(&self) -> Vec<PeerInfo> {
+        let mut infos = vec![];
+        let snapshot = self.read().await.clone();
+
+        for peer in snapshot.iter() {
+            let peer = peer.read().await;
+
+            let selected_candidate_pair = peer
+                .peer_connection
+                .dtls_transport()
+                .ice_transport()
+                .get_selected_candidate_pair()
+                .await
+                .map(|pair| pair.to_string());
+
+            infos.push(PeerInfo {
+                uuid: peer.uuid.clone(),
+                state: peer.peer_connection.connection_state().to_string(),
+                has_control: peer.has_controls,
+                view_only: peer.view_only,
+                connected_at: peer
+                    .connected_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                selected_candidate_pair,
+            });
+        }
+
+        infos
+    }
 }
 
 mod peer_utils {
@@ -114,9 +449,11 @@ mod peer_utils {
         peers: &Arc<RwLock<Vec<Arc<RwLock<AetherPeerConnection>>>>>,
         uuid: String,
     ) -> Option<Arc<RwLock<AetherPeerConnection>>> {
-        for peer in peers.read().await.clone().into_iter() {
+        let snapshot = peers.read().await.clone();
+
+        for peer in snapshot.into_iter() {
             if peer.read().await.uuid == uuid {
-                return Some(peer.clone());
+                return Some(peer);
             }
         }
 
@@ -127,19 +464,19 @@ mod peer_utils {
         peers: &Arc<RwLock<Vec<Arc<RwLock<AetherPeerConnection>>>>>,
         uuid: String,
     ) {
-        for (n, peer) in peers.read().await.clone().into_iter().enumerate() {
-            if peer.read().await.uuid == uuid {
-                peers.write().await.remove(n);
-            }
+        if let Some(peer) = fetch_peer_by_uuid(peers, uuid).await {
+            peers.write().await.retain(|p| !Arc::ptr_eq(p, &peer));
         }
     }
 
     pub(super) async fn fetch_peer_in_control(
         peers: &Arc<RwLock<Vec<Arc<RwLock<AetherPeerConnection>>>>>,
     ) -> Option<Arc<RwLock<AetherPeerConnection>>> {
-        for peer in peers.read().await.clone().into_iter() {
+        let snapshot = peers.read().await.clone();
+
+        for peer in snapshot.into_iter() {
             if peer.read().await.has_controls {
-                return Some(peer.clone());
+                return Some(peer);
             }
         }
 
@@ -147,52 +484,94 @@ mod peer_utils {
     }
 }
 
+fn unknown_peer(uuid: &str) -> anyhow::Error {
+    PeerError::new(
+        NackCode::UnknownPeer,
+        format!("No peer is registered as '{uuid}'."),
+    )
+    .into()
+}
+
 impl AetherWebRTCConnectionManager {
-    pub fn new(api: webrtc::api::API, state_watcher: Sender<ConnectionStatus>) -> Self {
+    pub fn new(
+        api: webrtc::api::API,
+        events: EventPublisher,
+        host: Host,
+        config: Arc<LandlordConfig>,
+        peers: PeerSet,
+    ) -> Self {
         Self {
             screen_track: RwLock::new(None).into(),
-            rtc_configuration: RTCConfiguration {
-                ice_servers: vec![RTCIceServer {
-                    urls: vec!["stun:stun.l.google.com:19302".to_owned()],
-                    ..Default::default()
-                }],
-                ..Default::default()
-            },
-            state_watcher: state_watcher,
-            api,
-            peers: RwLock::new(vec![]).into(),
+            events,
+            host,
+            api: api.into(),
+            config,
+            features: Feature::supported(),
+            peers,
         }
     }
 
-    async fn change_control_to(&self, uuid: String) {
-        for peer in self.peers.write().await.iter() {
-            let mut peer_w = peer.write().await;
-            if peer_w.uuid == uuid {
-                let _ = peer_w.take_control().await;
-            } else {
-                let _ = peer_w.release_control().await;
-            }
-        }
+    /// Restricts the optional features used with new peers to `features`.
+    pub fn set_features(&mut self, features: Vec<Feature>) {
+        self.features = features;
     }
 
-    async fn disconnect_peer(&self, uuid: String) -> anyhow::Result<()> {
-        for peer in self.peers.write().await.iter() {
-            let mut peer_w = peer.write().await;
-            if peer_w.uuid == uuid {
-                let _ = peer_w.disconnect().await?;
-            }
+    pub fn has_feature(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    async fn change_control_to(&self, uuid: String) -> anyhow::Result<()> {
+        self.peers.change_control_to(uuid).await
+    }
+
+    pub async fn disconnect_peer(&self, uuid: String) -> anyhow::Result<()> {
+        self.peers.disconnect_peer(uuid).await
+    }
+
+    pub async fn peer_uuids(&self) -> Vec<String> {
+        self.peers.uuids().await
+    }
+
+    async fn controller(&self) -> Option<String> {
+        self.peers.controller().await
+    }
+
+    async fn disconnect_all(&self) {
+        for uuid in self.peer_uuids().await {
+            let _ = self.disconnect_peer(uuid).await;
         }
+    }
+
+    pub async fn add_remote_candidate(
+        &self,
+        uuid: String,
+        candidate: RTCIceCandidateInit,
+    ) -> anyhow::Result<()> {
+        let peer = peer_utils::fetch_peer_by_uuid(&self.peers, uuid.clone())
+            .await
+            .ok_or_else(|| unknown_peer(&uuid))?;
+
+        let peer_connection = peer.read().await.peer_connection.clone();
+        peer_connection.add_ice_candidate(candidate).await?;
 
         Ok(())
     }
 
     async fn create_peer(
-        &mut self,
+        &self,
         screen_track: Arc<TrackLocalStaticSample>,
+        turn_servers: Vec<RTCIceServer>,
+        estimated_bitrate: Arc<AtomicU64>,
     ) -> anyhow::Result<RTCPeerConnection> {
+        let mut ice_servers = self.config.ice.rtc_ice_servers();
+        ice_servers.extend(turn_servers);
+
         let peer = self
             .api
-            .new_peer_connection(self.rtc_configuration.clone())
+            .new_peer_connection(RTCConfiguration {
+                ice_servers,
+                ..Default::default()
+            })
             .await?;
 
         let rtp_sender = peer
@@ -204,8 +583,10 @@ impl AetherWebRTCConnectionManager {
             while let Ok((packets, _)) = rtp_sender.read(&mut rtcp_buf).await {
                 for packet in packets {
                     if let Some(pil) = packet.as_any().downcast_ref::<PictureLossIndication>() {
+                        METRICS.picture_loss();
                         warn!("PIL obtained: {:?}", pil)
                     } else if let Some(fir) = packet.as_any().downcast_ref::<FullIntraRequest>() {
+                        METRICS.full_intra_request();
                         warn!("FIR obtained: {:?}", fir)
                     } else if let Some(report) = packet.as_any().downcast_ref::<ReceiverReport>() {
                         if let Some(f) = report.reports.first() {
@@ -215,6 +596,7 @@ impl AetherWebRTCConnectionManager {
                         .as_any()
                         .downcast_ref::<ReceiverEstimatedMaximumBitrate>()
                     {
+                        estimated_bitrate.store(bitrate.bitrate as u64, Ordering::Relaxed);
                         info!("Estimated bitrate: {:.02}k", bitrate.bitrate / 1000_f32)
                     } else {
                         warn!("Unknown RTCP packet received.")
@@ -227,7 +609,15 @@ impl AetherWebRTCConnectionManager {
         Ok(peer)
     }
 
-    async fn set_screen_source(&self, notifier: Arc<Notify>, codec: &'static str) {
+    /// Starts capturing the screen for a new track, once `notifier` fires.
+    fn start_screen_source(
+        &self,
+        notifier: Arc<Notify>,
+        codec: &'static str,
+    ) -> anyhow::Result<Arc<TrackLocalStaticSample>> {
+        ffmpeg::check_available()
+            .map_err(|e| PeerError::new(NackCode::CaptureUnavailable, e.to_string()))?;
+
         let screen_track = Arc::new(TrackLocalStaticSample::new(
             RTCRtpCodecCapability {
                 mime_type: codec.into(),
@@ -237,65 +627,175 @@ impl AetherWebRTCConnectionManager {
             "aether-rtc-screen".to_owned(),
         ));
 
-        self.screen_track
-            .write()
-            .await
-            .replace(screen_track.clone());
-
+        let source_track = screen_track.clone();
         let track_copy = self.screen_track.clone();
 
-        let peers_copy = self.peers.clone();
+        let peers_copy = (*self.peers).clone();
+        let capture = self.config.capture.clone();
+        let mut display = self.host.input.display().clone();
 
         tokio::spawn(async move {
             notifier.notified().await;
 
-            let mut ffmpeg_process = std::process::Command::new("ffmpeg")
-                .args(ffmpeg::get_ffmpeg_command())
-                .stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::null())
-                .spawn()
-                .expect("Unable to open ffmpeg, is it even in PATH?");
+            // The frame size stays the same whatever the region, so a capture
+            // of the new layout keeps feeding the same track.
+            loop {
+                let ffmpeg_command = ffmpeg::get_ffmpeg_command(&capture, codec, display.region());
+
+                let mut ffmpeg_process = match std::process::Command::new("ffmpeg")
+                    .args(ffmpeg_command)
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::null())
+                    .spawn()
+                {
+                    Ok(process) => process,
+                    Err(e) => {
+                        error!("Unable to open ffmpeg: {e}");
+                        METRICS.ffmpeg_failed();
+                        break;
+                    }
+                };
+
+                let reader = ffmpeg_process
+                    .stdout
+                    .take()
+                    .expect("Unable to access stdout, is it piped properly?");
+
+                METRICS.ffmpeg_started();
+                info!("Creating '{codec}' source for screen tracks.");
+
+                let player = async {
+                    if codec == MIME_TYPE_H264 {
+                        utils::h264_player_from(screen_track.clone(), peers_copy.clone(), reader)
+                            .await;
+                    } else {
+                        utils::ivf_player_from(screen_track.clone(), peers_copy.clone(), reader)
+                            .await;
+                    }
+                };
+
+                let relayout = tokio::select! {
+                    _ = player => false,
+                    _ = display.changed() => true,
+                };
 
-            let reader = ffmpeg_process
-                .stdout
-                .take()
-                .expect("Unable to access stdout, is it piped properly?");
+                let watched = !peers_copy.read().await.is_empty();
 
-            info!("Creating '{codec}' source for screen tracks.");
+                ffmpeg_process.kill().unwrap_or_default();
+                let status = ffmpeg_process.wait();
 
-            if codec == MIME_TYPE_H264 {
-                utils::h264_player_from(screen_track, peers_copy, reader).await;
-            } else {
-                utils::ivf_player_from(screen_track, peers_copy, reader).await;
+                // Stopped on purpose unless peers still watch a source that
+                // ended on its own, which it only does cleanly by exiting.
+                if !relayout && watched && !status.as_ref().is_ok_and(|s| s.success()) {
+                    warn!("'{codec}' source failed: {status:?}");
+                    METRICS.ffmpeg_failed();
+                }
+
+                if !relayout {
+                    info!("'{codec}' source exhausted.");
+                    break;
+                }
+
+                info!("Display layout changed, restarting the '{codec}' source.");
             }
-            info!("'{codec}' source exhausted.");
 
             let _ = track_copy.write().await.take();
-            ffmpeg_process.kill().unwrap_or_default();
         });
+
+        Ok(source_track)
     }
 
+    /// Answers `offer` for the peer identified by `uuid`.
+    ///
+    /// In trickle mode the answer is returned as soon as it is applied and
+    /// local candidates are reported through [`ConnectionStatus::LocalCandidate`]
+    /// followed by [`ConnectionStatus::CandidatesGathered`]. Otherwise the call
+    /// waits for gathering to complete and the answer embeds every candidate.
+    /// Trickle mode is only honoured while [`Feature::TrickleIce`] is enabled.
+    ///
+    /// Credentials for the configured TURN servers are issued to the peer and
+    /// used on both ends of the connection.
     pub async fn connect(
-        &mut self,
+        &self,
         offer: RTCSessionDescription,
         uuid: String,
-    ) -> anyhow::Result<RTCSessionDescription> {
-        let codec = utils::get_preferred_codec();
+        options: ConnectOptions,
+    ) -> anyhow::Result<PeerAnswer> {
+        let mut registered = None;
+        let answer = self.negotiate(offer, uuid, options, &mut registered).await;
+
+        if let Err(e) = &answer {
+            METRICS.negotiation_failed(PeerError::code_of(e));
+
+            // The peer may be registered before the negotiation fails. Only
+            // this one goes, not another peer under the same uuid.
+            if let Some(peer) = registered {
+                let _ = peer.read().await.peer_connection.close().await;
+                self.peers.write().await.retain(|p| !Arc::ptr_eq(p, &peer));
+            }
+        }
 
-        let ntfy = Arc::new(Notify::new());
+        answer
+    }
 
-        if self.screen_track.read().await.is_none() {
-            self.set_screen_source(ntfy.clone(), codec).await
+    async fn negotiate(
+        &self,
+        offer: RTCSessionDescription,
+        uuid: String,
+        options: ConnectOptions,
+        registered: &mut Option<Arc<RwLock<AetherPeerConnection>>>,
+    ) -> anyhow::Result<PeerAnswer> {
+        if peer_utils::fetch_peer_by_uuid(&self.peers, uuid.clone())
+            .await
+            .is_some()
+        {
+            return Err(PeerError::new(
+                NackCode::DuplicatePeer,
+                format!("A peer is already registered as '{uuid}'."),
+            )
+            .into());
         }
 
-        let screen_track = self
-            .screen_track
-            .read()
-            .await
-            .clone()
-            .expect("Unable to load track after an expected load.");
+        let trickle = options.trickle && self.has_feature(Feature::TrickleIce);
+
+        let max_peers = self.config.peers.max;
+
+        if self.peers.read().await.len() >= max_peers {
+            return Err(PeerError::new(
+                NackCode::CapacityReached,
+                format!("The host already serves {max_peers} peers."),
+            )
+            .into());
+        }
+
+        let codec = utils::get_preferred_codec(&self.config.capture);
+
+        let ntfy = Arc::new(Notify::new());
+
+        // Locked until the track is set, so that concurrent negotiations
+        // start a single source.
+        let screen_track = {
+            let mut screen_track = self.screen_track.write().await;
+
+            match &*screen_track {
+                Some(track) => track.clone(),
+                None => screen_track
+                    .insert(self.start_screen_source(ntfy.clone(), codec)?)
+                    .clone(),
+            }
+        };
 
-        let peer = self.create_peer(screen_track).await?;
+        let turn_servers = self.config.ice.turn_servers_for(&uuid);
+
+        let estimated_bitrate = Arc::new(AtomicU64::new(0));
+
+        let peer = self
+            .create_peer(
+                screen_track,
+                turn_servers.clone(),
+                estimated_bitrate.clone(),
+            )
+            .await?;
 
         let (done_tx, mut done_rx) = tokio::sync::mpsc::channel::<()>(1);
 
@@ -303,7 +803,12 @@ impl AetherWebRTCConnectionManager {
             peer.into(),
             uuid,
             done_tx.clone(),
-            self.state_watcher.clone(),
+            self.events.clone(),
+            options.view_only,
+            estimated_bitrate,
+            Pointer::new(self.host.input.clone()),
+            Keyboard::new(self.host.input.clone()),
+            Gamepads::new(self.host.gamepads.clone()),
         )));
 
         let ice_nfty = ntfy.clone();
@@ -338,9 +843,12 @@ impl AetherWebRTCConnectionManager {
             .on_peer_connection_state_change(Box::new(
                 move |connection_state: RTCPeerConnectionState| {
                     match connection_state {
-                        RTCPeerConnectionState::Failed
-                        | RTCPeerConnectionState::Disconnected
-                        | RTCPeerConnectionState::Closed => {
+                        RTCPeerConnectionState::Connected => METRICS.connection_established(),
+                        RTCPeerConnectionState::Failed => {
+                            METRICS.ice_failed();
+                            let _ = done_tx.try_send(());
+                        }
+                        RTCPeerConnectionState::Disconnected | RTCPeerConnectionState::Closed => {
                             let _ = done_tx.try_send(());
                         }
                         _ => {}
@@ -351,11 +859,20 @@ impl AetherWebRTCConnectionManager {
             ));
 
         self.peers.write().await.push(associated_peer.clone());
+        registered.replace(associated_peer.clone());
 
         let peer_list_copy = self.peers.clone();
         let inner_peer = associated_peer.clone();
 
         let channel_ntfy = ntfy.clone();
+        let keyboard = self.has_feature(Feature::Keyboard);
+        let clipboard = self.host.clipboard.clone();
+        let files = self
+            .host
+            .files
+            .clone()
+            .filter(|_| self.has_feature(Feature::FileTransfer));
+        let gamepads = self.host.gamepads.is_some();
 
         auxilliary_peer_read
             .peer_connection
@@ -364,8 +881,34 @@ impl AetherWebRTCConnectionManager {
                 let peer_list_copy = peer_list_copy.clone();
 
                 let channel_ntfy = channel_ntfy.clone();
+                let clipboard = clipboard.clone();
+                let files = files.clone();
 
                 Box::pin(async move {
+                    if datachannel.label() == "clipboard" {
+                        if let Some(clipboard) = clipboard {
+                            clipboard::serve(datachannel, inner_peer, peer_list_copy, clipboard);
+                        }
+
+                        return;
+                    }
+
+                    if datachannel.label() == "file_transfer" {
+                        if let Some(files) = files {
+                            file_transfer::serve(datachannel, inner_peer, peer_list_copy, files);
+                        }
+
+                        return;
+                    }
+
+                    if datachannel.label() == "gamepad_events" {
+                        if gamepads {
+                            gamepad::serve(datachannel, inner_peer, peer_list_copy);
+                        }
+
+                        return;
+                    }
+
                     datachannel.on_close(Box::new(move || Box::pin(async {})));
                     datachannel.on_open(Box::new(move || Box::pin(async {})));
 
@@ -373,38 +916,30 @@ impl AetherWebRTCConnectionManager {
                     let channel_ntfy = channel_ntfy.clone();
 
                     datachannel.on_message(Box::new(move |msg: DataChannelMessage| {
-                        let mut expected_mouse_ctrl = None;
+                        let mut mouse_action = None;
+                        let mut key_event = None;
 
                         match channel.label() {
-                            "mouse_events" => {
-                                if let Ok(message) =
-                                    serde_json::from_slice::<serde_json::Value>(&msg.data.to_vec())
-                                {
-                                    let (window_width, window_height) = (1920usize, 1080usize);
-
-                                    let clicked_at = &message["payload"]["clicked_at"];
-
-                                    if let (Some(x), Some(y)) = (
-                                        clicked_at["x_ratio"].as_f64(),
-                                        clicked_at["y_ratio"].as_f64(),
-                                    ) {
-                                        expected_mouse_ctrl.replace((
-                                            (window_width as f64 * x) as i32,
-                                            (window_height as f64 * y) as i32,
-                                        ));
-                                    } else {
-                                        error!(
-                                            "Unable to resolve click position from: {:?}",
-                                            message
-                                        );
-                                    }
-                                } else {
+                            "mouse_events" => match MouseAction::parse(&msg.data) {
+                                Ok(action) => {
+                                    mouse_action.replace(action);
+                                }
+                                Err(e) => {
                                     error!(
-                                        "Unexpected value in the mouse events data channel: {:?}",
-                                        msg
+                                        "Unexpected value in the mouse events data channel: {e}"
                                     );
                                 }
-                            }
+                            },
+                            "keyboard_events" if keyboard => match KeyEvent::parse(&msg.data) {
+                                Ok(event) => {
+                                    key_event.replace(event);
+                                }
+                                Err(e) => {
+                                    error!(
+                                        "Unexpected value in the keyboard events data channel: {e}"
+                                    );
+                                }
+                            },
                             "signalled_closure" => {
                                 channel_ntfy.notify_waiters();
                             }
@@ -415,21 +950,50 @@ impl AetherWebRTCConnectionManager {
                         let peer_list_copy = peer_list_copy.clone();
 
                         Box::pin(async move {
-                            if let Some((x, y)) = expected_mouse_ctrl {
-                                if let Some(ctrl) =
-                                    peer_utils::fetch_peer_in_control(&peer_list_copy).await
-                                {
-                                    if ctrl.read().await.uuid == inner_peer.read().await.uuid {
-                                        let mouse = mouse_rs::Mouse::new();
-                                        let _ = mouse.move_to(x, y);
-                                        let _ = mouse.click(&mouse_rs::types::keys::Keys::LEFT);
-                                    }
+                            if let Some(event) = key_event {
+                                let in_control =
+                                    peer_utils::fetch_peer_in_control(&peer_list_copy)
+                                        .await
+                                        .is_some_and(|ctrl| Arc::ptr_eq(&ctrl, &inner_peer));
+
+                                let mut peer = inner_peer.write().await;
+
+                                if in_control {
+                                    peer.keyboard.apply(event);
                                 } else {
-                                    let _ = inner_peer.write().await.take_control().await;
-                                    let mouse = mouse_rs::Mouse::new();
-                                    let _ = mouse.move_to(x, y);
-                                    let _ = mouse.click(&mouse_rs::types::keys::Keys::LEFT);
+                                    debug!(
+                                        "Ignoring keyboard input of peer '{}', which is not in control.",
+                                        peer.uuid
+                                    );
                                 }
+
+                                return;
+                            }
+
+                            let Some(action) = mouse_action else {
+                                return;
+                            };
+
+                            let in_control =
+                                match peer_utils::fetch_peer_in_control(&peer_list_copy).await {
+                                    Some(ctrl) => Arc::ptr_eq(&ctrl, &inner_peer),
+                                    None => {
+                                        let mut peer = inner_peer.write().await;
+
+                                        let claimed = action.claims_control()
+                                            && !peer.view_only
+                                            && !peer_list_copy.control_locked();
+
+                                        if claimed {
+                                            let _ = peer.take_control().await;
+                                        }
+
+                                        claimed
+                                    }
+                                };
+
+                            if in_control {
+                                inner_peer.write().await.pointer.apply(action);
                             }
                         })
                     }));
@@ -439,7 +1003,33 @@ impl AetherWebRTCConnectionManager {
         auxilliary_peer_read
             .peer_connection
             .set_remote_description(offer)
-            .await?;
+            .await
+            .map_err(|e| PeerError::new(NackCode::BadSdp, e.to_string()))?;
+
+        if trickle {
+            let candidate_uuid = auxilliary_peer_read.uuid.clone();
+            let candidate_events = self.events.clone();
+
+            auxilliary_peer_read
+                .peer_connection
+                .on_ice_candidate(Box::new(move |candidate: Option<RTCIceCandidate>| {
+                    let uuid = candidate_uuid.clone();
+                    let candidate_events = candidate_events.clone();
+
+                    Box::pin(async move {
+                        let status = match candidate.map(|c| c.to_json()) {
+                            Some(Ok(init)) => ConnectionStatus::LocalCandidate(uuid, init),
+                            Some(Err(e)) => {
+                                error!("Unable to serialize local candidate: {e}");
+                                return;
+                            }
+                            None => ConnectionStatus::CandidatesGathered(uuid),
+                        };
+
+                        candidate_events.publish(status);
+                    })
+                }));
+        }
 
         let answer = auxilliary_peer_read
             .peer_connection
@@ -454,13 +1044,27 @@ impl AetherWebRTCConnectionManager {
             .peer_connection
             .set_local_description(answer.clone())
             .await?;
-        let _ = gather_complete.recv().await;
+
+        let answer = if trickle {
+            answer
+        } else {
+            let _ = gather_complete.recv().await;
+
+            auxilliary_peer_read
+                .peer_connection
+                .local_description()
+                .await
+                .unwrap_or(answer)
+        };
 
         auxilliary_peer_read.connect().await?;
+        drop(auxilliary_peer_read);
 
-        if peer_utils::fetch_peer_in_control(&self.peers)
-            .await
-            .is_none()
+        if !options.view_only
+            && !self.peers.control_locked()
+            && peer_utils::fetch_peer_in_control(&self.peers)
+                .await
+                .is_none()
         {
             let _ = associated_peer.write().await.take_control().await;
         }
@@ -472,17 +1076,51 @@ impl AetherWebRTCConnectionManager {
             tokio::select! {
                 _ = done_rx.recv() => {
                     let _ = watching_peer.read().await.peer_connection.close().await;
-                    let mut mut_associated_peer = watching_peer.write().await;
 
-                    if mut_associated_peer.has_controls {
-                        let _ = mut_associated_peer.release_control().await;
-                    }
-                    let _ = mut_associated_peer.disconnect().await;
-                    peer_utils::discard_peer_by_uuid(&peer_list, mut_associated_peer.uuid.clone()).await;
+                    let uuid = {
+                        let mut mut_associated_peer = watching_peer.write().await;
+                        let _ = mut_associated_peer.announce_disconnection().await;
+                        mut_associated_peer.uuid.clone()
+                    };
+
+                    peer_utils::discard_peer_by_uuid(&peer_list, uuid).await;
                 }
             };
         });
 
-        anyhow::Ok(answer)
+        anyhow::Ok(PeerAnswer {
+            description: answer,
+            ice_servers: turn_servers,
+        })
+    }
+}
+
+#[cfg(test)]
+impl AetherPeerConnection {
+    /// A peer that never connects, whose input goes nowhere.
+    async fn detached(uuid: &str) -> Arc<RwLock<Self>> {
+        use crate::conn::display::{Display, DisplayLayout};
+        use crate::conn::input::InputHandle;
+        use crate::events::EventBus;
+
+        let display = Display::fixed(DisplayLayout::fallback(1920, 1080), "", (1920, 1080));
+        let input = InputHandle::spawn(display, || anyhow::bail!("no input in tests"));
+
+        let peer_connection = build_api()
+            .new_peer_connection(RTCConfiguration::default())
+            .await
+            .unwrap();
+
+        Arc::new(RwLock::new(Self::new(
+            peer_connection.into(),
+            uuid.to_owned(),
+            tokio::sync::mpsc::channel(1).0,
+            EventBus::new(16).publisher(None),
+            false,
+            Arc::default(),
+            Pointer::new(input.clone()),
+            Keyboard::new(input),
+            Gamepads::new(None),
+        )))
     }
 }
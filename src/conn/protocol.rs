@@ -0,0 +1,617 @@
+use rocket::serde::{Deserialize, Serialize};
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+use crate::conn::ConnectionStatus;
+
+/// Major version of the landlord <-> server signaling protocol spoken by this
+/// build, carried by every frame. Frames of another major version are refused.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Minor version of the protocol, only exchanged in HELLO and WELCOME.
+pub const PROTOCOL_MINOR_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    MalformedFrame,
+    UnsupportedVersion,
+    UnexpectedMessage,
+    InvalidField,
+}
+
+/// Optional parts of the protocol, enabled for a session through HELLO and
+/// WELCOME.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Feature {
+    TrickleIce,
+    Audio,
+    Keyboard,
+    FileTransfer,
+    Recording,
+}
+
+impl Feature {
+    /// Features this build of the landlord implements.
+    pub fn supported() -> Vec<Feature> {
+        vec![
+            Feature::TrickleIce,
+            Feature::Keyboard,
+            Feature::FileTransfer,
+        ]
+    }
+}
+
+/// Why a CONNECTION, CONTROL or DISCONNECT request was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NackCode {
+    /// The offer is not a valid SDP offer or could not be applied.
+    BadSdp,
+    /// No peer is registered under the given uuid.
+    UnknownPeer,
+    /// A peer is already registered under the given uuid.
+    DuplicatePeer,
+    /// The peer only watches the screen and cannot take control.
+    NotControllable,
+    /// The screen cannot be captured on this host.
+    CaptureUnavailable,
+    /// The host already serves as many peers as it accepts.
+    CapacityReached,
+    /// Anything else, described by the `detail`.
+    Internal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct DisplaySpecification {
+    pub width: u32,
+    pub height: u32,
+    /// Refresh rate of the display, in Hz.
+    pub frame_rate: u32,
+}
+
+/// A processor or graphics adapter. `size` is the number of logical cores
+/// for a CPU and the amount of dedicated memory in bytes for a GPU, or 0
+/// when unknown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct DeviceEntry {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct DeviceSpecification {
+    pub cpu: Vec<DeviceEntry>,
+    pub gpu: Vec<DeviceEntry>,
+    /// Total system memory, in bytes.
+    #[serde(default)]
+    pub memory: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct HostSpecification {
+    pub display: DisplaySpecification,
+    /// Address used to reach the default route.
+    pub ip_addr: String,
+    /// Every routable address of the host, `ip_addr` included.
+    #[serde(default)]
+    pub ip_addrs: Vec<String>,
+    pub device: DeviceSpecification,
+}
+
+/// Every frame exchanged over the signaling websocket.
+///
+/// On the wire each frame is a JSON object carrying a `type` tag, the
+/// `version` of the protocol and the fields of the variant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    crate = "rocket::serde",
+    tag = "type",
+    rename_all = "SCREAMING_SNAKE_CASE",
+    deny_unknown_fields
+)]
+pub enum SignalingMessage {
+    // Handshake
+    /// First frame of every session, sent by the landlord.
+    Hello {
+        major: u16,
+        minor: u16,
+        features: Vec<Feature>,
+    },
+    /// Answer of the server to HELLO, with the features it enables among
+    /// the advertised ones.
+    Welcome {
+        major: u16,
+        minor: u16,
+        features: Vec<Feature>,
+    },
+
+    // Server -> landlord
+    Connection {
+        uuid: String,
+        sdp: String,
+        /// Whether the answer should be sent before ICE gathering completes,
+        /// with local candidates following as `ICE_CANDIDATE` messages.
+        #[serde(default)]
+        trickle: bool,
+    },
+    Control {
+        uuid: String,
+    },
+    Disconnect {
+        uuid: String,
+    },
+
+    // Landlord -> server
+    Specification {
+        message: HostSpecification,
+    },
+    ConnectionAck {
+        uuid: String,
+        answer: Box<RTCSessionDescription>,
+        /// TURN servers the browser should use, with credentials of its own.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        ice_servers: Vec<RTCIceServer>,
+    },
+    ConnectionNack {
+        uuid: String,
+        code: NackCode,
+        detail: String,
+    },
+    ControlAck {
+        uuid: String,
+    },
+    ControlNack {
+        uuid: String,
+        code: NackCode,
+        detail: String,
+    },
+    DisconnectAck {
+        uuid: String,
+    },
+    DisconnectNack {
+        uuid: String,
+        code: NackCode,
+        detail: String,
+    },
+    ConnectionMade {
+        uuid: String,
+    },
+    DisconnectionMade {
+        uuid: String,
+    },
+    ControlReleased {
+        uuid: String,
+    },
+    ControlTaken {
+        uuid: String,
+    },
+    /// Sent after reconnecting, describing the peers that survived the outage.
+    Resume {
+        peers: Vec<String>,
+        controller: Option<String>,
+    },
+
+    // Either direction
+    /// Trickled candidate for the peer `uuid`. Local candidates always follow
+    /// the matching `CONNECTION_ACK`.
+    IceCandidate {
+        uuid: String,
+        candidate: RTCIceCandidateInit,
+    },
+    /// No further candidates will be trickled for the peer `uuid`.
+    EndOfCandidates {
+        uuid: String,
+    },
+    Error {
+        code: ErrorCode,
+        detail: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolError {
+    pub code: ErrorCode,
+    pub detail: String,
+}
+
+impl ProtocolError {
+    fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            code,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.detail)
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<ProtocolError> for SignalingMessage {
+    fn from(error: ProtocolError) -> Self {
+        SignalingMessage::Error {
+            code: error.code,
+            detail: error.detail,
+        }
+    }
+}
+
+impl SignalingMessage {
+    /// Parses and validates a single text frame.
+    pub fn decode(frame: &str) -> Result<Self, ProtocolError> {
+        let mut value = serde_json::from_str::<serde_json::Value>(frame)
+            .map_err(|e| ProtocolError::new(ErrorCode::MalformedFrame, e.to_string()))?;
+
+        let object = value.as_object_mut().ok_or_else(|| {
+            ProtocolError::new(ErrorCode::MalformedFrame, "frame is not a JSON object")
+        })?;
+
+        let version = object
+            .remove("version")
+            .ok_or_else(|| ProtocolError::new(ErrorCode::MalformedFrame, "missing `version`"))?;
+
+        match version.as_u64() {
+            Some(version) if version == PROTOCOL_VERSION as u64 => {}
+            _ => {
+                return Err(ProtocolError::new(
+                    ErrorCode::UnsupportedVersion,
+                    format!("expected version {PROTOCOL_VERSION}, got {version}"),
+                ))
+            }
+        }
+
+        let message = serde_json::from_value::<Self>(value)
+            .map_err(|e| ProtocolError::new(ErrorCode::MalformedFrame, e.to_string()))?;
+
+        message.validate()?;
+
+        Ok(message)
+    }
+
+    /// Serializes the message, stamping it with the current protocol version.
+    pub fn encode(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("Signaling messages always serialize.");
+
+        if let Some(object) = value.as_object_mut() {
+            object.insert("version".to_owned(), PROTOCOL_VERSION.into());
+        }
+
+        value.to_string()
+    }
+
+    fn validate(&self) -> Result<(), ProtocolError> {
+        match self {
+            SignalingMessage::IceCandidate { uuid, candidate } => {
+                validate_uuid(uuid)?;
+
+                if candidate.candidate.trim().is_empty() {
+                    return Err(ProtocolError::new(
+                        ErrorCode::InvalidField,
+                        "`candidate` must not be empty, use END_OF_CANDIDATES instead",
+                    ));
+                }
+            }
+            // A bad `sdp` is answered with CONNECTION_NACK rather than ERROR.
+            SignalingMessage::Connection { uuid, .. }
+            | SignalingMessage::Control { uuid }
+            | SignalingMessage::Disconnect { uuid }
+            | SignalingMessage::EndOfCandidates { uuid }
+            | SignalingMessage::ConnectionAck { uuid, .. }
+            | SignalingMessage::ConnectionNack { uuid, .. }
+            | SignalingMessage::ControlAck { uuid }
+            | SignalingMessage::ControlNack { uuid, .. }
+            | SignalingMessage::DisconnectAck { uuid }
+            | SignalingMessage::DisconnectNack { uuid, .. }
+            | SignalingMessage::ConnectionMade { uuid }
+            | SignalingMessage::DisconnectionMade { uuid }
+            | SignalingMessage::ControlReleased { uuid }
+            | SignalingMessage::ControlTaken { uuid } => validate_uuid(uuid)?,
+            SignalingMessage::Resume { peers, controller } => {
+                for uuid in peers.iter().chain(controller) {
+                    validate_uuid(uuid)?;
+                }
+            }
+            SignalingMessage::Hello { .. }
+            | SignalingMessage::Welcome { .. }
+            | SignalingMessage::Specification { .. }
+            | SignalingMessage::Error { .. } => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_uuid(uuid: &str) -> Result<(), ProtocolError> {
+    if uuid.trim().is_empty() {
+        return Err(ProtocolError::new(
+            ErrorCode::InvalidField,
+            "`uuid` must not be empty",
+        ));
+    }
+
+    Ok(())
+}
+
+impl From<ConnectionStatus> for SignalingMessage {
+    fn from(status: ConnectionStatus) -> Self {
+        match status {
+            ConnectionStatus::Connected(uuid) => SignalingMessage::ConnectionMade { uuid },
+            ConnectionStatus::Disconnected(uuid) => SignalingMessage::DisconnectionMade { uuid },
+            ConnectionStatus::ControlRelease(uuid) => SignalingMessage::ControlReleased { uuid },
+            ConnectionStatus::ControlTake(uuid) => SignalingMessage::ControlTaken { uuid },
+            ConnectionStatus::LocalCandidate(uuid, candidate) => {
+                SignalingMessage::IceCandidate { uuid, candidate }
+            }
+            ConnectionStatus::CandidatesGathered(uuid) => {
+                SignalingMessage::EndOfCandidates { uuid }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const UUID: &str = "2f1d6c1e-7a8b-4c3d-9e0f-1a2b3c4d5e6f";
+
+    /// One message of every variant, with the `type` it goes by.
+    fn every_message() -> Vec<(&'static str, SignalingMessage)> {
+        let uuid = || UUID.to_owned();
+        let answer = serde_json::from_value::<RTCSessionDescription>(json!({
+            "type": "answer",
+            "sdp": "v=0\r\no=- 0 0 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n",
+        }))
+        .unwrap();
+        let candidate = RTCIceCandidateInit {
+            candidate: "candidate:1 1 udp 2130706431 192.0.2.1 50000 typ host".to_owned(),
+            sdp_mid: Some("0".to_owned()),
+            sdp_mline_index: Some(0),
+            username_fragment: None,
+        };
+
+        vec![
+            (
+                "HELLO",
+                SignalingMessage::Hello {
+                    major: PROTOCOL_VERSION,
+                    minor: PROTOCOL_MINOR_VERSION,
+                    features: Feature::supported(),
+                },
+            ),
+            (
+                "WELCOME",
+                SignalingMessage::Welcome {
+                    major: PROTOCOL_VERSION,
+                    minor: 0,
+                    features: vec![Feature::TrickleIce],
+                },
+            ),
+            (
+                "CONNECTION",
+                SignalingMessage::Connection {
+                    uuid: uuid(),
+                    sdp: "v=0".to_owned(),
+                    trickle: true,
+                },
+            ),
+            ("CONTROL", SignalingMessage::Control { uuid: uuid() }),
+            ("DISCONNECT", SignalingMessage::Disconnect { uuid: uuid() }),
+            (
+                "SPECIFICATION",
+                SignalingMessage::Specification {
+                    message: HostSpecification {
+                        display: DisplaySpecification {
+                            width: 1920,
+                            height: 1080,
+                            frame_rate: 60,
+                        },
+                        ip_addr: "192.0.2.1".to_owned(),
+                        ip_addrs: vec!["192.0.2.1".to_owned(), "2001:db8::1".to_owned()],
+                        device: DeviceSpecification {
+                            cpu: vec![DeviceEntry {
+                                name: "cpu".to_owned(),
+                                size: 8,
+                            }],
+                            gpu: vec![],
+                            memory: 16 << 30,
+                        },
+                    },
+                },
+            ),
+            (
+                "CONNECTION_ACK",
+                SignalingMessage::ConnectionAck {
+                    uuid: uuid(),
+                    answer: Box::new(answer),
+                    ice_servers: vec![RTCIceServer {
+                        urls: vec!["turn:turn.example.com:3478".to_owned()],
+                        username: "1700000000:landlord".to_owned(),
+                        credential: "secret".to_owned(),
+                    }],
+                },
+            ),
+            (
+                "CONNECTION_NACK",
+                SignalingMessage::ConnectionNack {
+                    uuid: uuid(),
+                    code: NackCode::BadSdp,
+                    detail: "no media".to_owned(),
+                },
+            ),
+            ("CONTROL_ACK", SignalingMessage::ControlAck { uuid: uuid() }),
+            (
+                "CONTROL_NACK",
+                SignalingMessage::ControlNack {
+                    uuid: uuid(),
+                    code: NackCode::NotControllable,
+                    detail: String::new(),
+                },
+            ),
+            (
+                "DISCONNECT_ACK",
+                SignalingMessage::DisconnectAck { uuid: uuid() },
+            ),
+            (
+                "DISCONNECT_NACK",
+                SignalingMessage::DisconnectNack {
+                    uuid: uuid(),
+                    code: NackCode::UnknownPeer,
+                    detail: String::new(),
+                },
+            ),
+            (
+                "CONNECTION_MADE",
+                SignalingMessage::ConnectionMade { uuid: uuid() },
+            ),
+            (
+                "DISCONNECTION_MADE",
+                SignalingMessage::DisconnectionMade { uuid: uuid() },
+            ),
+            (
+                "CONTROL_RELEASED",
+                SignalingMessage::ControlReleased { uuid: uuid() },
+            ),
+            (
+                "CONTROL_TAKEN",
+                SignalingMessage::ControlTaken { uuid: uuid() },
+            ),
+            (
+                "RESUME",
+                SignalingMessage::Resume {
+                    peers: vec![uuid()],
+                    controller: Some(uuid()),
+                },
+            ),
+            (
+                "ICE_CANDIDATE",
+                SignalingMessage::IceCandidate {
+                    uuid: uuid(),
+                    candidate,
+                },
+            ),
+            (
+                "END_OF_CANDIDATES",
+                SignalingMessage::EndOfCandidates { uuid: uuid() },
+            ),
+            (
+                "ERROR",
+                SignalingMessage::Error {
+                    code: ErrorCode::UnexpectedMessage,
+                    detail: "not now".to_owned(),
+                },
+            ),
+        ]
+    }
+
+    fn error_of(frame: Value) -> ErrorCode {
+        SignalingMessage::decode(&frame.to_string())
+            .unwrap_err()
+            .code
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        for (kind, message) in every_message() {
+            let frame = message.encode();
+            let value = serde_json::from_str::<Value>(&frame).unwrap();
+
+            assert_eq!(value["type"], kind);
+            assert_eq!(value["version"], PROTOCOL_VERSION);
+
+            let decoded = SignalingMessage::decode(&frame).unwrap();
+            assert_eq!(format!("{decoded:?}"), format!("{message:?}"), "{kind}");
+            assert_eq!(decoded.encode(), frame, "{kind}");
+        }
+    }
+
+    #[test]
+    fn optional_fields_have_defaults() {
+        let frame = json!({"type": "CONNECTION", "version": 1, "uuid": UUID, "sdp": "v=0"});
+
+        assert!(matches!(
+            SignalingMessage::decode(&frame.to_string()).unwrap(),
+            SignalingMessage::Connection { trickle: false, .. }
+        ));
+
+        let ack = SignalingMessage::ConnectionAck {
+            uuid: UUID.to_owned(),
+            answer: Box::default(),
+            ice_servers: vec![],
+        };
+        let value = serde_json::from_str::<Value>(&ack.encode()).unwrap();
+
+        assert!(value.get("ice_servers").is_none());
+    }
+
+    #[test]
+    fn refuses_other_versions() {
+        for version in [
+            json!(0),
+            json!(PROTOCOL_VERSION + 1),
+            json!("1"),
+            json!(null),
+        ] {
+            assert_eq!(
+                error_of(json!({"type": "CONTROL", "version": version, "uuid": UUID})),
+                ErrorCode::UnsupportedVersion,
+                "{version}"
+            );
+        }
+
+        assert_eq!(
+            error_of(json!({"type": "CONTROL", "uuid": UUID})),
+            ErrorCode::MalformedFrame
+        );
+    }
+
+    #[test]
+    fn refuses_malformed_frames() {
+        assert_eq!(
+            SignalingMessage::decode("{").unwrap_err().code,
+            ErrorCode::MalformedFrame
+        );
+
+        for frame in [
+            json!([1]),
+            json!({"type": "TELEPORT", "version": 1}),
+            json!({"type": "CONTROL", "version": 1}),
+            json!({"type": "CONTROL", "version": 1, "uuid": UUID, "extra": true}),
+        ] {
+            assert_eq!(
+                error_of(frame.clone()),
+                ErrorCode::MalformedFrame,
+                "{frame}"
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_invalid_fields() {
+        for frame in [
+            json!({"type": "CONTROL", "version": 1, "uuid": " "}),
+            json!({"type": "RESUME", "version": 1, "peers": [UUID], "controller": ""}),
+            json!({
+                "type": "ICE_CANDIDATE",
+                "version": 1,
+                "uuid": UUID,
+                "candidate": {"candidate": ""},
+            }),
+        ] {
+            assert_eq!(error_of(frame.clone()), ErrorCode::InvalidField, "{frame}");
+        }
+    }
+}
@@ -1,211 +1,979 @@
-use serde_json::json;
+use rand::Rng;
+use rocket::serde::Serialize;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch, Mutex, RwLock};
+use tokio::time::Instant;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 
-use crate::conn::{AetherWebRTCConnectionManager, ConnectionStatus};
+use crate::config::LandlordConfig;
+use crate::conn::host::Host;
+use crate::conn::protocol::{
+    ErrorCode, Feature, HostSpecification, NackCode, SignalingMessage, PROTOCOL_MINOR_VERSION,
+    PROTOCOL_VERSION,
+};
+use crate::conn::{
+    build_api, AetherWebRTCConnectionManager, ConnectOptions, ConnectionStatus, PeerError, PeerSet,
+};
+use crate::events::EventPublisher;
+use crate::probe::host_specification;
+use crate::rocket::futures::stream::{SplitSink, SplitStream};
 use crate::rocket::futures::{SinkExt, StreamExt};
 
-use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 
-use webrtc::api::interceptor_registry::register_default_interceptors;
-use webrtc::api::media_engine::MediaEngine;
-use webrtc::api::APIBuilder;
-use webrtc::interceptor::registry::Registry;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
 
-pub async fn start_server_connection(addr: &str, token: String) -> anyhow::Result<()> {
-    let (ws_stream, _) = connect_async(&format!("{addr}/v1/landlord/ws?token={token}")).await?;
+/// Maximum number of events kept while the signaling server is unreachable.
+const OUTBOX_CAPACITY: usize = 256;
 
-    let send_sync_ws_stream = Arc::new(RwLock::new(ws_stream));
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
 
-    send_sync_ws_stream
-        .write()
-        .await
-        .send(
-            serde_json::json!({
-                "type": "SPECIFICATION",
-                    "message": {
-                        "display": {
-                            "width": 1920,
-                            "height": 1080,
-                            "frame_rate": 24,
-                        },
-                        "ip_addr": "0.0.0.0",
-                        "device": {
-                            "cpu": [
-                                {
-                                    "name": "<>",
-                                    "size": 0,
-                                }],
-                            "gpu": [
-                                {
-                                    "name": "<>",
-                                    "size": 0,
-                            }]
-                        }
+/// How long the server may take to answer HELLO.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the host is probed for changes to its SPECIFICATION.
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+type SignalingSink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+type SignalingSource = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+type SharedOutbox = Arc<Mutex<Outbox>>;
+type SharedConnManager = Arc<RwLock<AetherWebRTCConnectionManager>>;
+/// Remote candidates of the peers whose offer is being answered, added once
+/// the offer is applied.
+type EarlyCandidates = Arc<Mutex<HashMap<String, Vec<RTCIceCandidateInit>>>>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum SessionState {
+    #[default]
+    Connecting,
+    Connected,
+    Reconnecting,
+    Closed,
+    Failed,
+}
+
+/// Health of the link to the signaling server.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SignalingStatus {
+    pub state: SessionState,
+    /// Why the session failed, once it did.
+    pub error: Option<String>,
+    pub peers: usize,
+    /// Round-trip time of the last answered ping, in milliseconds.
+    pub round_trip_time_ms: Option<u64>,
+    pub reconnections: u32,
+}
+
+pub type SharedSignalingStatus = Arc<RwLock<SignalingStatus>>;
+
+/// Outgoing half of the signaling link. It outlives individual websocket
+/// connections so that events raised while offline can be delivered later.
+#[derive(Default)]
+struct Outbox {
+    sink: Option<SignalingSink>,
+    pending: VecDeque<SignalingMessage>,
+    /// Whether the session got past its handshake. Events stay queued until
+    /// then so that HELLO is always the first frame.
+    ready: bool,
+    /// Local candidates of the peers being answered, held back until their
+    /// CONNECTION_ACK is queued so that they never overtake it.
+    held: HashMap<String, Vec<SignalingMessage>>,
+}
+
+impl Outbox {
+    fn attach(&mut self, sink: SignalingSink) {
+        self.sink = Some(sink);
+        self.ready = false;
+    }
+
+    fn detach(&mut self) {
+        self.sink = None;
+        self.ready = false;
+    }
+
+    /// Closes the current connection, if any, with a normal close frame.
+    async fn close(&mut self) {
+        if let Some(mut sink) = self.sink.take() {
+            let frame = CloseFrame {
+                code: CloseCode::Normal,
+                reason: "landlord stopped".into(),
+            };
+
+            let _ = sink.send(Message::Close(Some(frame))).await;
+            let _ = sink.close().await;
+        }
+
+        self.ready = false;
+    }
+
+    /// Sends `message` on the current connection, failing if there is none.
+    async fn send(&mut self, message: SignalingMessage) -> anyhow::Result<()> {
+        self.send_frame(message.encode().into()).await
+    }
+
+    /// Sends a raw websocket frame, such as a ping. Sending also flushes the
+    /// pongs tungstenite queued in reply to the server's pings.
+    async fn send_frame(&mut self, frame: Message) -> anyhow::Result<()> {
+        let sink = self
+            .sink
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("The signaling server is unreachable."))?;
+
+        if let Err(e) = sink.send(frame).await {
+            self.sink = None;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Writes out the frames tungstenite queued on its own.
+    async fn flush_frames(&mut self) -> anyhow::Result<()> {
+        if let Some(sink) = self.sink.as_mut() {
+            if let Err(e) = sink.flush().await {
+                self.sink = None;
+                return Err(e.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends `message`, keeping it around until the link is back if offline.
+    async fn deliver(&mut self, message: SignalingMessage) {
+        self.pending.push_back(message);
+
+        if self.pending.len() > OUTBOX_CAPACITY {
+            if let Some(dropped) = self.pending.pop_front() {
+                warn!("Signaling outbox is full, dropping {dropped:?}");
+            }
+        }
+
+        if self.ready {
+            self.flush().await;
+        }
+    }
+
+    /// Sends the event of a peer, unless it is a candidate to hold back.
+    async fn deliver_event(&mut self, status: ConnectionStatus) {
+        if let ConnectionStatus::LocalCandidate(uuid, _)
+        | ConnectionStatus::CandidatesGathered(uuid) = &status
+        {
+            if let Some(held) = self.held.get_mut(uuid) {
+                held.push(status.into());
+                return;
+            }
+        }
+
+        self.deliver(status.into()).await;
+    }
+
+    /// Holds back the local candidates of the peer `uuid` until it is
+    /// answered, unless the peer is already being answered.
+    fn hold_candidates(&mut self, uuid: &str) -> bool {
+        if self.held.contains_key(uuid) {
+            return false;
+        }
+
+        self.held.insert(uuid.to_owned(), vec![]);
+        true
+    }
+
+    /// Sends the answer to the CONNECTION of the peer `uuid`, followed by the
+    /// candidates held back until then. The candidates of a refused peer are
+    /// dropped.
+    async fn deliver_answer(&mut self, uuid: &str, answer: SignalingMessage) {
+        let held = self.held.remove(uuid).unwrap_or_default();
+        let accepted = matches!(answer, SignalingMessage::ConnectionAck { .. });
+
+        self.deliver(answer).await;
+
+        if accepted {
+            for candidate in held {
+                self.deliver(candidate).await;
+            }
+        }
+    }
+
+    async fn flush(&mut self) {
+        while let Some(message) = self.pending.front() {
+            if self.send(message.clone()).await.is_err() {
+                break;
+            }
+
+            self.pending.pop_front();
+        }
+    }
+}
+
+async fn reply(outbox: &SharedOutbox, message: SignalingMessage) {
+    let _ = outbox.lock().await.send(message).await;
+}
+
+/// Exponential backoff with equal jitter.
+struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Self { attempt: 0 }
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let ceiling = INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(MAX_BACKOFF);
+
+        self.attempt = self.attempt.saturating_add(1);
+
+        ceiling / 2 + ceiling.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+    }
+}
+
+/// Pings the signaling server and notices when it stops answering.
+struct Keepalive {
+    sequence: u64,
+    in_flight: Option<(u64, Instant)>,
+    last_seen: Instant,
+    timeout: Duration,
+}
+
+impl Keepalive {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            sequence: 0,
+            in_flight: None,
+            last_seen: Instant::now(),
+            timeout,
+        }
+    }
+
+    /// Any frame from the server proves the link is alive.
+    fn saw_traffic(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    fn is_expired(&self) -> bool {
+        self.last_seen.elapsed() > self.timeout
+    }
+
+    /// Payload of the next ping, a sequence number answered in the pong.
+    fn next_ping(&mut self) -> Vec<u8> {
+        self.sequence += 1;
+        self.in_flight = Some((self.sequence, Instant::now()));
+
+        self.sequence.to_be_bytes().to_vec()
+    }
+
+    /// Round-trip time of the ping answered by `payload`, if it is the last
+    /// one sent.
+    fn answer(&mut self, payload: &[u8]) -> Option<Duration> {
+        let (sequence, sent_at) = self.in_flight?;
+
+        if payload != sequence.to_be_bytes() {
+            return None;
+        }
+
+        self.in_flight = None;
+        Some(sent_at.elapsed())
+    }
+}
+
+enum SessionEnd {
+    /// The server closed the session on purpose.
+    Closed,
+    /// The link dropped and should be re-established.
+    Dropped,
+    /// The server speaks another major version of the protocol.
+    Incompatible(String),
+}
+
+/// Keeps the landlord registered with the configured signaling server until
+/// `shutdown` turns true, then disconnects every peer and closes the link.
+///
+/// Every connection opens with a HELLO/WELCOME handshake settling the
+/// optional features of the session. A server speaking another major version
+/// of the protocol ends the connection for good.
+///
+/// Dropped connections are re-established with exponential backoff. Every
+/// reconnection re-sends the SPECIFICATION, the events that could not be
+/// delivered while offline and finally a RESUME snapshot of the live peers,
+/// so connected peers keep receiving CONTROL and DISCONNECT. The host is
+/// probed again periodically and a new SPECIFICATION is sent when it changed.
+///
+/// The link is kept alive with websocket pings. When the server stays silent
+/// for longer than `signaling.ping_timeout`, the connection is dropped and
+/// established again. The state of the link, its measured round-trip time
+/// and the number of peers are published in `status`.
+pub async fn start_server_connection(
+    config: LandlordConfig,
+    token: String,
+    status: SharedSignalingStatus,
+    peers: PeerSet,
+    events: EventPublisher,
+    host: Host,
+    mut shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let config = Arc::new(config);
+
+    let outbox = SharedOutbox::default();
+    let (events, relayed) = events.relayed();
+    let forwarder = tokio::spawn(forward_events(relayed, outbox.clone(), status.clone()));
+
+    let url = format!(
+        "{}/v1/landlord/ws?token={}",
+        config.signaling.address,
+        rocket::http::RawStr::new(&token).percent_encode()
+    );
+
+    let conn_manager = SharedConnManager::new(RwLock::new(AetherWebRTCConnectionManager::new(
+        build_api(),
+        events,
+        host,
+        config.clone(),
+        peers,
+    )));
+
+    let result = tokio::select! {
+        result = maintain_link(&url, &outbox, &conn_manager, &config, &status) => result,
+        _ = shutdown.wait_for(|stop| *stop) => {
+            info!("Closing the signaling session.");
+            Ok(())
+        }
+    };
+
+    // Waits for the offers still being answered, so that their peers are
+    // disconnected too.
+    conn_manager.write().await.disconnect_all().await;
+    outbox.lock().await.close().await;
+    forwarder.abort();
+
+    let mut status = status.write().await;
+    status.round_trip_time_ms = None;
+
+    match &result {
+        Ok(()) => status.state = SessionState::Closed,
+        Err(e) => {
+            status.state = SessionState::Failed;
+            status.error = Some(e.to_string());
+        }
+    }
+
+    result
+}
+
+/// Relays the events of the peers of the session to the signaling server,
+/// counting the peers along the way.
+async fn forward_events(
+    mut events: mpsc::UnboundedReceiver<ConnectionStatus>,
+    outbox: SharedOutbox,
+    status: SharedSignalingStatus,
+) {
+    while let Some(event) = events.recv().await {
+        match event {
+            ConnectionStatus::Connected(_) => status.write().await.peers += 1,
+            ConnectionStatus::Disconnected(_) => {
+                let mut status = status.write().await;
+                status.peers = status.peers.saturating_sub(1);
+            }
+            _ => {}
+        }
+
+        outbox.lock().await.deliver_event(event).await;
+    }
+}
+
+/// Connects to the signaling server again and again, until the server closes
+/// the session or refuses the landlord.
+async fn maintain_link(
+    url: &str,
+    outbox: &SharedOutbox,
+    conn_manager: &SharedConnManager,
+    config: &LandlordConfig,
+    status: &SharedSignalingStatus,
+) -> anyhow::Result<()> {
+    let mut backoff = Backoff::new();
+    let mut resuming = false;
+
+    loop {
+        match connect_async(url).await {
+            Ok((ws_stream, _)) => {
+                let (ws_sink, ws_source) = ws_stream.split();
+                outbox.lock().await.attach(ws_sink);
+
+                if resuming {
+                    status.write().await.reconnections += 1;
                 }
-            })
-            .to_string()
-            .into(),
-        )
-        .await?;
 
-    let mut engine = MediaEngine::default();
-
-    engine
-        .register_default_codecs()
-        .expect("Unable to register default codecs.");
-
-    let mut registry = Registry::new();
-
-    registry = register_default_interceptors(registry, &mut engine)
-        .expect("Unable to register default interceptors.");
-
-    let api = APIBuilder::new()
-        .with_media_engine(engine)
-        .with_interceptor_registry(registry)
-        .build();
-
-    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
-
-    let state_ws = send_sync_ws_stream.clone();
-
-    tokio::spawn(async move {
-        tokio::select! {
-            Some(state) = rx.recv() => {
-                match state {
-                    ConnectionStatus::Connected(uuid) => {
-                        let _ = state_ws
-                            .write()
-                            .await
-                            .send(
-                                serde_json::json!({
-                                    "type": "CONNECTION_MADE",
-                                    "uuid": uuid
-                                })
-                                .to_string()
-                                .into(),
-                            )
-                            .await;
-                    }
-                    ConnectionStatus::Disconnected(uuid) => {
-                        let _ = state_ws
-                            .write()
-                            .await
-                            .send(
-                                serde_json::json!({
-                                    "type": "DISCONNECTION_MADE",
-                                    "uuid": uuid
-
-                                })
-                                .to_string()
-                                .into(),
-                            )
-                            .await;
-                    }
-                    ConnectionStatus::ControlRelease(uuid) => {
-                        let _ = state_ws
-                            .write()
-                            .await
-                            .send(
-                                serde_json::json!({
-                                    "type": "CONTROL_RELEASED",
-                                    "uuid": uuid
-                                })
-                                .to_string()
-                                .into(),
-                            )
-                            .await;
+                let session =
+                    run_session(ws_source, outbox, conn_manager, config, status, resuming).await;
+
+                // Only sessions that got past WELCOME count as established,
+                // so that a server dropping every connection is not hammered.
+                let welcomed = {
+                    let mut status = status.write().await;
+                    let welcomed = status.state == SessionState::Connected;
+                    status.state = SessionState::Reconnecting;
+                    status.round_trip_time_ms = None;
+                    welcomed
+                };
+
+                match session {
+                    Ok(SessionEnd::Closed) => {
+                        info!("Signaling server closed the session.");
+                        return Ok(());
                     }
-                    ConnectionStatus::ControlTake(uuid) => {
-                        let _ = state_ws
-                            .write()
-                            .await
-                            .send(
-                                serde_json::json!({
-                                    "type": "CONTROL_TAKEN",
-                                    "uuid": uuid
-                                })
-                                .to_string()
-                                .into(),
-                            )
-                            .await;
+                    Ok(SessionEnd::Incompatible(reason)) => {
+                        outbox.lock().await.detach();
+                        anyhow::bail!("Signaling server speaks an incompatible protocol: {reason}");
                     }
+                    Ok(SessionEnd::Dropped) => warn!("Signaling connection dropped."),
+                    Err(e) => warn!("Signaling connection failed: {e}"),
                 }
+
+                outbox.lock().await.detach();
+                resuming = true;
+
+                if welcomed {
+                    backoff.reset();
+                }
+            }
+            Err(tungstenite::Error::Http(response)) if response.status().is_client_error() => {
+                anyhow::bail!(
+                    "Signaling server refused the landlord: {}",
+                    response.status()
+                );
             }
+            Err(e) => warn!("Unable to reach the signaling server: {e}"),
         }
-    });
-
-    let mut conn_manager = AetherWebRTCConnectionManager::new(api, tx);
-
-    while let Some(Ok(msg)) = send_sync_ws_stream.write().await.next().await {
-        let data = serde_json::Value::from(msg.to_text()?);
-        let request_type = data["type"].as_str();
-
-        if request_type.is_none() {
-            continue;
-        }
-
-        match request_type.unwrap() {
-            "CONNECTION" => {
-                if let Ok(answer) = conn_manager
-                    .connect(
-                        RTCSessionDescription::offer(data["sdp"].as_str().unwrap().into()).unwrap(),
-                        data["uuid"].as_str().unwrap().into(),
-                    )
-                    .await
-                {
-                    let _ = send_sync_ws_stream
-                        .write()
-                        .await
-                        .send(
-                            json!({
-                                "type": "CONNECTION_ACK",
-                                "answer": answer
-                            })
-                            .to_string()
-                            .into(),
-                        )
-                        .await;
+
+        let delay = backoff.next_delay();
+        info!("Reconnecting to the signaling server in {delay:?}.");
+        tokio::time::sleep(delay).await;
+    }
+}
+
+async fn run_session(
+    mut ws_source: SignalingSource,
+    outbox: &SharedOutbox,
+    conn_manager: &SharedConnManager,
+    config: &LandlordConfig,
+    status: &SharedSignalingStatus,
+    resuming: bool,
+) -> anyhow::Result<SessionEnd> {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&mut ws_source, outbox)).await {
+        Ok(Ok(Handshake::Welcome(features))) => {
+            info!("Signaling session enabled {features:?}.");
+            conn_manager.write().await.set_features(features);
+            status.write().await.state = SessionState::Connected;
+        }
+        Ok(Ok(Handshake::Ended(end))) => return Ok(end),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => anyhow::bail!("The signaling server did not answer HELLO."),
+    }
+
+    let mut specification = host_specification(config).await;
+
+    {
+        let mut outbox = outbox.lock().await;
+
+        outbox
+            .send(SignalingMessage::Specification {
+                message: specification.clone(),
+            })
+            .await?;
+
+        outbox.ready = true;
+        outbox.flush().await;
+
+        if resuming {
+            let conn_manager = conn_manager.read().await;
+
+            outbox
+                .send(SignalingMessage::Resume {
+                    peers: conn_manager.peer_uuids().await,
+                    controller: conn_manager.controller().await,
+                })
+                .await?;
+        }
+    }
+
+    let mut probe = tokio::time::interval_at(Instant::now() + PROBE_INTERVAL, PROBE_INTERVAL);
+
+    let ping_interval = Duration::from_secs(config.signaling.ping_interval);
+    let mut ping = tokio::time::interval_at(Instant::now() + ping_interval, ping_interval);
+    let mut keepalive = Keepalive::new(Duration::from_secs(config.signaling.ping_timeout));
+    let early = EarlyCandidates::default();
+
+    loop {
+        let msg = tokio::select! {
+            msg = ws_source.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = probe.tick() => {
+                resend_specification_if_changed(outbox, config, &mut specification).await?;
+                continue;
+            }
+            _ = ping.tick() => {
+                if keepalive.is_expired() {
+                    warn!("Signaling server silent for more than {:?}.", keepalive.timeout);
+                    return Ok(SessionEnd::Dropped);
                 }
+
+                let payload = keepalive.next_ping();
+                outbox.lock().await.send_frame(Message::Ping(payload.into())).await?;
+                continue;
+            }
+        };
+
+        keepalive.saw_traffic();
+
+        let frame = match msg? {
+            Message::Text(text) => text,
+            Message::Binary(_) => {
+                reply(
+                    outbox,
+                    SignalingMessage::Error {
+                        code: ErrorCode::MalformedFrame,
+                        detail: "binary frames are not supported".to_owned(),
+                    },
+                )
+                .await;
+                continue;
             }
-            "CONTROL" => {
-                let _ = conn_manager
-                    .change_control_to(data["uuid"].to_string())
-                    .await;
-
-                let _ = send_sync_ws_stream
-                    .write()
-                    .await
-                    .send(
-                        json!({
-                            "type": "CONTROL_ACK",
-                            "uuid": data["uuid"]
-                        })
-                        .to_string()
-                        .into(),
-                    )
-                    .await;
-            }
-            "DISCONNECT" => {
-                let _ = conn_manager.disconnect_peer(data["uuid"].to_string()).await;
-
-                let _ = send_sync_ws_stream
-                    .write()
-                    .await
-                    .send(
-                        json!({
-                            "type": "DISCONNECT_ACK",
-                            "uuid": data["uuid"]
-                        })
-                        .to_string()
-                        .into(),
-                    )
-                    .await;
+            Message::Close(Some(frame)) if frame.code == CloseCode::Normal => {
+                return Ok(SessionEnd::Closed)
+            }
+            Message::Close(_) => return Ok(SessionEnd::Dropped),
+            Message::Ping(_) => {
+                outbox.lock().await.flush_frames().await?;
+                continue;
+            }
+            Message::Pong(payload) => {
+                if let Some(rtt) = keepalive.answer(&payload) {
+                    debug!("Signaling round-trip time: {rtt:?}");
+                    status.write().await.round_trip_time_ms = Some(rtt.as_millis() as u64);
+                }
+                continue;
+            }
+            Message::Frame(_) => continue,
+        };
+
+        match SignalingMessage::decode(frame.as_str()) {
+            Ok(message) => handle_message(message, outbox, conn_manager, &early).await?,
+            Err(e) => {
+                warn!("Rejected signaling frame: {e}");
+                reply(outbox, e.into()).await;
+            }
+        }
+    }
+
+    Ok(SessionEnd::Dropped)
+}
+
+enum Handshake {
+    /// The session may start with the given features.
+    Welcome(Vec<Feature>),
+    /// The session ended before it started.
+    Ended(SessionEnd),
+}
+
+/// Advertises the protocol version and supported features, then waits for
+/// the server to pick the features it enables.
+async fn handshake(
+    ws_source: &mut SignalingSource,
+    outbox: &SharedOutbox,
+) -> anyhow::Result<Handshake> {
+    let supported = Feature::supported();
+
+    outbox
+        .lock()
+        .await
+        .send(SignalingMessage::Hello {
+            major: PROTOCOL_VERSION,
+            minor: PROTOCOL_MINOR_VERSION,
+            features: supported.clone(),
+        })
+        .await?;
+
+    while let Some(msg) = ws_source.next().await {
+        let frame = match msg? {
+            Message::Text(text) => text,
+            Message::Close(Some(frame)) if frame.code == CloseCode::Normal => {
+                return Ok(Handshake::Ended(SessionEnd::Closed))
+            }
+            Message::Close(_) => return Ok(Handshake::Ended(SessionEnd::Dropped)),
+            Message::Ping(_) => {
+                outbox.lock().await.flush_frames().await?;
+                continue;
+            }
+            _ => continue,
+        };
+
+        let message = match SignalingMessage::decode(frame.as_str()) {
+            Ok(message) => message,
+            Err(e) if e.code == ErrorCode::UnsupportedVersion => {
+                return Ok(Handshake::Ended(SessionEnd::Incompatible(e.detail)))
+            }
+            Err(e) => {
+                reply(outbox, e.clone().into()).await;
+                anyhow::bail!("Invalid answer to HELLO: {e}");
+            }
+        };
+
+        match message {
+            SignalingMessage::Welcome {
+                major, features, ..
+            } if major == PROTOCOL_VERSION => {
+                let (enabled, unknown): (Vec<_>, Vec<_>) =
+                    features.into_iter().partition(|f| supported.contains(f));
+
+                if !unknown.is_empty() {
+                    warn!("Ignoring features the landlord did not offer: {unknown:?}");
+                }
+
+                return Ok(Handshake::Welcome(enabled));
+            }
+            SignalingMessage::Welcome { major, minor, .. } => {
+                return Ok(Handshake::Ended(SessionEnd::Incompatible(format!(
+                    "the landlord speaks {PROTOCOL_VERSION}.{PROTOCOL_MINOR_VERSION}, \
+                     the server {major}.{minor}"
+                ))))
+            }
+            SignalingMessage::Error {
+                code: ErrorCode::UnsupportedVersion,
+                detail,
+            } => return Ok(Handshake::Ended(SessionEnd::Incompatible(detail))),
+            unexpected => anyhow::bail!("Expected WELCOME, got {unexpected:?}"),
+        }
+    }
+
+    Ok(Handshake::Ended(SessionEnd::Dropped))
+}
+
+async fn resend_specification_if_changed(
+    outbox: &SharedOutbox,
+    config: &LandlordConfig,
+    specification: &mut HostSpecification,
+) -> anyhow::Result<()> {
+    let probed = host_specification(config).await;
+
+    if probed != *specification {
+        info!("Host specification changed, sending it again.");
+
+        outbox
+            .lock()
+            .await
+            .send(SignalingMessage::Specification {
+                message: probed.clone(),
+            })
+            .await?;
+
+        *specification = probed;
+    }
+
+    Ok(())
+}
+
+async fn handle_message(
+    message: SignalingMessage,
+    outbox: &SharedOutbox,
+    conn_manager: &SharedConnManager,
+    early: &EarlyCandidates,
+) -> anyhow::Result<()> {
+    let trickle_ice = conn_manager.read().await.has_feature(Feature::TrickleIce);
+
+    match message {
+        SignalingMessage::Connection { uuid, sdp, trickle } => {
+            let options = ConnectOptions {
+                trickle,
+                ..Default::default()
+            };
+
+            if !outbox.lock().await.hold_candidates(&uuid) {
+                let duplicate = SignalingMessage::ConnectionNack {
+                    detail: format!("An offer of '{uuid}' is already being answered."),
+                    uuid,
+                    code: NackCode::DuplicatePeer,
+                };
+
+                outbox.lock().await.deliver(duplicate).await;
+                return Ok(());
+            }
+
+            early.lock().await.insert(uuid.clone(), vec![]);
+
+            // Answering may wait for ICE gathering, during which frames must
+            // still be read and pings sent, and the features of a resumed
+            // session set without waiting for the answer.
+            tokio::spawn(answer_offer(
+                conn_manager.read().await.clone(),
+                outbox.clone(),
+                early.clone(),
+                uuid,
+                sdp,
+                options,
+            ));
+        }
+        SignalingMessage::Control { uuid } => {
+            let message = match conn_manager
+                .read()
+                .await
+                .change_control_to(uuid.clone())
+                .await
+            {
+                Ok(()) => SignalingMessage::ControlAck { uuid },
+                Err(e) => SignalingMessage::ControlNack {
+                    uuid,
+                    code: PeerError::code_of(&e),
+                    detail: e.to_string(),
+                },
+            };
+
+            reply(outbox, message).await;
+        }
+        SignalingMessage::Disconnect { uuid } => {
+            let message = match conn_manager
+                .read()
+                .await
+                .disconnect_peer(uuid.clone())
+                .await
+            {
+                Ok(()) => SignalingMessage::DisconnectAck { uuid },
+                Err(e) => SignalingMessage::DisconnectNack {
+                    uuid,
+                    code: PeerError::code_of(&e),
+                    detail: e.to_string(),
+                },
+            };
+
+            reply(outbox, message).await;
+        }
+        SignalingMessage::IceCandidate { uuid, candidate } if trickle_ice => {
+            add_remote_candidate(outbox, conn_manager, early, uuid, candidate).await;
+        }
+        SignalingMessage::EndOfCandidates { uuid } if trickle_ice => {
+            let end = RTCIceCandidateInit::default();
+            add_remote_candidate(outbox, conn_manager, early, uuid, end).await;
+        }
+        SignalingMessage::Error { code, detail } => {
+            error!("Signaling server reported {code:?}: {detail}");
+        }
+        unexpected => {
+            reply(
+                outbox,
+                SignalingMessage::Error {
+                    code: ErrorCode::UnexpectedMessage,
+                    detail: format!("the landlord does not accept {unexpected:?}"),
+                },
+            )
+            .await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds a candidate of the peer `uuid`, or keeps it until the offer of the
+/// peer is applied.
+async fn add_remote_candidate(
+    outbox: &SharedOutbox,
+    conn_manager: &SharedConnManager,
+    early: &EarlyCandidates,
+    uuid: String,
+    candidate: RTCIceCandidateInit,
+) {
+    if let Some(early) = early.lock().await.get_mut(&uuid) {
+        early.push(candidate);
+        return;
+    }
+
+    let added = conn_manager
+        .read()
+        .await
+        .add_remote_candidate(uuid, candidate)
+        .await;
+
+    if let Err(e) = added {
+        reply(
+            outbox,
+            SignalingMessage::Error {
+                code: ErrorCode::InvalidField,
+                detail: e.to_string(),
+            },
+        )
+        .await;
+    }
+}
+
+/// Answers the offer of the peer `uuid` and queues the answer, followed by
+/// the local candidates held back until then.
+async fn answer_offer(
+    conn_manager: AetherWebRTCConnectionManager,
+    outbox: SharedOutbox,
+    early: EarlyCandidates,
+    uuid: String,
+    sdp: String,
+    options: ConnectOptions,
+) {
+    let answer = match RTCSessionDescription::offer(sdp) {
+        Ok(offer) => conn_manager.connect(offer, uuid.clone(), options).await,
+        Err(e) => Err(PeerError::new(NackCode::BadSdp, e.to_string()).into()),
+    };
+
+    let early = early.lock().await.remove(&uuid).unwrap_or_default();
+
+    if answer.is_ok() {
+        for candidate in early {
+            if let Err(e) = conn_manager
+                .add_remote_candidate(uuid.clone(), candidate)
+                .await
+            {
+                warn!("Unable to add a candidate of peer '{uuid}': {e}");
             }
-            _ => {}
         }
     }
 
-    todo!()
+    let message = match answer {
+        Ok(answer) => SignalingMessage::ConnectionAck {
+            uuid: uuid.clone(),
+            answer: answer.description.into(),
+            ice_servers: answer.ice_servers,
+        },
+        Err(e) => {
+            warn!("Unable to connect peer '{uuid}': {e}");
+
+            SignalingMessage::ConnectionNack {
+                uuid: uuid.clone(),
+                code: PeerError::code_of(&e),
+                detail: e.to_string(),
+            }
+        }
+    };
+
+    outbox.lock().await.deliver_answer(&uuid, message).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::conn::display::{Display, DisplayLayout};
+    use crate::conn::input::InputHandle;
+    use crate::events::EventBus;
+
+    #[test]
+    fn backoff_grows_with_jitter_up_to_its_cap() {
+        let mut backoff = Backoff::new();
+
+        for attempt in 0..20 {
+            let ceiling = INITIAL_BACKOFF
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(MAX_BACKOFF);
+            let delay = backoff.next_delay();
+
+            assert!(delay >= ceiling / 2 && delay < ceiling, "{delay:?}");
+        }
+
+        // Far past the cap, delays stay within it.
+        assert!(backoff.next_delay() >= MAX_BACKOFF / 2);
+
+        backoff.reset();
+        assert!(backoff.next_delay() < INITIAL_BACKOFF);
+
+        let delays: Vec<Duration> = (0..16)
+            .map(|_| {
+                backoff.reset();
+                backoff.next_delay()
+            })
+            .collect();
+
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+
+    #[tokio::test]
+    async fn drops_a_server_that_stops_answering_pings() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        // Welcomes the landlord, then never reads again, so that its pings go
+        // unanswered.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+            ws.next().await.unwrap().unwrap();
+            ws.send(
+                SignalingMessage::Welcome {
+                    major: PROTOCOL_VERSION,
+                    minor: PROTOCOL_MINOR_VERSION,
+                    features: vec![],
+                }
+                .encode()
+                .into(),
+            )
+            .await
+            .unwrap();
+
+            std::future::pending::<()>().await;
+        });
+
+        let mut config = LandlordConfig::default();
+        config.signaling.ping_interval = 1;
+        config.signaling.ping_timeout = 2;
+
+        let display = Display::fixed(DisplayLayout::fallback(1920, 1080), "", (1920, 1080));
+        let host = Host {
+            input: InputHandle::spawn(display, || anyhow::bail!("no input in tests")),
+            clipboard: None,
+            files: None,
+            gamepads: None,
+        };
+
+        let conn_manager = SharedConnManager::new(RwLock::new(AetherWebRTCConnectionManager::new(
+            build_api(),
+            EventBus::new(16).publisher(None),
+            host,
+            Arc::new(config.clone()),
+            PeerSet::default(),
+        )));
+
+        let (ws_stream, _) = connect_async(format!("ws://{address}")).await.unwrap();
+        let (ws_sink, ws_source) = ws_stream.split();
+
+        let outbox = SharedOutbox::default();
+        outbox.lock().await.attach(ws_sink);
+        let status = SharedSignalingStatus::default();
+        let started = Instant::now();
+
+        let session = tokio::time::timeout(
+            Duration::from_secs(30),
+            run_session(ws_source, &outbox, &conn_manager, &config, &status, false),
+        )
+        .await
+        .expect("The session outlived its ping timeout.");
+
+        assert!(matches!(session, Ok(SessionEnd::Dropped)));
+        assert!(started.elapsed() >= Duration::from_secs(2));
+        assert_eq!(status.read().await.state, SessionState::Connected);
+        assert_eq!(status.read().await.round_trip_time_ms, None);
+
+        server.abort();
+    }
 }
@@ -0,0 +1,240 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rocket::fairing::AdHoc;
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::Serialize;
+use rocket::{Shutdown, State};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+
+use crate::admin::Admin;
+use crate::config::LandlordConfig;
+use crate::conn::ConnectionStatus;
+
+/// A change of a peer, as published on the [`EventBus`].
+#[derive(Clone)]
+pub struct PeerEvent {
+    /// Session of the peer, or `None` for LAN peers.
+    pub session: Option<String>,
+    pub at: SystemTime,
+    pub status: ConnectionStatus,
+}
+
+/// How a [`PeerEvent`] is shown outside of the landlord.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct EventRecord<'a> {
+    pub event: &'static str,
+    pub session: Option<&'a str>,
+    pub peer: &'a str,
+    /// Unix timestamp, in milliseconds.
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate: Option<&'a RTCIceCandidateInit>,
+}
+
+impl PeerEvent {
+    pub fn kind(&self) -> &'static str {
+        match self.status {
+            ConnectionStatus::ControlRelease(_) => "control_released",
+            ConnectionStatus::ControlTake(_) => "control_taken",
+            ConnectionStatus::Connected(_) => "connected",
+            ConnectionStatus::Disconnected(_) => "disconnected",
+            ConnectionStatus::LocalCandidate(..) => "local_candidate",
+            ConnectionStatus::CandidatesGathered(_) => "candidates_gathered",
+        }
+    }
+
+    pub fn record(&self) -> EventRecord<'_> {
+        EventRecord {
+            event: self.kind(),
+            session: self.session.as_deref(),
+            peer: self.status.uuid(),
+            timestamp: self
+                .at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            candidate: match &self.status {
+                ConnectionStatus::LocalCandidate(_, candidate) => Some(candidate),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Fans the events of every peer out to any number of subscribers.
+///
+/// Each subscriber has its own buffer of `events.capacity` events. One that
+/// falls behind loses its oldest events and is told how many, without slowing
+/// down the peers or the other subscribers.
+#[derive(Clone)]
+pub struct EventBus(broadcast::Sender<PeerEvent>);
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        Self(broadcast::channel(capacity).0)
+    }
+
+    /// Publisher for the peers of a connection manager.
+    pub fn publisher(&self, session: Option<String>) -> EventPublisher {
+        EventPublisher {
+            sender: self.0.clone(),
+            session,
+            relay: None,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PeerEvent> {
+        self.0.subscribe()
+    }
+}
+
+/// Publishes on the [`EventBus`] on behalf of the peers of one session, or of
+/// the LAN peers.
+#[derive(Clone)]
+pub struct EventPublisher {
+    sender: broadcast::Sender<PeerEvent>,
+    session: Option<String>,
+    /// Where the events also go without ever being dropped, for the
+    /// signaling server.
+    relay: Option<mpsc::UnboundedSender<ConnectionStatus>>,
+}
+
+impl EventPublisher {
+    pub fn publish(&self, status: ConnectionStatus) {
+        if let Some(relay) = &self.relay {
+            let _ = relay.send(status.clone());
+        }
+
+        // Having no subscriber at all is fine.
+        let _ = self.sender.send(PeerEvent {
+            session: self.session.clone(),
+            at: SystemTime::now(),
+            status,
+        });
+    }
+
+    /// This publisher, also relaying every event to the returned receiver.
+    /// Unlike subscribers of the bus, the receiver never loses any.
+    pub fn relayed(self) -> (Self, mpsc::UnboundedReceiver<ConnectionStatus>) {
+        let (relay, relayed) = mpsc::unbounded_channel();
+
+        (
+            Self {
+                relay: Some(relay),
+                ..self
+            },
+            relayed,
+        )
+    }
+}
+
+/// Logs every event as a line of JSON, candidates at the debug level only.
+async fn log_events(mut events: broadcast::Receiver<PeerEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let Ok(line) = serde_json::to_string(&event.record()) else {
+                    continue;
+                };
+
+                match event.status {
+                    ConnectionStatus::LocalCandidate(..)
+                    | ConnectionStatus::CandidatesGathered(_) => {
+                        debug!("Peer event: {line}")
+                    }
+                    _ => info!("Peer event: {line}"),
+                }
+            }
+            Err(RecvError::Lagged(missed)) => {
+                warn!("{missed} peer events were not logged.")
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// POSTs every event to the webhook, one at a time.
+async fn post_events(mut events: broadcast::Receiver<PeerEvent>, url: String, timeout: Duration) {
+    let client = match reqwest::Client::builder().timeout(timeout).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Unable to set up the event webhook: {e}");
+            return;
+        }
+    };
+
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let delivery = client
+                    .post(&url)
+                    .json(&event.record())
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+
+                // Errors would show the URL, which is secret.
+                if let Err(e) = delivery {
+                    warn!("Event webhook failed: {}", e.without_url());
+                }
+            }
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Event webhook fell behind, {missed} events were dropped.")
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Manages the [`EventBus`] and starts the log and webhook subscribers.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Event bus", |rocket| async {
+        let config = rocket
+            .state::<LandlordConfig>()
+            .cloned()
+            .expect("The landlord configuration must be attached first.");
+
+        let bus = EventBus::new(config.events.capacity);
+
+        tokio::spawn(log_events(bus.subscribe()));
+
+        if !config.events.webhook.expose().is_empty() {
+            tokio::spawn(post_events(
+                bus.subscribe(),
+                config.events.webhook.expose().to_owned(),
+                Duration::from_secs(config.events.webhook_timeout),
+            ));
+        }
+
+        rocket.manage(bus)
+    })
+}
+
+/// Streams the events of every peer as Server-Sent Events named after their
+/// kind. A client too slow to keep up gets a `lagged` event with the number
+/// of events it missed. Events carry the addresses of peers, so only the
+/// administrator may listen.
+#[get("/events")]
+pub fn event_stream(_admin: Admin, bus: &State<EventBus>, mut end: Shutdown) -> EventStream![] {
+    let mut events = bus.subscribe();
+
+    EventStream! {
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = &mut end => break,
+            };
+
+            match event {
+                Ok(event) => yield Event::json(&event.record()).event(event.kind()),
+                Err(RecvError::Lagged(missed)) => {
+                    yield Event::data(missed.to_string()).event("lagged")
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
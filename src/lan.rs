@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::json::Json;
+use rocket::{Request, State};
+use tokio::sync::Mutex;
+use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+use crate::admin::same_token;
+use crate::config::LandlordConfig;
+use crate::conn::host::Host;
+use crate::conn::protocol::NackCode;
+use crate::conn::{build_api, AetherWebRTCConnectionManager, ConnectOptions, PeerError, PeerSet};
+use crate::events::{EventBus, EventPublisher};
+
+/// Connection manager for peers negotiating directly with the landlord,
+/// without going through the signaling server. Offers are answered
+/// concurrently, so a peer slow to gather candidates holds up no other.
+pub struct LanConnectionManager {
+    pub manager: AetherWebRTCConnectionManager,
+    pub peers: PeerSet,
+    /// Peers started through `POST /whep`, the only ones the WHEP resources
+    /// may reach.
+    pub whep_sessions: Mutex<HashSet<String>>,
+}
+
+impl LanConnectionManager {
+    pub fn new(config: LandlordConfig, events: EventPublisher, host: Host) -> Self {
+        let peers = PeerSet::default();
+
+        Self {
+            manager: AetherWebRTCConnectionManager::new(
+                build_api(),
+                events,
+                host,
+                config.into(),
+                peers.clone(),
+            ),
+            peers,
+            whep_sessions: Mutex::default(),
+        }
+    }
+}
+
+/// HTTP status matching a failed request on the manager.
+pub(crate) fn status_of(error: &anyhow::Error) -> Status {
+    match PeerError::code_of(error) {
+        NackCode::BadSdp => Status::BadRequest,
+        NackCode::UnknownPeer => Status::NotFound,
+        NackCode::DuplicatePeer | NackCode::NotControllable => Status::Conflict,
+        NackCode::CaptureUnavailable | NackCode::CapacityReached => Status::ServiceUnavailable,
+        NackCode::Internal => Status::InternalServerError,
+    }
+}
+
+/// What a peer offering through `/sdp` or `/whep` may do. When `lan.token` is set, the
+/// request must carry it as `Authorization: Bearer <token>` and the peer may
+/// take control. Otherwise anyone on the network may offer, but only to
+/// watch the screen.
+pub struct LanAccess {
+    pub view_only: bool,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LanAccess {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(config) = request.rocket().state::<LandlordConfig>() else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+
+        if !config.lan.enabled() {
+            return Outcome::Success(LanAccess { view_only: true });
+        }
+
+        let given = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        match given {
+            Some(given) if same_token(given, config.lan.token.expose()) => {
+                Outcome::Success(LanAccess { view_only: false })
+            }
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Manages the [`LanConnectionManager`] once the runtime is up.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("LAN connection manager", |rocket| async {
+        let config = rocket
+            .state::<LandlordConfig>()
+            .cloned()
+            .expect("The landlord configuration must be attached first.");
+
+        let events = rocket
+            .state::<EventBus>()
+            .expect("The event bus must be attached first.")
+            .publisher(None);
+
+        let host = rocket
+            .state::<Host>()
+            .cloned()
+            .expect("Host access must be attached first.");
+
+        rocket.manage(LanConnectionManager::new(config, events, host))
+    })
+}
+
+/// Answers an offer from a peer on the local network, such as the bundled
+/// demonstration page, as far as its [`LanAccess`] goes.
+#[post("/sdp", format = "json", data = "<offer>")]
+pub async fn sdp_offer(
+    offer: Json<RTCSessionDescription>,
+    access: LanAccess,
+    manager: &State<LanConnectionManager>,
+) -> Result<Json<RTCSessionDescription>, Status> {
+    if offer.sdp_type != RTCSdpType::Offer {
+        return Err(Status::BadRequest);
+    }
+
+    let offer =
+        RTCSessionDescription::offer(offer.into_inner().sdp).map_err(|_| Status::BadRequest)?;
+
+    let uuid = uuid::Uuid::new_v4().to_string();
+
+    match manager
+        .manager
+        .connect(
+            offer,
+            uuid.clone(),
+            ConnectOptions {
+                view_only: access.view_only,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        Ok(answer) => {
+            info!("Answered LAN offer as peer '{uuid}'.");
+            Ok(Json(answer.description))
+        }
+        Err(e) => {
+            error!("Unable to answer LAN offer: {e}");
+            Err(status_of(&e))
+        }
+    }
+}
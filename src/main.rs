@@ -1,14 +1,28 @@
 #[macro_use]
 extern crate rocket;
+mod admin;
+mod config;
 mod conn;
+mod events;
+mod lan;
+mod metrics;
+mod probe;
+mod session;
+mod token;
+mod whep;
 
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::fs::FileServer;
-use rocket::http::Header;
+use rocket::http::{Header, Status};
+use rocket::response::status::Accepted;
 use rocket::serde::json::Json;
-use rocket::{Request, Response};
+use rocket::{Request, Response, State};
 use rocket_dyn_templates::{context, Template};
 
+use crate::conn::host::{self, Host};
+use crate::events::EventBus;
+use crate::session::{SessionInfo, SessionRegistry};
+
 pub struct CORS;
 
 #[rocket::async_trait]
@@ -24,18 +38,50 @@ impl Fairing for CORS {
         response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
         response.set_header(Header::new(
             "Access-Control-Allow-Methods",
-            "POST, GET, PATCH, OPTIONS",
+            "POST, GET, PATCH, DELETE, OPTIONS",
         ));
         response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
         response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        response.set_header(Header::new("Access-Control-Expose-Headers", "Location"));
     }
 }
 
+/// Starts a signaling session with the given token, registered under the
+/// returned id.
+///
+/// The body must be a JSON string. When token verification is configured,
+/// a malformed token earns a 400, a bad signature, expiry or audience a 401
+/// and a token issued for another host a 409. A token already used by a live
+/// session also earns a 409, unless duplicates replace the live session.
 #[post("/negotiate-server", format = "json", data = "<token>")]
-async fn server_negotiation_request(mut token: Json<serde_json::Value>) {
-    let token = token.take().as_str().unwrap().to_owned();
-    // Change this or fetch it dynamically.
-    tokio::spawn(conn::ws::start_server_connection("127.0.0.1:7878", token));
+async fn server_negotiation_request(
+    token: Json<serde_json::Value>,
+    config: &State<config::LandlordConfig>,
+    registry: &State<SessionRegistry>,
+    events: &State<EventBus>,
+    host: &State<Host>,
+) -> Result<Accepted<Json<SessionInfo>>, Status> {
+    let Some(token) = token.as_str() else {
+        return Err(Status::BadRequest);
+    };
+
+    if config.token.enabled() {
+        if let Err(e) = token::verify(&config.token, token) {
+            warn!("Refused negotiation: {e}");
+            return Err(e.status());
+        }
+    }
+
+    match registry.start(config, events, host, token.to_owned()).await {
+        Some(session) => {
+            info!("Started session '{}'.", session.id);
+            Ok(Accepted(Json(session)))
+        }
+        None => {
+            warn!("Refused negotiation: the token is used by a live session.");
+            Err(Status::Conflict)
+        }
+    }
 }
 
 #[get("/")]
@@ -62,10 +108,29 @@ fn rocket() -> _ {
         routes![
             default_landing_page,
             all_options,
-            server_negotiation_request
+            server_negotiation_request,
+            session::list_sessions,
+            session::session_details,
+            session::stop_session,
+            admin::list_peers,
+            admin::give_control,
+            admin::revoke_control,
+            admin::kick_peer,
+            config::effective_config,
+            events::event_stream,
+            metrics::metrics,
+            lan::sdp_offer,
+            whep::whep_offer,
+            whep::whep_trickle,
+            whep::whep_teardown
         ],
     )
     .mount("/static", FileServer::from("./static"))
+    .manage(SessionRegistry::default())
     .attach(CORS)
+    .attach(config::fairing())
+    .attach(events::fairing())
+    .attach(host::fairing())
+    .attach(lan::fairing())
     .attach(Template::fairing())
 }
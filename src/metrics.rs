@@ -0,0 +1,266 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::LazyLock;
+
+use prometheus_client::encoding::text::encode;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+use rocket::http::{ContentType, Status};
+use rocket::State;
+
+use crate::admin::Admin;
+use crate::conn::protocol::NackCode;
+use crate::conn::PeerSet;
+use crate::lan::LanConnectionManager;
+use crate::session::SessionRegistry;
+
+/// Counters of the whole landlord, updated by the connection managers and
+/// the capture pipeline.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ReasonLabels {
+    reason: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CodecLabels {
+    codec: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct SessionLabels {
+    /// Session id, or `lan` for LAN peers.
+    session: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct PeerLabels {
+    session: String,
+    peer: String,
+}
+
+type FloatGauge = Gauge<f64, AtomicU64>;
+
+#[derive(Default)]
+pub struct Metrics {
+    control_changes: Counter,
+    connections_established: Counter,
+    connections_failed: Family<ReasonLabels, Counter>,
+    frames_written: Family<CodecLabels, Counter>,
+    bytes_written: Family<CodecLabels, Counter>,
+    ffmpeg_restarts: Counter,
+    /// Whether the last ffmpeg capture failed, so that the next one counts
+    /// as a restart.
+    ffmpeg_failed: AtomicBool,
+    rtcp_picture_loss: Counter,
+    rtcp_full_intra_requests: Counter,
+}
+
+impl Metrics {
+    pub fn control_changed(&self) {
+        self.control_changes.inc();
+    }
+
+    pub fn connection_established(&self) {
+        self.connections_established.inc();
+    }
+
+    /// A connection refused while negotiating it.
+    pub fn negotiation_failed(&self, code: NackCode) {
+        let reason = match code {
+            NackCode::BadSdp => "bad_sdp",
+            NackCode::UnknownPeer => "unknown_peer",
+            NackCode::DuplicatePeer => "duplicate_peer",
+            NackCode::NotControllable => "not_controllable",
+            NackCode::CaptureUnavailable => "capture_unavailable",
+            NackCode::CapacityReached => "capacity_reached",
+            NackCode::Internal => "internal",
+        };
+
+        self.connections_failed
+            .get_or_create(&ReasonLabels { reason })
+            .inc();
+    }
+
+    /// A negotiated connection that never got through.
+    pub fn ice_failed(&self) {
+        self.connections_failed
+            .get_or_create(&ReasonLabels {
+                reason: "ice_failed",
+            })
+            .inc();
+    }
+
+    pub fn frame_written(&self, codec: &'static str, bytes: usize) {
+        let labels = CodecLabels { codec };
+
+        self.frames_written.get_or_create(&labels).inc();
+        self.bytes_written
+            .get_or_create(&labels)
+            .inc_by(bytes as u64);
+    }
+
+    /// Counts the ffmpeg captures started after one failed.
+    pub fn ffmpeg_started(&self) {
+        if self.ffmpeg_failed.swap(false, Ordering::Relaxed) {
+            self.ffmpeg_restarts.inc();
+        }
+    }
+
+    /// An ffmpeg capture that could not start or exited with an error.
+    pub fn ffmpeg_failed(&self) {
+        self.ffmpeg_failed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn picture_loss(&self) {
+        self.rtcp_picture_loss.inc();
+    }
+
+    pub fn full_intra_request(&self) {
+        self.rtcp_full_intra_requests.inc();
+    }
+
+    fn register(&self, registry: &mut Registry) {
+        registry.register(
+            "control_changes",
+            "Times a peer took control",
+            self.control_changes.clone(),
+        );
+        registry.register(
+            "connections_established",
+            "Peer connections that reached the connected state",
+            self.connections_established.clone(),
+        );
+        registry.register(
+            "connections_failed",
+            "Peer connections refused or failed, by reason",
+            self.connections_failed.clone(),
+        );
+        registry.register(
+            "frames_written",
+            "Video frames written to the screen track, by codec",
+            self.frames_written.clone(),
+        );
+        registry.register(
+            "bytes_written",
+            "Video bytes written to the screen track, by codec",
+            self.bytes_written.clone(),
+        );
+        registry.register(
+            "ffmpeg_restarts",
+            "Screen captures started after one failed",
+            self.ffmpeg_restarts.clone(),
+        );
+        registry.register(
+            "rtcp_picture_loss_indications",
+            "RTCP PLI packets received from peers",
+            self.rtcp_picture_loss.clone(),
+        );
+        registry.register(
+            "rtcp_full_intra_requests",
+            "RTCP FIR packets received from peers",
+            self.rtcp_full_intra_requests.clone(),
+        );
+    }
+}
+
+/// Gauges of the peers connected right now.
+async fn register_peers(registry: &mut Registry, peer_sets: Vec<(String, PeerSet)>) {
+    let peers = Family::<SessionLabels, Gauge>::default();
+    let round_trip_time = Family::<PeerLabels, FloatGauge>::default();
+    let fraction_lost = Family::<PeerLabels, FloatGauge>::default();
+    let estimated_bitrate = Family::<PeerLabels, FloatGauge>::default();
+
+    for (session, set) in peer_sets {
+        let links = set.link_stats().await;
+
+        peers
+            .get_or_create(&SessionLabels {
+                session: session.clone(),
+            })
+            .set(links.len() as i64);
+
+        for link in links {
+            let labels = PeerLabels {
+                session: session.clone(),
+                peer: link.uuid,
+            };
+
+            if let Some(rtt) = link.round_trip_time {
+                round_trip_time.get_or_create(&labels).set(rtt);
+            }
+
+            if let Some(lost) = link.fraction_lost {
+                fraction_lost.get_or_create(&labels).set(lost);
+            }
+
+            if let Some(bitrate) = link.estimated_bitrate {
+                estimated_bitrate.get_or_create(&labels).set(bitrate);
+            }
+        }
+    }
+
+    registry.register("peers", "Connected peers, by session", peers);
+    registry.register(
+        "peer_round_trip_time_seconds",
+        "Round-trip time reported by the peer",
+        round_trip_time,
+    );
+    registry.register(
+        "peer_fraction_lost",
+        "Fraction of the video packets lost on the way to the peer",
+        fraction_lost,
+    );
+    registry.register(
+        "peer_estimated_bitrate",
+        "Latest REMB estimate of the peer, in bits per second",
+        estimated_bitrate,
+    );
+}
+
+/// Exposes the metrics in the Prometheus text format, to the admin only as
+/// they name sessions and peers.
+#[get("/metrics")]
+pub async fn metrics(
+    _admin: Admin,
+    lan: &State<LanConnectionManager>,
+    registry: &State<SessionRegistry>,
+) -> Result<(ContentType, String), Status> {
+    let mut peer_sets = vec![("lan".to_owned(), lan.peers.clone())];
+    peer_sets.extend(registry.peer_sets().await);
+
+    let mut metrics = Registry::with_prefix("landlord");
+    METRICS.register(&mut metrics);
+    register_peers(&mut metrics, peer_sets).await;
+
+    let mut body = String::new();
+    encode(&mut body, &metrics).map_err(|_| Status::InternalServerError)?;
+
+    Ok((
+        ContentType::new("application", "openmetrics-text")
+            .with_params([("version", "1.0.0"), ("charset", "utf-8")]),
+        body,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_restarts_after_failures_only() {
+        let metrics = Metrics::default();
+
+        metrics.ffmpeg_started();
+        metrics.ffmpeg_started();
+        assert_eq!(metrics.ffmpeg_restarts.get(), 0);
+
+        metrics.ffmpeg_failed();
+        metrics.ffmpeg_started();
+        metrics.ffmpeg_started();
+        assert_eq!(metrics.ffmpeg_restarts.get(), 1);
+    }
+}
@@ -0,0 +1,443 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, UdpSocket};
+
+use crate::config::LandlordConfig;
+use crate::conn::display::DisplayLayout;
+#[cfg(target_os = "linux")]
+use crate::conn::display::{Monitor, Rect};
+use crate::conn::protocol::{
+    DeviceEntry, DeviceSpecification, DisplaySpecification, HostSpecification,
+};
+
+/// Describes the host as advertised in SPECIFICATION.
+///
+/// Probing reads files and spawns `xrandr`, so it runs on the blocking pool.
+/// Whatever cannot be probed falls back to the configured screen geometry,
+/// the capture frame rate or empty device lists.
+pub async fn host_specification(config: &LandlordConfig) -> HostSpecification {
+    let fallback = DisplaySpecification {
+        width: config.screen.width,
+        height: config.screen.height,
+        frame_rate: config.capture.frame_rate,
+    };
+
+    tokio::task::spawn_blocking(move || {
+        let (ip_addr, ip_addrs) = probe_addresses();
+        let (cpu, memory) = probe_cpu();
+
+        HostSpecification {
+            display: probe_display().unwrap_or(fallback),
+            ip_addr,
+            ip_addrs,
+            device: DeviceSpecification {
+                cpu,
+                gpu: probe_gpu(),
+                memory,
+            },
+        }
+    })
+    .await
+    .expect("Hardware probing panicked.")
+}
+
+/// The output of `xrandr --current`, describing the X screen and its outputs.
+#[cfg(target_os = "linux")]
+fn xrandr() -> Option<String> {
+    let output = std::process::Command::new("xrandr")
+        .arg("--current")
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads the size of the X screen and the refresh rate of its first active
+/// mode from `xrandr`.
+#[cfg(target_os = "linux")]
+fn probe_display() -> Option<DisplaySpecification> {
+    parse_display(&xrandr()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe_display() -> Option<DisplaySpecification> {
+    None
+}
+
+/// Reads the X screen and the geometry of its active outputs from `xrandr`.
+///
+/// Spawns `xrandr`, so it belongs on the blocking pool.
+#[cfg(target_os = "linux")]
+pub fn probe_layout() -> Option<DisplayLayout> {
+    parse_layout(&xrandr()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn probe_layout() -> Option<DisplayLayout> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn parse_display(output: &str) -> Option<DisplaySpecification> {
+    let layout = parse_layout(output)?;
+
+    //    1920x1080     60.00*+  50.00    59.94
+    let frame_rate = output
+        .lines()
+        .filter(|line| line.starts_with(' '))
+        .flat_map(str::split_whitespace)
+        .find(|rate| rate.contains('*'))
+        .and_then(|rate| rate.trim_end_matches(['*', '+']).parse::<f32>().ok())?;
+
+    Some(DisplaySpecification {
+        width: layout.desktop.width,
+        height: layout.desktop.height,
+        frame_rate: frame_rate.round() as u32,
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn parse_layout(output: &str) -> Option<DisplayLayout> {
+    // Screen 0: minimum 8 x 8, current 3840 x 1080, maximum 32767 x 32767
+    let (width, height) = output
+        .lines()
+        .find(|line| line.starts_with("Screen "))?
+        .split(", ")
+        .find_map(|part| part.strip_prefix("current "))?
+        .split_once(" x ")?;
+
+    // HDMI-1 connected primary 1920x1080+0+0 (normal left inverted ...) 527mm x 296mm
+    // DP-1 connected 1920x1080+1920+0 (normal left inverted ...) 527mm x 296mm
+    let monitors = output
+        .lines()
+        .filter(|line| !line.starts_with(' '))
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let name = words.next()?;
+
+            if words.next()? != "connected" {
+                return None;
+            }
+
+            // Connected outputs that are turned off have no geometry.
+            let rect = words.take(2).find_map(parse_geometry)?;
+
+            Some(Monitor {
+                name: name.to_owned(),
+                rect,
+            })
+        })
+        .collect();
+
+    let desktop = Rect {
+        x: 0,
+        y: 0,
+        width: width.trim().parse().ok()?,
+        height: height.trim().parse().ok()?,
+    };
+
+    // An empty desktop gives way to the configured screen size.
+    (!desktop.is_empty()).then_some(DisplayLayout { desktop, monitors })
+}
+
+/// Reads an X geometry such as `1920x1080+1920+0`.
+#[cfg(target_os = "linux")]
+fn parse_geometry(geometry: &str) -> Option<Rect> {
+    let (size, origin) = geometry.split_once('+')?;
+    let (x, y) = origin.split_once('+')?;
+    let (width, height) = size.split_once('x')?;
+
+    Some(Rect {
+        x: x.parse().ok()?,
+        y: y.parse().ok()?,
+        width: width.parse().ok()?,
+        height: height.parse().ok()?,
+    })
+    .filter(|rect| !rect.is_empty())
+}
+
+/// Groups the logical processors of `/proc/cpuinfo` by model and reads the
+/// total memory from `/proc/meminfo`.
+fn probe_cpu() -> (Vec<DeviceEntry>, u64) {
+    let cpu = std::fs::read_to_string("/proc/cpuinfo")
+        .map(|cpuinfo| parse_cpuinfo(&cpuinfo))
+        .unwrap_or_default();
+
+    let memory = std::fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|meminfo| parse_meminfo(&meminfo))
+        .unwrap_or(0);
+
+    (cpu, memory)
+}
+
+fn parse_cpuinfo(cpuinfo: &str) -> Vec<DeviceEntry> {
+    let mut models = BTreeMap::<String, u64>::new();
+
+    for processor in cpuinfo.split("\n\n").filter(|p| p.contains("processor")) {
+        let model = processor
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| matches!(key.trim(), "model name" | "Model" | "Hardware"))
+            .map_or("Unknown CPU", |(_, value)| value.trim());
+
+        *models.entry(model.to_owned()).or_default() += 1;
+    }
+
+    models
+        .into_iter()
+        .map(|(name, size)| DeviceEntry { name, size })
+        .collect()
+}
+
+/// The total memory in `/proc/meminfo`, in bytes.
+fn parse_meminfo(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|total| {
+            total
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .map(|kilobytes| kilobytes * 1024)
+}
+
+/// Lists the DRM cards as `<driver> [<vendor>:<device>]`, with the VRAM size
+/// when the driver exposes it.
+fn probe_gpu() -> Vec<DeviceEntry> {
+    let Ok(cards) = std::fs::read_dir("/sys/class/drm") else {
+        return vec![];
+    };
+
+    let mut cards: Vec<_> = cards
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("card"))
+                .is_some_and(|index| index.chars().all(|c| c.is_ascii_digit()))
+        })
+        .collect();
+
+    cards.sort();
+
+    cards
+        .into_iter()
+        .filter_map(|card| {
+            let device = card.join("device");
+            let uevent = std::fs::read_to_string(device.join("uevent")).ok()?;
+
+            let field = |key: &str| {
+                uevent
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                    .map(str::to_owned)
+            };
+
+            let name = match (field("DRIVER"), field("PCI_ID")) {
+                (Some(driver), Some(id)) => format!("{driver} [{}]", id.to_lowercase()),
+                (Some(driver), None) => driver,
+                (None, _) => return None,
+            };
+
+            let size = std::fs::read_to_string(device.join("mem_info_vram_total"))
+                .ok()
+                .and_then(|size| size.trim().parse().ok())
+                .unwrap_or(0);
+
+            Some(DeviceEntry { name, size })
+        })
+        .collect()
+}
+
+/// Returns the address of the default route and every routable address of
+/// the host's interfaces.
+fn probe_addresses() -> (String, Vec<String>) {
+    let mut addresses: Vec<IpAddr> = webrtc::util::ifaces::ifaces()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|iface| iface.addr.map(|addr| addr.ip()))
+        .filter(is_routable)
+        .collect();
+
+    addresses.sort();
+    addresses.dedup();
+
+    // Connecting a UDP socket sends nothing, it only selects a route.
+    let primary = UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("192.0.2.1:9")?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip())
+        .ok()
+        .filter(is_routable)
+        .or_else(|| addresses.first().copied());
+
+    (
+        primary.map_or("0.0.0.0".to_owned(), |addr| addr.to_string()),
+        addresses.iter().map(IpAddr::to_string).collect(),
+    )
+}
+
+fn is_routable(addr: &IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(v4) => !(v4.is_loopback() || v4.is_unspecified() || v4.is_link_local()),
+        IpAddr::V6(v6) => {
+            !(v6.is_loopback() || v6.is_unspecified() || (v6.segments()[0] & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `xrandr --current` of a laptop with a second monitor, and a third one
+    /// connected but turned off.
+    #[cfg(target_os = "linux")]
+    const XRANDR: &str = "\
+Screen 0: minimum 320 x 200, current 3840 x 1080, maximum 16384 x 16384
+eDP-1 connected primary 1920x1080+0+0 (normal left inverted right x axis y axis) 344mm x 194mm
+   1920x1080     60.02*+  60.01    59.97    59.96    59.93
+   1680x1050     59.95    59.88
+HDMI-1 connected 1920x1080+1920+0 (normal left inverted right x axis y axis) 527mm x 296mm
+   1920x1080     60.00 +  74.97*   50.00    59.94
+   1280x720      60.00    50.00
+DP-1 disconnected (normal left inverted right x axis y axis)
+DP-2 connected (normal left inverted right x axis y axis)
+   2560x1440     59.95 +
+";
+
+    /// Two logical processors of a laptop.
+    const CPUINFO_X86: &str = "\
+processor\t: 0
+vendor_id\t: GenuineIntel
+model name\t: Intel(R) Core(TM) i7-8550U CPU @ 1.80GHz
+cpu MHz\t\t: 1800.000
+
+processor\t: 1
+vendor_id\t: GenuineIntel
+model name\t: Intel(R) Core(TM) i7-8550U CPU @ 1.80GHz
+cpu MHz\t\t: 1800.000
+";
+
+    /// A Raspberry Pi, whose board is described after the processors.
+    const CPUINFO_ARM: &str = "\
+processor\t: 0
+model name\t: ARMv7 Processor rev 3 (v7l)
+BogoMIPS\t: 108.00
+
+processor\t: 1
+BogoMIPS\t: 108.00
+
+Hardware\t: BCM2835
+Revision\t: c03114
+Model\t\t: Raspberry Pi 4 Model B Rev 1.4
+";
+
+    const MEMINFO: &str = "\
+MemTotal:       16303952 kB
+MemFree:         9876543 kB
+MemAvailable:   12345678 kB
+";
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_the_layout() {
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        assert_eq!(
+            parse_layout(XRANDR),
+            Some(DisplayLayout {
+                desktop: rect(0, 0, 3840, 1080),
+                monitors: vec![
+                    Monitor {
+                        name: "eDP-1".to_owned(),
+                        rect: rect(0, 0, 1920, 1080),
+                    },
+                    Monitor {
+                        name: "HDMI-1".to_owned(),
+                        rect: rect(1920, 0, 1920, 1080),
+                    },
+                ],
+            })
+        );
+
+        assert_eq!(parse_layout("xrandr: Can't open display"), None);
+        assert_eq!(
+            parse_layout("Screen 0: minimum 8 x 8, current 0 x 0, maximum 8 x 8"),
+            None
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_the_display() {
+        assert_eq!(
+            parse_display(XRANDR),
+            Some(DisplaySpecification {
+                width: 3840,
+                height: 1080,
+                frame_rate: 60,
+            })
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_geometries() {
+        assert_eq!(
+            parse_geometry("1080x1920+1920+360"),
+            Some(Rect {
+                x: 1920,
+                y: 360,
+                width: 1080,
+                height: 1920,
+            })
+        );
+
+        for geometry in ["primary", "(normal", "1920x1080", "0x1080+0+0", "axb+0+0"] {
+            assert_eq!(parse_geometry(geometry), None, "{geometry}");
+        }
+    }
+
+    #[test]
+    fn groups_processors_by_model() {
+        let entry = |name: &str, size| DeviceEntry {
+            name: name.to_owned(),
+            size,
+        };
+
+        assert_eq!(
+            parse_cpuinfo(CPUINFO_X86),
+            [entry("Intel(R) Core(TM) i7-8550U CPU @ 1.80GHz", 2)]
+        );
+        assert_eq!(
+            parse_cpuinfo(CPUINFO_ARM),
+            [
+                entry("ARMv7 Processor rev 3 (v7l)", 1),
+                entry("Unknown CPU", 1)
+            ]
+        );
+        assert_eq!(parse_cpuinfo(""), []);
+    }
+
+    #[test]
+    fn reads_the_total_memory() {
+        assert_eq!(parse_meminfo(MEMINFO), Some(16303952 * 1024));
+        assert_eq!(parse_meminfo("MemFree: 1 kB"), None);
+    }
+}
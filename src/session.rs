@@ -0,0 +1,226 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use tokio::sync::{watch, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::admin::Admin;
+use crate::config::{DuplicatePolicy, LandlordConfig};
+use crate::conn::host::Host;
+use crate::conn::ws::{start_server_connection, SharedSignalingStatus, SignalingStatus};
+use crate::conn::PeerSet;
+use crate::events::EventBus;
+
+/// How long a stopped session may take to close its peers and websocket.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A signaling session started through `/negotiate-server`.
+struct Session {
+    id: String,
+    token: String,
+    started_at: SystemTime,
+    status: SharedSignalingStatus,
+    peers: PeerSet,
+    shutdown: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+impl Session {
+    async fn info(&self) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            started_at: self
+                .started_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            status: self.status.read().await.clone(),
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        !self.handle.is_finished()
+    }
+
+    /// Asks the session to close, aborting it if it takes too long.
+    async fn stop(self) {
+        let _ = self.shutdown.send(true);
+
+        let abort = self.handle.abort_handle();
+
+        if tokio::time::timeout(STOP_TIMEOUT, self.handle)
+            .await
+            .is_err()
+        {
+            warn!("Session '{}' did not stop in time, aborting it.", self.id);
+            abort.abort();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SessionInfo {
+    pub id: String,
+    /// Unix timestamp, in seconds.
+    pub started_at: u64,
+    #[serde(flatten)]
+    pub status: SignalingStatus,
+}
+
+/// The signaling sessions of the landlord. Ended sessions are forgotten, with
+/// their token, the next time a session starts or sessions are listed.
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: RwLock<Vec<Session>>,
+}
+
+/// Forgets the sessions that ended.
+fn prune(sessions: &mut Vec<Session>) {
+    sessions.retain(Session::is_live);
+}
+
+impl SessionRegistry {
+    /// Starts a session with `token`, unless a live session already uses it
+    /// and duplicates are rejected.
+    pub async fn start(
+        &self,
+        config: &LandlordConfig,
+        events: &EventBus,
+        host: &Host,
+        token: String,
+    ) -> Option<SessionInfo> {
+        let mut sessions = self.sessions.write().await;
+        prune(&mut sessions);
+
+        if let Some(index) = sessions.iter().position(|session| session.token == token) {
+            if config.sessions.on_duplicate == DuplicatePolicy::Reject {
+                return None;
+            }
+
+            let duplicate = sessions.remove(index);
+            drop(sessions);
+
+            info!("Replacing session '{}'.", duplicate.id);
+            duplicate.stop().await;
+
+            sessions = self.sessions.write().await;
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let status = SharedSignalingStatus::default();
+        let peers = PeerSet::default();
+        let (shutdown, stopped) = watch::channel(false);
+
+        let task_id = id.clone();
+        let task = start_server_connection(
+            config.clone(),
+            token.clone(),
+            status.clone(),
+            peers.clone(),
+            events.publisher(Some(id.clone())),
+            host.clone(),
+            stopped,
+        );
+
+        let handle = tokio::spawn(async move {
+            match task.await {
+                Ok(()) => info!("Session '{task_id}' ended."),
+                Err(e) => error!("Session '{task_id}' failed: {e}"),
+            }
+        });
+
+        let session = Session {
+            id,
+            token,
+            started_at: SystemTime::now(),
+            status,
+            peers,
+            shutdown,
+            handle,
+        };
+
+        let info = session.info().await;
+        sessions.push(session);
+
+        Some(info)
+    }
+
+    pub async fn list(&self) -> Vec<SessionInfo> {
+        let mut sessions = self.sessions.write().await;
+        prune(&mut sessions);
+        let mut infos = Vec::with_capacity(sessions.len());
+
+        for session in sessions.iter() {
+            infos.push(session.info().await);
+        }
+
+        infos
+    }
+
+    /// Peers of every live session, by session id.
+    pub async fn peer_sets(&self) -> Vec<(String, PeerSet)> {
+        self.sessions
+            .read()
+            .await
+            .iter()
+            .filter(|session| session.is_live())
+            .map(|session| (session.id.clone(), session.peers.clone()))
+            .collect()
+    }
+
+    pub async fn get(&self, id: &str) -> Option<SessionInfo> {
+        let sessions = self.sessions.read().await;
+
+        match sessions.iter().find(|session| session.id == id) {
+            Some(session) => Some(session.info().await),
+            None => None,
+        }
+    }
+
+    /// Closes the peers and the websocket of the session `id` and forgets it.
+    pub async fn stop(&self, id: &str) -> bool {
+        let session = {
+            let mut sessions = self.sessions.write().await;
+
+            match sessions.iter().position(|session| session.id == id) {
+                Some(index) => sessions.remove(index),
+                None => return false,
+            }
+        };
+
+        session.stop().await;
+        true
+    }
+}
+
+#[get("/sessions")]
+pub async fn list_sessions(
+    _admin: Admin,
+    registry: &State<SessionRegistry>,
+) -> Json<Vec<SessionInfo>> {
+    Json(registry.list().await)
+}
+
+#[get("/sessions/<id>")]
+pub async fn session_details(
+    _admin: Admin,
+    id: &str,
+    registry: &State<SessionRegistry>,
+) -> Option<Json<SessionInfo>> {
+    registry.get(id).await.map(Json)
+}
+
+/// Stops the session `id`, disconnecting its peers.
+#[delete("/sessions/<id>")]
+pub async fn stop_session(_admin: Admin, id: &str, registry: &State<SessionRegistry>) -> Status {
+    if registry.stop(id).await {
+        info!("Stopped session '{id}'.");
+        Status::NoContent
+    } else {
+        Status::NotFound
+    }
+}
@@ -0,0 +1,76 @@
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use rocket::http::Status;
+use rocket::serde::Deserialize;
+
+use crate::config::{TokenAlgorithm, TokenConfig};
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Claims {
+    host_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    /// The token is not a JWT at all.
+    Malformed(String),
+    /// The signature, expiry or audience of the token is wrong.
+    Rejected(String),
+    /// The token was issued for another host.
+    OtherHost(String),
+}
+
+impl TokenError {
+    pub fn status(&self) -> Status {
+        match self {
+            TokenError::Malformed(_) => Status::BadRequest,
+            TokenError::Rejected(_) => Status::Unauthorized,
+            TokenError::OtherHost(_) => Status::Conflict,
+        }
+    }
+}
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::Malformed(detail) => write!(f, "malformed token: {detail}"),
+            TokenError::Rejected(detail) => write!(f, "rejected token: {detail}"),
+            TokenError::OtherHost(host) => write!(f, "token issued for host '{host}'"),
+        }
+    }
+}
+
+/// Checks the signature, `exp`, `aud` and `host_id` claims of `token`.
+pub fn verify(config: &TokenConfig, token: &str) -> Result<(), TokenError> {
+    let key = config.key.expose().as_bytes();
+
+    let (key, algorithm) = match config.algorithm {
+        TokenAlgorithm::Hs256 => (DecodingKey::from_secret(key), Algorithm::HS256),
+        TokenAlgorithm::EdDsa => (
+            DecodingKey::from_ed_pem(key).map_err(|e| TokenError::Rejected(e.to_string()))?,
+            Algorithm::EdDSA,
+        ),
+    };
+
+    let mut validation = Validation::new(algorithm);
+    validation.set_audience(&[&config.audience]);
+    validation.set_required_spec_claims(&["exp", "aud"]);
+    validation.leeway = config.leeway;
+
+    let claims = jsonwebtoken::decode::<Claims>(token, &key, &validation)
+        .map_err(|e| match e.kind() {
+            ErrorKind::InvalidToken
+            | ErrorKind::Base64(_)
+            | ErrorKind::Json(_)
+            | ErrorKind::Utf8(_) => TokenError::Malformed(e.to_string()),
+            _ => TokenError::Rejected(e.to_string()),
+        })?
+        .claims;
+
+    match claims.host_id {
+        Some(host_id) if host_id == config.host_id => Ok(()),
+        Some(host_id) => Err(TokenError::OtherHost(host_id)),
+        None => Err(TokenError::Rejected("missing `host_id` claim".to_owned())),
+    }
+}
@@ -0,0 +1,143 @@
+use rocket::http::{ContentType, Status};
+use rocket::response::status::Created;
+use rocket::State;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+use crate::conn::ConnectOptions;
+use crate::lan::{status_of, LanAccess, LanConnectionManager};
+
+/// Starts a WHEP playback session of the screen track, for clients with the
+/// same [`LanAccess`] as `/sdp`. Sessions only ever watch the screen.
+///
+/// The answer embeds every local candidate, while the client may trickle its
+/// own candidates through `PATCH` on the returned resource.
+#[post("/whep", format = "application/sdp", data = "<offer>")]
+pub async fn whep_offer(
+    offer: String,
+    _access: LanAccess,
+    manager: &State<LanConnectionManager>,
+) -> Result<Created<(ContentType, String)>, Status> {
+    let offer = RTCSessionDescription::offer(offer).map_err(|_| Status::BadRequest)?;
+
+    let uuid = uuid::Uuid::new_v4().to_string();
+
+    let options = ConnectOptions {
+        trickle: false,
+        view_only: true,
+    };
+
+    match manager.manager.connect(offer, uuid.clone(), options).await {
+        Ok(answer) => {
+            info!("Started WHEP session '{uuid}'.");
+
+            // Sessions that ended on their own go along.
+            let live = manager.manager.peer_uuids().await;
+            let mut sessions = manager.whep_sessions.lock().await;
+            sessions.retain(|session| live.contains(session));
+            sessions.insert(uuid.clone());
+
+            Ok(Created::new(format!("/whep/{uuid}")).body((
+                ContentType::new("application", "sdp"),
+                answer.description.sdp,
+            )))
+        }
+        Err(e) => {
+            error!("Unable to answer WHEP offer: {e}");
+            Err(status_of(&e))
+        }
+    }
+}
+
+/// Adds the candidates of a `application/trickle-ice-sdpfrag` body to the
+/// WHEP session `id`.
+#[patch(
+    "/whep/<id>",
+    format = "application/trickle-ice-sdpfrag",
+    data = "<fragment>"
+)]
+pub async fn whep_trickle(
+    id: &str,
+    fragment: String,
+    _access: LanAccess,
+    manager: &State<LanConnectionManager>,
+) -> Status {
+    if !manager.whep_sessions.lock().await.contains(id) {
+        return Status::NotFound;
+    }
+
+    let manager = &manager.manager;
+
+    let candidates = match parse_sdp_fragment(&fragment) {
+        Some(candidates) => candidates,
+        None => return Status::BadRequest,
+    };
+
+    for candidate in candidates {
+        if let Err(e) = manager.add_remote_candidate(id.to_owned(), candidate).await {
+            warn!("Rejected candidate for WHEP session '{id}': {e}");
+            return Status::BadRequest;
+        }
+    }
+
+    Status::NoContent
+}
+
+/// Tears down the WHEP session `id`.
+#[delete("/whep/<id>")]
+pub async fn whep_teardown(
+    id: &str,
+    _access: LanAccess,
+    manager: &State<LanConnectionManager>,
+) -> Status {
+    if !manager.whep_sessions.lock().await.remove(id) {
+        return Status::NotFound;
+    }
+
+    match manager.manager.disconnect_peer(id.to_owned()).await {
+        Ok(()) => {
+            info!("Stopped WHEP session '{id}'.");
+            Status::Ok
+        }
+        Err(e) => {
+            error!("Unable to stop WHEP session '{id}': {e}");
+            status_of(&e)
+        }
+    }
+}
+
+/// Extracts the candidates of a trickle SDP fragment (RFC 8840), mapping
+/// `a=end-of-candidates` to an empty candidate.
+fn parse_sdp_fragment(fragment: &str) -> Option<Vec<RTCIceCandidateInit>> {
+    let mut candidates = vec![];
+
+    let mut username_fragment = None;
+    let mut mline_index = None;
+    let mut mid = None;
+
+    for line in fragment.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(ufrag) = line.strip_prefix("a=ice-ufrag:") {
+            username_fragment = Some(ufrag.to_owned());
+        } else if line.starts_with("m=") {
+            mline_index = Some(mline_index.map_or(0, |index: u16| index + 1));
+            mid = None;
+        } else if let Some(value) = line.strip_prefix("a=mid:") {
+            mid = Some(value.to_owned());
+        } else if let Some(candidate) = line.strip_prefix("a=") {
+            if candidate.starts_with("candidate:") {
+                candidates.push(RTCIceCandidateInit {
+                    candidate: candidate.to_owned(),
+                    sdp_mid: mid.clone(),
+                    sdp_mline_index: mline_index,
+                    username_fragment: username_fragment.clone(),
+                });
+            } else if candidate == "end-of-candidates" {
+                candidates.push(RTCIceCandidateInit::default());
+            }
+        } else {
+            return None;
+        }
+    }
+
+    Some(candidates)
+}
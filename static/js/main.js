@@ -2,12 +2,17 @@ const videoPlayer = document.querySelector("video#player")
 const audioPlayer = document.querySelector("audio#player")
 const startButton = document.querySelector("button#start")
 const closeButton = document.querySelector("button#close")
+const lockButton = document.querySelector("button#lock")
 
 closeButton.disabled = true;
+lockButton.disabled = true;
 
 const portField = document.querySelector("input#local-port");
 portField.value = portField.getAttribute("placeholder");
 
+// Lets the peer take control through `/sdp`, when the landlord has a LAN token.
+const LAN_TOKEN = new URLSearchParams(window.location.search).get("token");
+
 const ICE_SERVERS = [
     {
         "urls": [
@@ -29,29 +34,371 @@ function openConnection() {
     pc.addTransceiver('audio', { direction: 'recvonly' });
 
     var dataChannel = pc.createDataChannel("mouse_events");
+    var keyboardChannel = pc.createDataChannel("keyboard_events");
+    var clipboardChannel = pc.createDataChannel("clipboard");
+    var fileChannel = pc.createDataChannel("file_transfer");
+    var gamepadChannel = pc.createDataChannel("gamepad_events");
     var signalledClosure = pc.createDataChannel("signalled_closure");
 
-    const clickHandler = (event) => {
+    const BUTTONS = ["left", "middle", "right", "back", "forward"];
 
+    const positionOf = (event) => {
         const rect = videoPlayer.getBoundingClientRect();
-        const x_ratio = (event.clientX - rect.left) / rect.width;
-        const y_ratio = (event.clientY - rect.top) / rect.height;
 
-        const click_payload = { x_ratio, y_ratio };
+        return {
+            x_ratio: (event.clientX - rect.left) / rect.width,
+            y_ratio: (event.clientY - rect.top) / rect.height,
+        };
+    }
+
+    // Locked, the pointer sends how far it moves instead of where it is.
+    const locked = () => document.pointerLockElement === videoPlayer;
 
+    const sendMouse = (payload) => {
         dataChannel.send(
             JSON.stringify({
                 type: "mouse",
-                payload: { "clicked_at": click_payload },
+                payload,
             })
         );
     }
 
+    // Fingers and pens are sent as they are, the host turns them into mouse
+    // gestures when it has no touchscreen.
+    const isTouch = (event) => event.pointerType == "touch" || event.pointerType == "pen";
+
+    const sendTouch = (event, phase) => {
+        const rect = videoPlayer.getBoundingClientRect();
+
+        sendMouse({
+            action: "touch",
+            phase,
+            id: event.pointerId,
+            tool: event.pointerType == "touch" ? "finger" : (event.buttons & 32 ? "eraser" : "pen"),
+            at: positionOf(event),
+            pressure: event.pressure,
+            tilt_x: event.tiltX,
+            tilt_y: event.tiltY,
+            width_ratio: event.width / rect.width,
+            height_ratio: event.height / rect.height,
+            barrel: (event.buttons & 2) != 0,
+        });
+    }
+
+    const pointerHandlers = {
+        pointermove: (event) => {
+            if (isTouch(event)) {
+                sendTouch(event, "move");
+            } else if (locked()) {
+                sendMouse({ action: "move_by", dx: event.movementX, dy: event.movementY });
+            } else {
+                sendMouse({ action: "move", at: positionOf(event) });
+            }
+        },
+        pointerdown: (event) => {
+            if (!locked()) {
+                videoPlayer.setPointerCapture(event.pointerId);
+            }
+
+            if (isTouch(event)) {
+                sendTouch(event, "down");
+                return;
+            }
+
+            sendMouse({ action: "down", button: BUTTONS[event.button], at: locked() ? undefined : positionOf(event) });
+        },
+        pointerup: (event) => {
+            if (isTouch(event)) {
+                sendTouch(event, "up");
+                return;
+            }
+
+            sendMouse({ action: "up", button: BUTTONS[event.button], at: locked() ? undefined : positionOf(event) });
+        },
+        wheel: (event) => {
+            event.preventDefault();
+            sendMouse({
+                action: "wheel",
+                delta_x: event.deltaX,
+                delta_y: event.deltaY,
+                delta_mode: event.deltaMode == WheelEvent.DOM_DELTA_LINE ? "line" : "pixel",
+                at: locked() ? undefined : positionOf(event),
+            });
+        },
+        pointercancel: (event) => {
+            if (isTouch(event)) {
+                sendTouch(event, "cancel");
+            }
+        },
+        // A pen leaving the video goes out of range.
+        pointerleave: (event) => {
+            if (event.pointerType == "pen") {
+                sendTouch(event, "cancel");
+            }
+        },
+        contextmenu: (event) => event.preventDefault(),
+    };
+
+    const keyHandler = (event) => {
+        event.preventDefault();
+        keyboardChannel.send(
+            JSON.stringify({
+                type: "keyboard",
+                payload: {
+                    action: event.type == "keydown" ? "down" : "up",
+                    code: event.code,
+                    modifiers: {
+                        shift: event.shiftKey,
+                        ctrl: event.ctrlKey,
+                        alt: event.altKey,
+                        meta: event.metaKey,
+                    },
+                },
+            })
+        );
+    }
+
+    keyboardChannel.onopen = () => {
+        videoPlayer.tabIndex = 0;
+        videoPlayer.addEventListener("keydown", keyHandler);
+        videoPlayer.addEventListener("keyup", keyHandler);
+    }
+    keyboardChannel.onclose = () => {
+        videoPlayer.removeEventListener("keydown", keyHandler);
+        videoPlayer.removeEventListener("keyup", keyHandler);
+    }
+
+    const CLIPBOARD_CHUNK = 16 * 1024;
+    var incomingText = "";
+
+    // What was copied here goes to the host whenever the video gets focus.
+    const sendClipboard = async () => {
+        let text;
+
+        try {
+            text = await navigator.clipboard.readText();
+        } catch (e) {
+            return;
+        }
+
+        let start = 0;
+
+        do {
+            let end = Math.min(start + CLIPBOARD_CHUNK, text.length);
+
+            // Keep surrogate pairs in one chunk.
+            if (end < text.length && /[\uD800-\uDBFF]/.test(text[end - 1])) {
+                end -= 1;
+            }
+
+            clipboardChannel.send(
+                JSON.stringify({
+                    type: "clipboard",
+                    payload: {
+                        kind: "text",
+                        data: text.slice(start, end),
+                        more: end < text.length,
+                    },
+                })
+            );
+
+            start = end;
+        } while (start < text.length);
+    }
+
+    clipboardChannel.onmessage = (event) => {
+        const { payload } = JSON.parse(event.data);
+
+        if (payload.kind != "text") {
+            return;
+        }
+
+        incomingText += payload.data;
+
+        if (!payload.more) {
+            navigator.clipboard.writeText(incomingText).catch(() => {});
+            incomingText = "";
+        }
+    }
+    clipboardChannel.onopen = () => {
+        videoPlayer.addEventListener("focus", sendClipboard);
+    }
+    clipboardChannel.onclose = () => {
+        videoPlayer.removeEventListener("focus", sendClipboard);
+    }
+
+    const FILE_CHUNK = 16 * 1024;
+    const uploads = {};
+
+    const sendTransfer = (payload) => {
+        fileChannel.send(JSON.stringify({ type: "file_transfer", payload }));
+    }
+
+    // Files dropped on the video are sent to the host.
+    const dropHandlers = {
+        dragover: (event) => event.preventDefault(),
+        drop: async (event) => {
+            event.preventDefault();
+
+            for (const file of event.dataTransfer.files) {
+                const data = new Uint8Array(await file.arrayBuffer());
+                const digest = new Uint8Array(await crypto.subtle.digest("SHA-256", data));
+                const id = crypto.randomUUID();
+
+                uploads[id] = data;
+                sendTransfer({
+                    action: "offer",
+                    id,
+                    name: file.name,
+                    size: data.length,
+                    sha256: Array.from(digest, (byte) => byte.toString(16).padStart(2, "0")).join(""),
+                });
+            }
+        },
+    };
+
+    const sendChunks = async (id, offset) => {
+        const data = uploads[id];
+        const header = new Uint8Array(24);
+        header.set(id.replaceAll("-", "").match(/../g).map((byte) => parseInt(byte, 16)));
+
+        for (; offset < data.length; offset += FILE_CHUNK) {
+            if (fileChannel.bufferedAmount > 1024 * 1024) {
+                await new Promise((resolve) => fileChannel.addEventListener("bufferedamountlow", resolve, { once: true }));
+            }
+
+            const chunk = data.subarray(offset, offset + FILE_CHUNK);
+            const message = new Uint8Array(header.length + chunk.length);
+            new DataView(header.buffer).setBigUint64(16, BigInt(offset));
+            message.set(header);
+            message.set(chunk, header.length);
+            fileChannel.send(message);
+        }
+    }
+
+    fileChannel.binaryType = "arraybuffer";
+    fileChannel.bufferedAmountLowThreshold = 256 * 1024;
+    fileChannel.onmessage = (event) => {
+        if (typeof event.data != "string") {
+            return;
+        }
+
+        const { payload } = JSON.parse(event.data);
+
+        if (payload.action == "accept" && uploads[payload.id]) {
+            sendChunks(payload.id, payload.offset);
+        } else if (["complete", "failed", "reject"].includes(payload.action)) {
+            console.log(`File transfer ${payload.id}: ${payload.action}`, payload.reason || "");
+            delete uploads[payload.id];
+        }
+    }
+    fileChannel.onopen = () => {
+        for (const [name, handler] of Object.entries(dropHandlers)) {
+            videoPlayer.addEventListener(name, handler);
+        }
+    }
+    fileChannel.onclose = () => {
+        for (const [name, handler] of Object.entries(dropHandlers)) {
+            videoPlayer.removeEventListener(name, handler);
+        }
+    }
+
+    // Pads are polled every frame, and their state sent whenever it changes.
+    const MAX_PADS = 4;
+    const padTimestamps = {};
+    let padFrame = null;
+
+    const sendPad = (payload) => {
+        gamepadChannel.send(JSON.stringify({ type: "gamepad", payload }));
+    }
+
+    const pollPads = () => {
+        const pads = navigator.getGamepads();
+
+        for (let index = 0; index < MAX_PADS; index++) {
+            const pad = pads[index];
+
+            if (pad && pad.connected && pad.mapping == "standard") {
+                if (padTimestamps[index] != pad.timestamp) {
+                    padTimestamps[index] = pad.timestamp;
+                    sendPad({
+                        index,
+                        buttons: pad.buttons.map((button) => button.value),
+                        axes: pad.axes,
+                    });
+                }
+            } else if (index in padTimestamps) {
+                delete padTimestamps[index];
+                sendPad({ index, connected: false });
+            }
+        }
+
+        padFrame = requestAnimationFrame(pollPads);
+    }
+
+    gamepadChannel.onmessage = (event) => {
+        const { payload } = JSON.parse(event.data);
+        const actuator = navigator.getGamepads()[payload.index]?.vibrationActuator;
+
+        if (!actuator) {
+            return;
+        }
+
+        if (payload.strong == 0 && payload.weak == 0) {
+            actuator.reset().catch(() => {});
+        } else {
+            actuator.playEffect("dual-rumble", {
+                duration: payload.duration || 5000,
+                strongMagnitude: payload.strong,
+                weakMagnitude: payload.weak,
+            }).catch(() => {});
+        }
+    }
+    gamepadChannel.onopen = () => {
+        padFrame = requestAnimationFrame(pollPads);
+    }
+    gamepadChannel.onclose = () => {
+        cancelAnimationFrame(padFrame);
+    }
+
+    const lockHandler = () => {
+        // Raw motion, without the acceleration of this side, where supported.
+        const request = videoPlayer.requestPointerLock({ unadjustedMovement: true });
+
+        if (request) {
+            request.catch(() => videoPlayer.requestPointerLock());
+        }
+    }
+    const lockChangeHandler = () => {
+        sendMouse({ action: "mode", mode: locked() ? "relative" : "absolute" });
+    }
+
     dataChannel.onopen = () => {
-        videoPlayer.addEventListener("click", clickHandler);
+        // Touch goes to the host rather than panning and zooming the page.
+        videoPlayer.style.touchAction = "none";
+
+        for (const [name, handler] of Object.entries(pointerHandlers)) {
+            videoPlayer.addEventListener(name, handler, { passive: false });
+        }
+
+        lockButton.addEventListener("click", lockHandler);
+        document.addEventListener("pointerlockchange", lockChangeHandler);
+        lockButton.disabled = false;
     }
     dataChannel.onclose = () => {
-        videoPlayer.removeEventListener("click", clickHandler);
+        videoPlayer.style.touchAction = "";
+
+        for (const [name, handler] of Object.entries(pointerHandlers)) {
+            videoPlayer.removeEventListener(name, handler);
+        }
+
+        lockButton.removeEventListener("click", lockHandler);
+        document.removeEventListener("pointerlockchange", lockChangeHandler);
+        lockButton.disabled = true;
+
+        if (locked()) {
+            document.exitPointerLock();
+        }
     }
 
     closeButton.addEventListener("click", () => {
@@ -110,7 +457,8 @@ function openConnection() {
                     'type': offer.type,
                 }),
                 headers: {
-                    'Content-Type': 'application/json'
+                    'Content-Type': 'application/json',
+                    ...(LAN_TOKEN ? { 'Authorization': `Bearer ${LAN_TOKEN}` } : {}),
                 }
             }
         ).then((response) => {